@@ -0,0 +1,76 @@
+// Requests per second `/blocks` serves to concurrent clients, by connection pool size.
+// A pool of one connection stands in for the single shared connection the server used
+// before it had a pool. Run with `cargo bench --bench blocks_throughput`.
+
+mod common;
+
+use actix_web::{web, App, HttpServer};
+use r2d2_sqlite::SqliteConnectionManager;
+use regtest_block_explorer::config::ApiConfig;
+use regtest_block_explorer::handlers::get_all_blocks;
+use std::time::{Duration, Instant};
+
+const BLOCKS: u32 = 1_000;
+const POOL_SIZES: [u32; 2] = [1, 10];
+const CLIENTS: [usize; 3] = [1, 8, 32];
+const RUN_FOR: Duration = Duration::from_secs(5);
+
+#[actix_web::main]
+async fn main() {
+    let (conn, path) = common::temp_db("blocks");
+    common::store_chain(&conn, BLOCKS, 4);
+    drop(conn);
+
+    println!("{:>9} {:>8} {:>10}", "pool size", "clients", "requests/s");
+    for pool_size in POOL_SIZES {
+        let pool = r2d2::Pool::builder()
+            .max_size(pool_size)
+            .build(SqliteConnectionManager::file(&path))
+            .expect("build pool");
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(ApiConfig::new(100)))
+                .route("/blocks", web::get().to(get_all_blocks))
+        })
+            .bind(("127.0.0.1", 0))
+            .expect("bind");
+        let url = format!("http://{}/blocks", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        for clients in CLIENTS {
+            let requests = load(&url, clients).await;
+            println!("{:>9} {:>8} {:>10.0}", pool_size, clients, requests as f64 / RUN_FOR.as_secs_f64());
+        }
+        handle.stop(true).await;
+    }
+}
+
+// Successful requests `clients` concurrent clients make in RUN_FOR, each walking the pages
+// of 20 blocks in turn
+async fn load(url: &str, clients: usize) -> usize {
+    let http = reqwest::Client::new();
+    let deadline = Instant::now() + RUN_FOR;
+    let tasks: Vec<_> = (0..clients).map(|client| {
+        let (http, url) = (http.clone(), url.to_string());
+        tokio::spawn(async move {
+            let mut done = 0;
+            let mut page = client;
+            while Instant::now() < deadline {
+                let response = http.get(&url).query(&[("page", page % 50 + 1)]).send().await;
+                if response.is_ok_and(|response| response.status().is_success()) {
+                    done += 1;
+                }
+                page += 1;
+            }
+            done
+        })
+    }).collect();
+    let mut total = 0;
+    for task in tasks {
+        total += task.await.expect("client task");
+    }
+    total
+}
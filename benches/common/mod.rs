@@ -0,0 +1,105 @@
+// Fixtures shared by the benchmarks: regtest-shaped blocks built in code and a fresh
+// on-disk database, since the numbers should include SQLite's file I/O
+
+#![allow(dead_code)]
+
+use bitcoin::absolute::LockTime;
+use bitcoin::block::{Header, Version};
+use bitcoin::hashes::Hash;
+use bitcoin::script::Builder;
+use bitcoin::transaction;
+use bitcoin::{
+    Amount, Block, BlockHash, CompactTarget, OutPoint, ScriptBuf, Sequence, Transaction, TxIn,
+    TxMerkleNode, TxOut, Txid, WPubkeyHash, Witness,
+};
+use regtest_block_explorer::config::{ChainConfig, SqliteConfig};
+use regtest_block_explorer::db::{init_db, insert_block};
+use rusqlite::Connection;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// Empty database file `name` in the temp directory, with its WAL files removed too
+pub fn temp_db(name: &str) -> (Connection, PathBuf) {
+    let path = std::env::temp_dir().join(format!("explorer-bench-{}-{}.db", name, std::process::id()));
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+    }
+    let conn = init_db(&path, &ChainConfig::default(), &SqliteConfig::default()).expect("create database");
+    (conn, path)
+}
+
+fn coinbase_tx(height: u32) -> Transaction {
+    Transaction {
+        version: transaction::Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: Builder::new().push_int(height as i64).push_int(0).into_script(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(5_000_000_000),
+            script_pubkey: ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([1; 20])),
+        }],
+    }
+}
+
+// A P2WPKH spend with two outputs, the most common shape on a busy chain. Its input points
+// at an outpoint derived from `seed`, so every transaction is distinct.
+fn payment_tx(seed: u64) -> Transaction {
+    let mut prev = [0; 32];
+    prev[..8].copy_from_slice(&seed.to_le_bytes());
+    Transaction {
+        version: transaction::Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::from_byte_array(prev), 0),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::from_slice(&[vec![0x30; 71], vec![0x02; 33]]),
+        }],
+        output: [2u8, 3].iter().map(|&key| TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([key; 20])),
+        }).collect(),
+    }
+}
+
+// Block at `height` on top of `prev` holding a coinbase and `payments` other transactions
+pub fn block(prev: BlockHash, height: u32, payments: usize) -> Block {
+    let mut txdata = vec![coinbase_tx(height)];
+    txdata.extend((0..payments).map(|i| payment_tx(((height as u64) << 32) | i as u64)));
+    let mut block = Block {
+        header: Header {
+            version: Version::TWO,
+            prev_blockhash: prev,
+            merkle_root: TxMerkleNode::all_zeros(),
+            time: 1_700_000_000 + height * 600,
+            bits: CompactTarget::from_consensus(0x207fffff),
+            nonce: 0,
+        },
+        txdata,
+    };
+    block.header.merkle_root = block.compute_merkle_root().expect("block has transactions");
+    block
+}
+
+// Store `len` blocks from height 0 up, each with `payments` transactions besides the coinbase
+pub fn store_chain(conn: &Connection, len: u32, payments: usize) {
+    let mut prev = BlockHash::all_zeros();
+    for height in 0..len {
+        let block = block(prev, height, payments);
+        insert_block(conn, &block, height, &ChainConfig::default()).expect("store block");
+        prev = block.block_hash();
+    }
+}
+
+
+// Median, 99th percentile and mean of `samples`
+pub fn summarize(samples: &mut [Duration]) -> (Duration, Duration, Duration) {
+    samples.sort();
+    let percentile = |p: usize| samples[(samples.len() * p / 100).min(samples.len() - 1)];
+    let mean = samples.iter().sum::<Duration>() / samples.len() as u32;
+    (percentile(50), percentile(99), mean)
+}
@@ -0,0 +1,55 @@
+// Transactions per second written for blocks of 5,000 transactions, into an on-disk database
+// with the default SQLite settings: by insert_block, which writes a block in one SQLite
+// transaction, and by insert_tx on its own per transaction, where every statement commits
+// separately as it did before. Run with `cargo bench --bench insert_block`.
+
+mod common;
+
+use bitcoin::hashes::Hash;
+use bitcoin::BlockHash;
+use regtest_block_explorer::config::ChainConfig;
+use regtest_block_explorer::db::{insert_block, insert_tx};
+use rusqlite::Connection;
+use std::time::{Duration, Instant};
+
+const BLOCKS: u32 = 3;
+const TRANSACTIONS: usize = 5_000;
+
+fn main() {
+    println!("{:<18} {:>12} {:>16}", "writes", "per block", "transactions/s");
+    for (name, one_transaction) in [("one transaction", true), ("autocommit", false)] {
+        let (conn, _) = common::temp_db(if one_transaction { "insert" } else { "autocommit" });
+        let elapsed = store_blocks(&conn, one_transaction);
+        let transactions = BLOCKS as usize * TRANSACTIONS;
+        println!(
+            "{:<18} {:>12.2?} {:>16.0}",
+            name, elapsed / BLOCKS, transactions as f64 / elapsed.as_secs_f64(),
+        );
+    }
+}
+
+// Time spent writing BLOCKS blocks of TRANSACTIONS transactions each
+fn store_blocks(conn: &Connection, one_transaction: bool) -> Duration {
+    let config = ChainConfig::default();
+    let mut prev = BlockHash::all_zeros();
+    let mut elapsed = Duration::ZERO;
+    for height in 0..BLOCKS {
+        // The coinbase makes up the rest of the block's transactions
+        let block = common::block(prev, height, TRANSACTIONS - 1);
+        let hash = block.block_hash().to_string();
+        let start = Instant::now();
+        if one_transaction {
+            insert_block(conn, &block, height, &config).expect("store block");
+        } else {
+            // Only the columns the transactions' foreign key needs
+            conn.execute("INSERT INTO blocks (hash, height) VALUES (?1, ?2)", rusqlite::params![&hash, height])
+                .expect("store block row");
+            for (index, tx) in block.txdata.iter().enumerate() {
+                insert_tx(conn, tx, &hash, index, None, &config).expect("store transaction");
+            }
+        }
+        elapsed += start.elapsed();
+        prev = block.block_hash();
+    }
+    elapsed
+}
@@ -0,0 +1,44 @@
+// Latency of `/blocks/latest` on a 10,000-block database, with the indexes on block height,
+// block timestamp and transaction block hash, and again with them dropped, as before they
+// existed. Run with `cargo bench --bench latest_blocks`.
+
+mod common;
+
+use actix_web::web;
+use r2d2_sqlite::SqliteConnectionManager;
+use regtest_block_explorer::config::ApiConfig;
+use regtest_block_explorer::handlers::get_latest_blocks;
+use std::collections::HashMap;
+use std::time::Instant;
+
+const BLOCKS: u32 = 10_000;
+const REQUESTS: usize = 1_000;
+
+#[actix_web::main]
+async fn main() {
+    let (conn, path) = common::temp_db("latest");
+    common::store_chain(&conn, BLOCKS, 2);
+    let pool = web::Data::new(
+        r2d2::Pool::builder().max_size(1).build(SqliteConnectionManager::file(&path)).expect("build pool"),
+    );
+    let api = web::Data::new(ApiConfig::new(100));
+
+    println!("{:<16} {:>10} {:>10} {:>10}", "indexes", "p50", "p99", "mean");
+    for indexes in ["with", "without"] {
+        if indexes == "without" {
+            conn.execute_batch(
+                "DROP INDEX idx_blocks_height; DROP INDEX idx_blocks_timestamp; DROP INDEX idx_transactions_block_hash;",
+            ).expect("drop indexes");
+        }
+        let mut samples = Vec::with_capacity(REQUESTS);
+        for _ in 0..REQUESTS {
+            let query = web::Query(HashMap::from([("limit".to_string(), "10".to_string())]));
+            let start = Instant::now();
+            let response = get_latest_blocks(pool.clone(), query, api.clone()).await.expect("latest blocks");
+            assert!(response.status().is_success());
+            samples.push(start.elapsed());
+        }
+        let (p50, p99, mean) = common::summarize(&mut samples);
+        println!("{:<16} {:>10.2?} {:>10.2?} {:>10.2?}", indexes, p50, p99, mean);
+    }
+}
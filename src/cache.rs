@@ -0,0 +1,177 @@
+use lru::LruCache;
+use rusqlite::{Connection, Result};
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::db::{query_block, query_latest_block, query_rich_list, query_tx};
+use crate::metrics::CACHE_LOOKUPS;
+use crate::models::{BlockResponse, RichListEntry, TxResponse};
+
+// Entries kept per kind unless configured otherwise
+pub const DEFAULT_CACHE_SIZE: usize = 1000;
+
+struct Entries {
+    // Tip (height, hash) the cached responses were built against
+    tip: Option<(u32, String)>,
+    blocks: LruCache<String, BlockResponse>,
+    txs: LruCache<String, TxResponse>,
+}
+
+// LRU cache of query_block and query_tx results for the API, keyed by hash and txid.
+// Responses include tip-dependent fields (confirmations, spent outputs), and a reorg can
+// replace blocks outright, so everything is dropped whenever the stored tip changes. The tip
+// is read on every lookup, which is one indexed query against several for a cache miss.
+pub struct LookupCache {
+    // None when the cache is disabled with a size of 0
+    entries: Option<Mutex<Entries>>,
+}
+
+impl LookupCache {
+    pub fn new(capacity: usize) -> Self {
+        let entries = NonZeroUsize::new(capacity).map(|capacity| Mutex::new(Entries {
+            tip: None,
+            blocks: LruCache::new(capacity),
+            txs: LruCache::new(capacity),
+        }));
+        Self { entries }
+    }
+
+    pub fn block(&self, conn: &Connection, hash: &str) -> Result<Option<BlockResponse>> {
+        self.lookup(conn, hash, "block", |entries| &mut entries.blocks, query_block)
+    }
+
+    pub fn tx(&self, conn: &Connection, txid: &str) -> Result<Option<TxResponse>> {
+        self.lookup(conn, txid, "tx", |entries| &mut entries.txs, query_tx)
+    }
+
+    // Misses aren't cached: an unknown hash may be indexed at any time
+    fn lookup<T: Clone>(
+        &self,
+        conn: &Connection,
+        key: &str,
+        kind: &str,
+        cache: fn(&mut Entries) -> &mut LruCache<String, T>,
+        query: fn(&Connection, &str) -> Result<Option<T>>,
+    ) -> Result<Option<T>> {
+        let Some(entries) = &self.entries else {
+            return query(conn, key);
+        };
+
+        let tip = query_latest_block(conn)?;
+        {
+            let mut entries = entries.lock().unwrap_or_else(|e| e.into_inner());
+            if entries.tip != tip {
+                entries.blocks.clear();
+                entries.txs.clear();
+                entries.tip = tip.clone();
+            }
+            if let Some(value) = cache(&mut entries).get(key) {
+                CACHE_LOOKUPS.with_label_values(&[kind, "hit"]).inc();
+                return Ok(Some(value.clone()));
+            }
+        }
+        CACHE_LOOKUPS.with_label_values(&[kind, "miss"]).inc();
+
+        let value = query(conn, key)?;
+        if let Some(value) = &value {
+            let mut entries = entries.lock().unwrap_or_else(|e| e.into_inner());
+            // Don't store a response built against a tip that has moved on in the meantime
+            if entries.tip == tip {
+                cache(&mut entries).put(key.to_string(), value.clone());
+            }
+        }
+        Ok(value)
+    }
+}
+
+// How long a computed rich list is served before the next request recomputes it, and how many
+// addresses it ranks
+pub const RICH_LIST_TTL: Duration = Duration::from_secs(60);
+pub const RICH_LIST_SIZE: usize = 10_000;
+
+// The richest addresses, as (unix time computed, ranking)
+pub type RichList = (u64, Arc<Vec<RichListEntry>>);
+
+// The rich list aggregates the whole address index, far too slow to run per request, so one
+// ranking is shared until it's `ttl` old. It's only as fresh as that: unlike LookupCache, a new
+// tip doesn't invalidate it.
+pub struct RichListCache {
+    ttl: Duration,
+    entry: Mutex<Option<(Instant, RichList)>>,
+}
+
+impl RichListCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, entry: Mutex::new(None) }
+    }
+
+    // The lock is held while recomputing, so requests arriving meanwhile wait for that result
+    // rather than each running the aggregate themselves
+    pub fn get(&self, conn: &Connection) -> Result<RichList> {
+        let mut entry = self.entry.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((computed, list)) = entry.as_ref()
+            && computed.elapsed() < self.ttl
+        {
+            return Ok(list.clone());
+        }
+        let list = (unix_now(), Arc::new(query_rich_list(conn, RICH_LIST_SIZE)?));
+        *entry = Some((Instant::now(), list.clone()));
+        Ok(list)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ChainConfig;
+    use crate::db::insert_block;
+    use crate::test_utils::*;
+    use bitcoin::hashes::Hash;
+
+    #[test]
+    fn cached_tx_is_refreshed_when_the_tip_moves() {
+        let conn = test_db();
+        let config = ChainConfig::default();
+        let cache = LookupCache::new(10);
+        let coinbase = coinbase_tx(0, 5_000);
+        let block0 = test_block(bitcoin::BlockHash::all_zeros(), 0, vec![coinbase.clone()]);
+        insert_block(&conn, &block0, 0, &config).unwrap();
+
+        let txid = coinbase.compute_txid().to_string();
+        assert_eq!(cache.tx(&conn, &txid).unwrap().unwrap().confirmations, Some(1));
+        assert_eq!(cache.tx(&conn, &txid).unwrap().unwrap().confirmations, Some(1));
+
+        let block1 = test_block(block0.block_hash(), 1, vec![coinbase_tx(1, 5_000)]);
+        insert_block(&conn, &block1, 1, &config).unwrap();
+        assert_eq!(cache.tx(&conn, &txid).unwrap().unwrap().confirmations, Some(2));
+    }
+
+    #[test]
+    fn rich_list_is_served_from_cache_until_it_expires() {
+        let conn = test_db();
+        let config = ChainConfig::default();
+        let block0 = test_block(bitcoin::BlockHash::all_zeros(), 0, vec![coinbase_tx(0, 5_000)]);
+        insert_block(&conn, &block0, 0, &config).unwrap();
+
+        let cache = RichListCache::new(Duration::from_secs(60));
+        let (_, list) = cache.get(&conn).unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!((list[0].rank, list[0].balance, list[0].utxo_count), (1, 5_000, 1));
+
+        let block1 = test_block(block0.block_hash(), 1, vec![coinbase_tx(1, 7_000)]);
+        insert_block(&conn, &block1, 1, &config).unwrap();
+        assert_eq!(cache.get(&conn).unwrap().1[0].balance, 5_000);
+        assert_eq!(RichListCache::new(Duration::ZERO).get(&conn).unwrap().1[0].balance, 12_000);
+    }
+
+    #[test]
+    fn disabled_cache_still_answers() {
+        let conn = test_db();
+        let cache = LookupCache::new(0);
+        assert!(cache.block(&conn, &"00".repeat(32)).unwrap().is_none());
+    }
+}
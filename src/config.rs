@@ -0,0 +1,134 @@
+use bitcoin::Network;
+
+// Chain settings shared by the file parser, the indexer and the API
+#[derive(Debug, Clone, Copy)]
+pub struct ChainConfig {
+    pub network: Network,
+    // Whether blocks and transactions are stored with their raw bytes; off with index --no-raw
+    pub store_raw: bool,
+    // What storing a block that's already stored does; index --on-conflict
+    pub on_conflict: OnConflict,
+}
+
+impl ChainConfig {
+    pub fn new(network: Network) -> Self {
+        Self { network, store_raw: true, on_conflict: OnConflict::Skip }
+    }
+
+    // Message start bytes that prefix every block in the network's .blk files
+    pub fn magic(&self) -> [u8; 4] {
+        self.network.magic().to_bytes()
+    }
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        Self::new(Network::Regtest)
+    }
+}
+
+// How insert_block treats a block whose hash is already stored. Skip leaves the stored rows
+// alone; Update rewrites the block and its transactions, and drops any transaction rows still
+// attached to it that the block doesn't contain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OnConflict {
+    Skip,
+    Update,
+}
+
+// Default for serve --max-page-size
+pub const DEFAULT_MAX_PAGE_SIZE: usize = 100;
+
+// Settings from the serve command that the API handlers share
+#[derive(Debug, Clone, Copy)]
+pub struct ApiConfig {
+    // Most items a paginated endpoint returns per page, whatever ?limit= asks for
+    pub max_page_size: usize,
+}
+
+impl ApiConfig {
+    pub fn new(max_page_size: usize) -> Self {
+        Self { max_page_size: max_page_size.max(1) }
+    }
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_PAGE_SIZE)
+    }
+}
+
+// SQLite's journal_mode. WAL lets API reads proceed while the indexer writes; with DELETE
+// they block each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum JournalMode {
+    Wal,
+    Delete,
+}
+
+impl JournalMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JournalMode::Wal => "WAL",
+            JournalMode::Delete => "DELETE",
+        }
+    }
+}
+
+// SQLite's synchronous setting: how often commits wait for the disk. In WAL mode NORMAL only
+// syncs at checkpoints, so a power loss or OS crash can roll back the last few commits, but
+// never corrupts the database; an application crash loses nothing. FULL syncs every commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Synchronous {
+    Off,
+    Normal,
+    Full,
+}
+
+impl Synchronous {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+        }
+    }
+}
+
+// Defaults for --sqlite-cache-size (KiB) and --sqlite-mmap-size (bytes). SQLite's own are a
+// 2 MiB cache and no memory mapping.
+pub const DEFAULT_SQLITE_CACHE_KIB: u64 = 64 * 1024;
+pub const DEFAULT_SQLITE_MMAP_SIZE: u64 = 256 * 1024 * 1024;
+
+// PRAGMAs set on every database connection, from the global --sqlite-* flags
+#[derive(Debug, Clone, Copy)]
+pub struct SqliteConfig {
+    pub journal_mode: JournalMode,
+    pub synchronous: Synchronous,
+    // Page cache per connection, in KiB
+    pub cache_size_kib: u64,
+    // Bytes of the file each connection may memory-map; 0 turns it off
+    pub mmap_size: u64,
+}
+
+impl Default for SqliteConfig {
+    fn default() -> Self {
+        Self {
+            journal_mode: JournalMode::Wal,
+            synchronous: Synchronous::Normal,
+            cache_size_kib: DEFAULT_SQLITE_CACHE_KIB,
+            mmap_size: DEFAULT_SQLITE_MMAP_SIZE,
+        }
+    }
+}
+
+// Parse a --network value; "mainnet" is accepted as an alias for bitcoin's "bitcoin"
+pub fn parse_network(name: &str) -> Result<Network, String> {
+    match name {
+        "regtest" => Ok(Network::Regtest),
+        "testnet" => Ok(Network::Testnet),
+        "signet" => Ok(Network::Signet),
+        "mainnet" | "bitcoin" => Ok(Network::Bitcoin),
+        other => Err(format!("unknown network '{}' (expected regtest, testnet, signet or mainnet)", other)),
+    }
+}
@@ -1,10 +1,49 @@
-use rusqlite::{Connection, Result};
+use bitcoin::bip158::{self, BlockFilter};
+use bitcoin::hashes::Hash;
+use bitcoin::pow::{CompactTarget, Target, Work};
+use bitcoin::BlockHash;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, OptionalExtension, Result};
+use std::collections::HashMap;
 use std::path::Path;
+use crate::config::{ChainConfig, OnConflict, SqliteConfig};
+use crate::events::publish_block;
+use crate::metrics;
+use crate::migrations::run_migrations;
 use crate::models::*;  
+use crate::rpc::NodeTxInfo;
+use crate::script::{
+    coinbase_height, coinbase_tag, input_script_type, op_return_data, output_address,
+    output_script_type, printable_ascii, script_sig_to_asm, script_to_asm, tx_flags,
+    TX_FLAG_HAS_WITNESS, TX_FLAG_SEGWIT, TX_FLAG_TAPROOT,
+};
 
-// Initialize DB and create tables
-pub fn init_db(db_path: &Path) -> Result<Connection> {
+// Pool of connections shared by the API handlers
+pub type DbPool = r2d2::Pool<SqliteConnectionManager>;
+
+// Initialize DB, create the base tables and bring the schema up to date
+pub fn init_db(db_path: &Path, config: &ChainConfig, sqlite: &SqliteConfig) -> Result<Connection> {
     let conn = Connection::open(db_path)?;
+    // The journal mode is stored in the file, so it's only set here rather than on every
+    // pooled connection
+    conn.pragma_update_and_check(None, "journal_mode", sqlite.journal_mode.as_str(), |_| Ok(()))?;
+    set_connection_pragmas(&conn, sqlite)?;
+    init_schema(&conn, config)?;
+    Ok(conn)
+}
+
+// PRAGMAs that only last as long as the connection
+fn set_connection_pragmas(conn: &Connection, sqlite: &SqliteConfig) -> Result<()> {
+    conn.pragma_update(None, "synchronous", sqlite.synchronous.as_str())?;
+    // A negative cache_size is in KiB rather than pages
+    conn.pragma_update(None, "cache_size", -(sqlite.cache_size_kib as i64))?;
+    // Reports the size it settled on, which may be capped at compile time
+    conn.pragma_update_and_check(None, "mmap_size", sqlite.mmap_size as i64, |_| Ok(()))
+}
+
+// Create the base tables and run the migrations on an open connection
+pub fn init_schema(conn: &Connection, config: &ChainConfig) -> Result<()> {
+    // Schema as of the first release; later changes are applied by run_migrations
     conn.execute(
         "CREATE TABLE IF NOT EXISTS blocks (
             hash TEXT PRIMARY KEY,
@@ -32,21 +71,62 @@ pub fn init_db(db_path: &Path) -> Result<Connection> {
         )",
         [],
     )?;
-    Ok(conn)
+    run_migrations(conn, config)
 }
 
-
+// Open a connection pool on a database already set up by init_db.
+// Connections wait on locks instead of failing immediately while the indexer writes.
+pub fn init_pool(db_path: &Path, sqlite: SqliteConfig) -> std::result::Result<DbPool, r2d2::Error> {
+    let manager = SqliteConnectionManager::file(db_path)
+        .with_init(move |conn| {
+            conn.profile(Some(metrics::record_db_query));
+            set_connection_pragmas(conn, &sqlite)?;
+            conn.busy_timeout(std::time::Duration::from_secs(5))
+        });
+    r2d2::Pool::new(manager)
+}
 
 // Function to insert a block
-pub fn insert_block(conn: &Connection, block: &bitcoin::Block, height: u32) -> Result<()> {
+// The block and all of its transactions are written in one SQLite transaction, so a crash
+// never leaves a partial block behind.
+// Returns false when the block was already stored and config.on_conflict skipped it.
+pub fn insert_block(conn: &Connection, block: &bitcoin::Block, height: u32, config: &ChainConfig) -> Result<bool> {
+    insert_block_with_fees(conn, block, height, None, config)
+}
+
+// insert_block with per-transaction fees and input values already known, as reported by the
+// node. What the node left out (or no list at all) falls back to indexed inputs.
+pub fn insert_block_with_fees(
+    conn: &Connection,
+    block: &bitcoin::Block,
+    height: u32,
+    node_txs: Option<&[NodeTxInfo]>,
+    config: &ChainConfig,
+) -> Result<bool> {
     let hash = block.block_hash().to_string();
+    if config.on_conflict == OnConflict::Skip && query_block_height(conn, &hash)?.is_some() {
+        return Ok(false);
+    }
     let header = &block.header;
     let header_blob = bitcoin::consensus::encode::serialize(header);
     let raw_data = bitcoin::consensus::encode::serialize(block);
+    let (weight, stripped_size) = block_weight_and_stripped_size(block);
+    let chainwork = chainwork_after(conn, &header.prev_blockhash.to_string(), header.bits.to_consensus())?;
+    let output_value = |tx: &bitcoin::Transaction| tx.output.iter().map(|output| output.value.to_sat()).sum::<u64>();
+    let total_output_value: u64 = block.txdata.iter().map(output_value).sum();
+    let subsidy_plus_fees = block.txdata.first().map_or(0, output_value);
 
-    conn.execute(
-        "INSERT OR REPLACE INTO blocks (hash, height, version, prev_block, merkle_root, timestamp, bits, nonce, size, header, raw_data) 
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+    let db_tx = conn.unchecked_transaction()?;
+    // Re-storing a block replaces its row, so only the size difference is counted
+    let previous_size: Option<i64> = db_tx.query_row(
+        "SELECT COALESCE(size, 0) FROM blocks WHERE hash = ?1",
+        [&hash],
+        |row| row.get(0),
+    ).optional()?;
+    db_tx.execute(
+        "INSERT OR REPLACE INTO blocks (hash, height, version, prev_block, merkle_root, timestamp, bits, nonce, size, header, raw_data, tx_count, weight, stripped_size, chainwork,
+                                        total_output_value, subsidy_plus_fees)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
         rusqlite::params![
             &hash,
             &height,
@@ -58,31 +138,171 @@ pub fn insert_block(conn: &Connection, block: &bitcoin::Block, height: u32) -> R
             &header.nonce,
             &raw_data.len(),
             &header_blob,
-            &raw_data
+            config.store_raw.then_some(&raw_data),
+            &block.txdata.len(),
+            &weight,
+            &stripped_size,
+            &chainwork,
+            &total_output_value,
+            &subsidy_plus_fees
         ],
     )?;
+    add_to_counts(&db_tx, &ChainCounts {
+        blocks: previous_size.is_none() as i64,
+        block_bytes: raw_data.len() as i64 - previous_size.unwrap_or(0),
+        ..Default::default()
+    })?;
+    if previous_size.is_some() {
+        let txids: Vec<String> = block.txdata.iter().map(|tx| tx.compute_txid().to_string()).collect();
+        delete_stale_block_txs(&db_tx, &hash, &txids)?;
+    }
 
-    for tx in &block.txdata {
-        insert_tx(conn, tx, &hash)?;
+    for (index, tx) in block.txdata.iter().enumerate() {
+        let known = node_txs.and_then(|node_txs| node_txs.get(index));
+        insert_tx(&db_tx, tx, &hash, index, known, config)?;
+    }
+    // After the transactions, so spends of outputs created earlier in the block are found
+    if let Some(filter) = compute_block_filter(&db_tx, block)? {
+        db_tx.execute(
+            "INSERT OR REPLACE INTO block_filters (block_hash, filter) VALUES (?1, ?2)",
+            rusqlite::params![&hash, &filter],
+        )?;
+    }
+    db_tx.commit()?;
+
+    metrics::BLOCKS_INDEXED.inc();
+    publish_block(BlockSummary {
+        hash,
+        height,
+        timestamp: header.time,
+        tx_count: block.txdata.len(),
+        size: raw_data.len(),
+    });
+    Ok(true)
+}
+
+// Remove the transactions stored under `block_hash` that aren't in `txids`, along with what was
+// indexed from them, when a stored block is rewritten. The block's other transactions are
+// replaced in place by insert_tx.
+fn delete_stale_block_txs(conn: &Connection, block_hash: &str, txids: &[String]) -> Result<()> {
+    let txids = serde_json::to_string(txids).unwrap_or_default();
+    let mut stmt = conn.prepare(
+        "SELECT txid, fee, is_coinbase, tx_flags FROM transactions
+         WHERE block_hash = ?1 AND txid NOT IN (SELECT value FROM json_each(?2))"
+    )?;
+    let stale = stmt
+        .query_map(rusqlite::params![block_hash, txids], |row| {
+            Ok((row.get::<_, String>(0)?, tx_counts(row.get(1)?, row.get(2)?, row.get(3)?)))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    for (txid, counts) in stale {
+        add_to_counts(conn, &counts_diff(&ChainCounts::default(), &counts))?;
+        conn.execute("UPDATE address_index SET spent = 0, spent_by = NULL WHERE spent_by = ?1", [&txid])?;
+        conn.execute("DELETE FROM address_index WHERE txid = ?1", [&txid])?;
+        conn.execute("DELETE FROM spends WHERE spending_txid = ?1", [&txid])?;
+        conn.execute("DELETE FROM op_returns WHERE txid = ?1", [&txid])?;
+        conn.execute("DELETE FROM transactions WHERE txid = ?1", [&txid])?;
     }
     Ok(())
 }
 
+// BIP158 basic filter for a block, serialized: its output scripts and the scripts of the
+// outputs its inputs spend, which are read from the indexed transactions. None when a spent
+// output isn't indexed, e.g. after indexing from --start-height.
+pub fn compute_block_filter(conn: &Connection, block: &bitcoin::Block) -> Result<Option<Vec<u8>>> {
+    let prev_txids: Vec<String> = block.txdata.iter()
+        .filter(|tx| !tx.is_coinbase())
+        .flat_map(|tx| tx.input.iter().map(|input| input.previous_output.txid.to_string()))
+        .collect();
+    let prev_txids = serde_json::to_string(&prev_txids).unwrap_or_default();
+    let mut stmt = conn.prepare(
+        "SELECT txid, outputs FROM transactions WHERE txid IN (SELECT value FROM json_each(?1))"
+    )?;
+    let rows = stmt.query_map([prev_txids], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+    let mut prev_outputs: HashMap<String, Vec<TxOutSimplified>> = HashMap::new();
+    for row in rows {
+        let (txid, outputs) = row?;
+        prev_outputs.insert(txid, serde_json::from_str(&outputs).unwrap_or_default());
+    }
+
+    let filter = BlockFilter::new_script_filter(block, |outpoint| {
+        prev_outputs.get(&outpoint.txid.to_string())
+            .and_then(|outputs| outputs.get(outpoint.vout as usize))
+            .and_then(|output| hex::decode(&output.script_pubkey).ok())
+            .map(bitcoin::ScriptBuf::from_bytes)
+            .ok_or(bip158::Error::UtxoMissing(*outpoint))
+    });
+    Ok(filter.ok().map(|filter| filter.content))
+}
+
+// BIP141 block weight in weight units, and the block's size without witness data.
+// Weight is 3 * stripped size + total size, which gives the stripped size back.
+pub fn block_weight_and_stripped_size(block: &bitcoin::Block) -> (u64, usize) {
+    let weight = block.weight().to_wu();
+    let stripped_size = (weight as usize - block.total_size()) / 3;
+    (weight, stripped_size)
+}
+
+// Difficulty as Bitcoin Core reports it: the mainnet maximum target over the block's target,
+// on every network
+pub fn difficulty_from_bits(bits: u32) -> f64 {
+    Target::from_compact(CompactTarget::from_consensus(bits)).difficulty_float()
+}
+
+// Total work of the chain up to and including a block with `bits` on top of `prev_block`, as
+// the 64-character hex Core's getblock uses. None when the parent isn't stored (or has no
+// chainwork), as after indexing from --start-height.
+pub fn chainwork_after(conn: &Connection, prev_block: &str, bits: u32) -> Result<Option<String>> {
+    let work = Target::from_compact(CompactTarget::from_consensus(bits)).to_work();
+    if prev_block == BlockHash::all_zeros().to_string() {
+        return Ok(Some(hex::encode(work.to_be_bytes())));
+    }
+    let parent: Option<String> = conn
+        .query_row("SELECT chainwork FROM blocks WHERE hash = ?1", [prev_block], |row| row.get(0))
+        .optional()?
+        .flatten();
+    let parent = parent
+        .and_then(|chainwork| <[u8; 32]>::try_from(hex::decode(chainwork).ok()?).ok())
+        .map(Work::from_be_bytes);
+    Ok(parent.map(|parent| hex::encode((parent + work).to_be_bytes())))
+}
+
 // Function to insert a transaction
 // FIXED: Convert TxIn/TxOut to serializable versions
-pub fn insert_tx(conn: &Connection, tx: &bitcoin::Transaction, block_hash: &str) -> Result<()> {
+// `index_in_block` is the transaction's position in its block; the coinbase is always 0.
+// `known` is what the node reported: its fee is stored as is instead of being computed from the
+// inputs, and its input values stand in for spent outputs that aren't indexed.
+pub fn insert_tx(
+    conn: &Connection,
+    tx: &bitcoin::Transaction,
+    block_hash: &str,
+    index_in_block: usize,
+    known: Option<&NodeTxInfo>,
+    config: &ChainConfig,
+) -> Result<()> {
     let txid = tx.compute_txid().to_string();
+    let is_coinbase = tx.is_coinbase();
     
-    // Convert inputs to simplified version
-    let inputs: Vec<TxInSimplified> = tx.input.iter().map(|input| {
+    // Convert inputs to simplified version. A coinbase input keeps the null outpoint
+    // (all-zero prev_txid, vout 0xffffffff) as a sentinel and never has a value.
+    let mut inputs: Vec<TxInSimplified> = tx.input.iter().map(|input| {
         TxInSimplified {
             prev_txid: input.previous_output.txid.to_string(),
             vout: input.previous_output.vout,
-            script_sig: hex::encode(&input.script_sig.as_bytes()),
+            script_sig: hex::encode(input.script_sig.as_bytes()),
+            script_sig_asm: None,
             sequence: input.sequence.0,
             witness: input.witness.iter()
-                .map(|w| hex::encode(w))
+                .map(hex::encode)
                 .collect(),
+            coinbase: is_coinbase,
+            value: None,
+            input_type: input_script_type(&input.script_sig, &input.witness, is_coinbase).to_string(),
+            coinbase_height: None,
+            coinbase_tag: None,
+            prev_script_type: None,
+            prev_address: None,
+            prevout: None,
         }
     }).collect();
     
@@ -90,29 +310,117 @@ pub fn insert_tx(conn: &Connection, tx: &bitcoin::Transaction, block_hash: &str)
     let outputs: Vec<TxOutSimplified> = tx.output.iter().map(|output| {
         TxOutSimplified {
             value: output.value.to_sat(),
-            script_pubkey: hex::encode(&output.script_pubkey.as_bytes()),
+            script_pubkey: hex::encode(output.script_pubkey.as_bytes()),
+            script_pubkey_asm: None,
+            script_type: output_script_type(&output.script_pubkey).to_string(),
+            address: output_address(&output.script_pubkey, config.network),
+            spent: false,
+            spent_by_txid: None,
         }
     }).collect();
     
+    if let Some(known) = known {
+        for (input, value) in inputs.iter_mut().zip(&known.input_values) {
+            input.value = *value;
+        }
+    }
+    // Outputs spent by this transaction are already stored: blocks are written in height
+    // order and transactions in block order
+    resolve_input_values(conn, &mut inputs)?;
+    let fee = known.and_then(|known| known.fee).or_else(|| compute_fee(&inputs, &outputs));
+
     let inputs_json = serde_json::to_string(&inputs).unwrap();
     let outputs_json = serde_json::to_string(&outputs).unwrap();
     let raw_data = bitcoin::consensus::encode::serialize(tx);
+    let flags = tx_flags(tx);
+
+    // Re-inserting a transaction (e.g. during a rebuild) replaces its row, so only the
+    // difference from what the old row contributed is counted
+    let previous = conn.query_row(
+        "SELECT fee, is_coinbase, tx_flags FROM transactions WHERE txid = ?1",
+        [&txid],
+        |row| Ok(tx_counts(row.get(0)?, row.get(1)?, row.get(2)?)),
+    ).optional()?;
 
     conn.execute(
-        "INSERT OR REPLACE INTO transactions (txid, block_hash, inputs, outputs, raw_data) 
-         VALUES (?1, ?2, ?3, ?4, ?5)",
-        rusqlite::params![&txid, block_hash, &inputs_json, &outputs_json, &raw_data],
+        "INSERT OR REPLACE INTO transactions (txid, block_hash, inputs, outputs, raw_data, version, lock_time, size, vsize, weight, is_coinbase, index_in_block, fee, tx_flags, base_size, witness_size) 
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+        rusqlite::params![
+            &txid,
+            block_hash,
+            &inputs_json,
+            &outputs_json,
+            config.store_raw.then_some(&raw_data),
+            &tx.version.0,
+            &tx.lock_time.to_consensus_u32(),
+            &tx.total_size(),
+            &tx.vsize(),
+            &tx.weight().to_wu(),
+            &is_coinbase,
+            &index_in_block,
+            &fee,
+            &flags,
+            &tx.base_size(),
+            &(tx.total_size() - tx.base_size())
+        ],
     )?;
+    add_to_counts(conn, &counts_diff(&tx_counts(fee, is_coinbase, flags), &previous.unwrap_or_default()))?;
+
+    metrics::TRANSACTIONS_INDEXED.inc();
+
+    // A transaction that made it into a block is no longer pending
+    delete_mempool_tx(conn, &txid)?;
+
+    // Record outputs paying to a decodable address
+    for (vout, output) in outputs.iter().enumerate() {
+        if let Some(address) = &output.address {
+            conn.execute(
+                "INSERT OR IGNORE INTO address_index (address, txid, vout, value, spent) VALUES (?1, ?2, ?3, ?4, 0)",
+                rusqlite::params![address, &txid, &vout, &output.value],
+            )?;
+        }
+    }
+
+    // A transaction may carry several OP_RETURN outputs
+    for (vout, output) in tx.output.iter().enumerate() {
+        if let Some(data) = op_return_data(&output.script_pubkey) {
+            conn.execute(
+                "INSERT OR REPLACE INTO op_returns (txid, vout, data, ascii) VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![&txid, &vout, hex::encode(&data), printable_ascii(&data)],
+            )?;
+        }
+    }
+
+    // Mark the outputs this transaction spends
+    if !is_coinbase {
+        for input in &tx.input {
+            conn.execute(
+                "INSERT OR REPLACE INTO spends (prev_txid, vout, spending_txid) VALUES (?1, ?2, ?3)",
+                rusqlite::params![&input.previous_output.txid.to_string(), &input.previous_output.vout, &txid],
+            )?;
+            conn.execute(
+                "UPDATE address_index SET spent = 1, spent_by = ?1 WHERE txid = ?2 AND vout = ?3",
+                rusqlite::params![&txid, &input.previous_output.txid.to_string(), &input.previous_output.vout],
+            )?;
+            // A pending transaction spending the same output lost the double-spend and can
+            // never confirm
+            for conflict in query_mempool_spenders(conn, &input.previous_output)? {
+                delete_mempool_tx(conn, &conflict)?;
+            }
+        }
+    }
     Ok(())
 }
 
 pub fn query_block(conn: &Connection, hash: &str) -> Result<Option<BlockResponse>> {
     let mut stmt = conn.prepare(
-        "SELECT hash, height, version, prev_block, merkle_root, timestamp, bits, nonce, size FROM blocks WHERE hash = ?1"
+        "SELECT hash, height, version, prev_block, merkle_root, timestamp, bits, nonce, size, tx_count, weight, stripped_size,
+                chainwork, total_output_value, subsidy_plus_fees
+         FROM blocks WHERE hash = ?1"
     )?;
     
     let mut block_iter = stmt.query_map([hash], |row| {
-        let mut block = BlockResponse {
+        Ok(BlockResponse {
             hash: row.get(0)?,
             height: row.get(1)?,
             version: row.get(2)?,
@@ -120,20 +428,16 @@ pub fn query_block(conn: &Connection, hash: &str) -> Result<Option<BlockResponse
             merkle_root: row.get(4)?,
             timestamp: row.get(5)?,
             bits: row.get(6)?,
+            difficulty: difficulty_from_bits(row.get(6)?),
             nonce: row.get(7)?,
-            tx_count: 0,  // Will update below
+            tx_count: row.get(9)?,
             size: row.get(8)?,
-        };
-        
-        // Count transactions for this block
-        let tx_count: usize = conn.query_row(
-            "SELECT COUNT(*) FROM transactions WHERE block_hash = ?1",
-            [hash],
-            |r| r.get(0)
-        )?;
-        block.tx_count = tx_count;
-        
-        Ok(block)
+            weight: row.get(10)?,
+            stripped_size: row.get(11)?,
+            chainwork: row.get(12)?,
+            total_output_value: row.get(13)?,
+            subsidy_plus_fees: row.get(14)?,
+        })
     })?;
     
     if let Some(block) = block_iter.next() {
@@ -157,90 +461,564 @@ pub fn query_block_by_height(conn: &Connection, height: u32) -> Result<Option<Bl
     }
 }
 
+pub fn query_block_hash_at_height(conn: &Connection, height: u32) -> Result<Option<String>> {
+    match conn.query_row(
+        "SELECT hash FROM blocks WHERE height = ?1",
+        [height],
+        |row| row.get(0)
+    ) {
+        Ok(hash) => Ok(Some(hash)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// The first height without a stored block above the lowest stored one: the height after the
+// last stored block, or the start of a gap an earlier run left by skipping a block it couldn't
+// store. None for an empty database.
+pub fn query_first_missing_height(conn: &Connection) -> Result<Option<u32>> {
+    conn.query_row(
+        "SELECT MIN(b.height) + 1 FROM blocks b
+         WHERE NOT EXISTS (SELECT 1 FROM blocks n WHERE n.height = b.height + 1)",
+        [],
+        |row| row.get(0)
+    )
+}
+
+pub fn query_block_height(conn: &Connection, hash: &str) -> Result<Option<u32>> {
+    match conn.query_row(
+        "SELECT height FROM blocks WHERE hash = ?1",
+        [hash],
+        |row| row.get(0)
+    ) {
+        Ok(height) => Ok(Some(height)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Consensus serialization of a stored block. Some(None) if the block was stored without it
+// (index --no-raw).
+pub fn query_block_raw(conn: &Connection, hash: &str) -> Result<Option<Option<Vec<u8>>>> {
+    match conn.query_row(
+        "SELECT raw_data FROM blocks WHERE hash = ?1",
+        [hash],
+        |row| row.get(0)
+    ) {
+        Ok(raw_data) => Ok(Some(raw_data)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// A block's serialized BIP158 basic filter: None if the block isn't stored, Some(None) if its
+// filter couldn't be computed because an output it spends isn't indexed
+pub fn query_block_filter(conn: &Connection, hash: &str) -> Result<Option<Option<Vec<u8>>>> {
+    conn.query_row(
+        "SELECT f.filter FROM blocks b LEFT JOIN block_filters f ON f.block_hash = b.hash WHERE b.hash = ?1",
+        [hash],
+        |row| row.get(0),
+    ).optional()
+}
+
+// Txids of a block's transactions in block order, or None if the block isn't stored
+pub fn query_block_txids(conn: &Connection, hash: &str) -> Result<Option<Vec<String>>> {
+    let exists: bool = conn.query_row("SELECT COUNT(*) > 0 FROM blocks WHERE hash = ?1", [hash], |row| row.get(0))?;
+    if !exists {
+        return Ok(None);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT txid FROM transactions WHERE block_hash = ?1 ORDER BY index_in_block"
+    )?;
+    let txids = stmt.query_map([hash], |row| row.get(0))?;
+    txids.collect::<Result<Vec<String>>>().map(Some)
+}
+
+// Txid of the transaction at position `index` in the block with `hash`; 0 is the coinbase
+pub fn query_txid_at_index(conn: &Connection, hash: &str, index: usize) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT txid FROM transactions WHERE block_hash = ?1 AND index_in_block = ?2",
+        rusqlite::params![hash, index],
+        |row| row.get(0),
+    ).optional()
+}
+
+// Check whether a block about to be stored at `height` builds on the stored block at height - 1.
+// Returns false only when a different block is stored there; a gap is not treated as a reorg.
+pub fn extends_stored_chain(conn: &Connection, block: &bitcoin::Block, height: u32) -> Result<bool> {
+    if height == 0 {
+        return Ok(true);
+    }
+    match query_block_hash_at_height(conn, height - 1)? {
+        Some(stored) => Ok(stored == block.header.prev_blockhash.to_string()),
+        None => Ok(true),
+    }
+}
+
+// Roll back a reorg: delete every block at or above `from_height` (the first orphaned height)
+// along with its transactions, so the new chain can be indexed forward from there.
+// Returns the number of blocks removed.
+pub fn handle_reorg(conn: &Connection, from_height: u32) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    let removed_counts = count_rows(&tx, Some(from_height))?;
+    add_to_counts(&tx, &counts_diff(&ChainCounts::default(), &removed_counts))?;
+    tx.execute(
+        "UPDATE address_index SET spent = 0, spent_by = NULL WHERE spent_by IN
+            (SELECT txid FROM transactions WHERE block_hash IN (SELECT hash FROM blocks WHERE height >= ?1))",
+        [from_height],
+    )?;
+    tx.execute(
+        "DELETE FROM address_index WHERE txid IN
+            (SELECT txid FROM transactions WHERE block_hash IN (SELECT hash FROM blocks WHERE height >= ?1))",
+        [from_height],
+    )?;
+    tx.execute(
+        "DELETE FROM spends WHERE spending_txid IN
+            (SELECT txid FROM transactions WHERE block_hash IN (SELECT hash FROM blocks WHERE height >= ?1))",
+        [from_height],
+    )?;
+    tx.execute(
+        "DELETE FROM op_returns WHERE txid IN
+            (SELECT txid FROM transactions WHERE block_hash IN (SELECT hash FROM blocks WHERE height >= ?1))",
+        [from_height],
+    )?;
+    tx.execute(
+        "DELETE FROM transactions WHERE block_hash IN (SELECT hash FROM blocks WHERE height >= ?1)",
+        [from_height],
+    )?;
+    tx.execute(
+        "DELETE FROM block_filters WHERE block_hash IN (SELECT hash FROM blocks WHERE height >= ?1)",
+        [from_height],
+    )?;
+    let removed = tx.execute("DELETE FROM blocks WHERE height >= ?1", [from_height])?;
+    tx.commit()?;
+    Ok(removed)
+}
+
+// Transaction columns read by tx_from_row, with the containing block's height
+const TX_SELECT: &str =
+    "SELECT t.txid, t.block_hash, t.inputs, t.outputs, t.version, t.lock_time, t.size, t.vsize, t.weight,
+            t.is_coinbase, t.tx_flags, b.height, t.base_size, t.witness_size, t.fee
+     FROM transactions t LEFT JOIN blocks b ON b.hash = t.block_hash";
+
 pub fn query_tx(conn: &Connection, txid: &str) -> Result<Option<TxResponse>> {
+    let tip_height = query_latest_block(conn)?.map(|(height, _)| height);
+    let mut stmt = conn.prepare(&format!("{} WHERE t.txid = ?1", TX_SELECT))?;
+    let mut txs = stmt
+        .query_map([txid], |row| tx_from_row(row, tip_height))?
+        .collect::<Result<Vec<TxResponse>>>()?;
+    complete_txs(conn, &mut txs)?;
+    Ok(txs.pop())
+}
+
+// One page of a block's transactions in block order, along with the block's transaction count.
+// None if the block isn't indexed.
+pub fn query_block_transactions(
+    conn: &Connection,
+    hash: &str,
+    limit: usize,
+    offset: usize,
+) -> Result<Option<(usize, Vec<TxResponse>)>> {
+    let tx_count: Option<usize> = conn
+        .query_row("SELECT tx_count FROM blocks WHERE hash = ?1", [hash], |row| row.get(0))
+        .optional()?;
+    let Some(tx_count) = tx_count else {
+        return Ok(None);
+    };
+
+    let tip_height = query_latest_block(conn)?.map(|(height, _)| height);
+    let mut stmt = conn.prepare(&format!(
+        "{} WHERE t.block_hash = ?1 ORDER BY t.index_in_block LIMIT ?2 OFFSET ?3",
+        TX_SELECT
+    ))?;
+    let mut txs = stmt
+        .query_map(rusqlite::params![hash, limit, offset], |row| tx_from_row(row, tip_height))?
+        .collect::<Result<Vec<TxResponse>>>()?;
+    complete_txs(conn, &mut txs)?;
+    Ok(Some((tx_count, txs)))
+}
+
+// The stored part of a TxResponse, from a row selected with TX_SELECT. complete_txs fills in
+// the rest.
+fn tx_from_row(row: &rusqlite::Row, tip_height: Option<u32>) -> Result<TxResponse> {
+    let block_hash: Option<String> = row.get(1)?;
+    let inputs: String = row.get(2)?;
+    let outputs: String = row.get(3)?;
+    let flags: u32 = row.get(10)?;
+    let block_height: Option<u32> = row.get(11)?;
+
+    // Unconfirmed transactions have 0 confirmations; a tx in the tip block has 1
+    let confirmations = match (&block_hash, block_height, tip_height) {
+        (None, _, _) => Some(0),
+        (Some(_), Some(height), Some(tip)) => Some((tip.saturating_sub(height) + 1) as u64),
+        _ => None,
+    };
+
+    Ok(TxResponse {
+        txid: row.get(0)?,
+        version: row.get(4)?,
+        lock_time: row.get(5)?,
+        block_hash,
+        block_height,
+        confirmations,
+        inputs: serde_json::from_str(&inputs).unwrap_or_default(),
+        outputs: serde_json::from_str(&outputs).unwrap_or_default(),
+        size: row.get(6)?,
+        base_size: row.get(12)?,
+        witness_size: row.get(13)?,
+        vsize: row.get(7)?,
+        weight: row.get(8)?,
+        is_coinbase: row.get(9)?,
+        fee: row.get(14)?,
+        fee_rate: None,
+        has_witness: flags & TX_FLAG_HAS_WITNESS != 0,
+        is_segwit: flags & TX_FLAG_SEGWIT != 0,
+        is_taproot: flags & TX_FLAG_TAPROOT != 0,
+        inputs_pagination: None,
+        outputs_pagination: None,
+    })
+}
+
+// Fill in what isn't stored with a transaction: input values, spent outputs, script asm and,
+// unless it was stored, the fee. Lookups are shared across `txs`, so a page costs as many queries as a single tx.
+fn complete_txs(conn: &Connection, txs: &mut [TxResponse]) -> Result<()> {
+    resolve_input_values(conn, txs.iter_mut().flat_map(|tx| tx.inputs.iter_mut()))?;
+    resolve_output_spends(conn, txs)?;
+    for tx in txs {
+        add_script_asm(&mut tx.inputs, &mut tx.outputs);
+        add_coinbase_details(&mut tx.inputs, tx.block_height);
+        tx.fee = tx.fee.or_else(|| compute_fee(&tx.inputs, &tx.outputs));
+        tx.fee_rate = tx.fee.filter(|_| tx.vsize > 0).map(|fee| fee as f64 / tx.vsize as f64);
+    }
+    Ok(())
+}
+
+// Where a confirmed transaction sits, as (block_hash, block_height, merkle_root, index_in_block).
+// None for unknown and unconfirmed transactions.
+pub fn query_tx_location(conn: &Connection, txid: &str) -> Result<Option<(String, u32, String, usize)>> {
+    match conn.query_row(
+        "SELECT b.hash, b.height, b.merkle_root, t.index_in_block FROM transactions t
+         JOIN blocks b ON b.hash = t.block_hash
+         WHERE t.txid = ?1",
+        [txid],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+    ) {
+        Ok(location) => Ok(Some(location)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Consensus serialization of a stored transaction. Some(None) if the transaction was stored
+// without it (index --no-raw).
+pub fn query_tx_raw(conn: &Connection, txid: &str) -> Result<Option<Option<Vec<u8>>>> {
+    match conn.query_row(
+        "SELECT raw_data FROM transactions WHERE txid = ?1",
+        [txid],
+        |row| row.get(0)
+    ) {
+        Ok(raw_data) => Ok(Some(raw_data)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Sum of input values minus sum of output values. Coinbase transactions pay no fee.
+fn compute_fee(inputs: &[TxInSimplified], outputs: &[TxOutSimplified]) -> Option<u64> {
+    if inputs.iter().any(|input| input.coinbase) {
+        return None;
+    }
+    let input_total: u64 = inputs.iter().map(|input| input.value).sum::<Option<u64>>()?;
+    let output_total: u64 = outputs.iter().map(|output| output.value).sum();
+    input_total.checked_sub(output_total)
+}
+
+// Fill in each input's value from the previous output it spends, when that transaction is indexed.
+// Otherwise the stored value, if the node reported one, is kept. All the previous transactions
+// are read in one query.
+fn resolve_input_values<'a>(
+    conn: &Connection,
+    inputs: impl IntoIterator<Item = &'a mut TxInSimplified>,
+) -> Result<()> {
+    let inputs: Vec<&mut TxInSimplified> = inputs.into_iter().filter(|input| !input.coinbase).collect();
+    if inputs.is_empty() {
+        return Ok(());
+    }
+    let prev_txids: Vec<&str> = inputs.iter().map(|input| input.prev_txid.as_str()).collect();
+    let prev_txids = serde_json::to_string(&prev_txids).unwrap_or_default();
+
     let mut stmt = conn.prepare(
-        "SELECT txid, block_hash, inputs, outputs FROM transactions WHERE txid = ?1"
+        "SELECT txid, outputs FROM transactions WHERE txid IN (SELECT value FROM json_each(?1))"
     )?;
-    
-    let mut tx_iter = stmt.query_map([txid], |row| {
-        let txid: String = row.get(0)?;
-        let block_hash: Option<String> = row.get(1)?;
-        let inputs: String = row.get(2)?;
-        let outputs: String = row.get(3)?;
-        
-        let inputs: Vec<TxInSimplified> = serde_json::from_str(&inputs).unwrap_or_default();
-        let outputs: Vec<TxOutSimplified> = serde_json::from_str(&outputs).unwrap_or_default();
-        
-        // Get block height if available
-        let block_height = if let Some(ref hash) = block_hash {
-            conn.query_row(
-                "SELECT height FROM blocks WHERE hash = ?1",
-                [hash],
-                |r| r.get(0)
-            ).ok()
-        } else {
-            None
-        };
-        
-        Ok(TxResponse {
-            txid,
-            version: 1,  // Placeholder; could store in DB if needed
-            lock_time: 0,  // Placeholder
-            block_hash,
-            block_height,
-            confirmations: None,  // Placeholder
-            inputs,
-            outputs,
-            size: 0,  // Placeholder; calculate if needed
-            vsize: 0,  // Placeholder
-            weight: 0,  // Placeholder
-        })
-    })?;
-    
-    if let Some(tx) = tx_iter.next() {
-        Ok(Some(tx?))
-    } else {
-        Ok(None)
+    let rows = stmt.query_map([prev_txids], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+    let mut prev_outputs: HashMap<String, Vec<TxOutSimplified>> = HashMap::new();
+    for row in rows {
+        let (txid, outputs) = row?;
+        prev_outputs.insert(txid, serde_json::from_str(&outputs).unwrap_or_default());
+    }
+
+    for input in inputs {
+        let prev_output = prev_outputs
+            .get(&input.prev_txid)
+            .and_then(|outputs| outputs.get(input.vout as usize));
+        if let Some(prev_output) = prev_output {
+            input.value = Some(prev_output.value);
+            input.prev_script_type = Some(prev_output.script_type.clone());
+            input.prev_address = prev_output.address.clone();
+        }
+    }
+    Ok(())
+}
+
+// Scripts are stored as hex only, so they're disassembled per request
+fn add_script_asm(inputs: &mut [TxInSimplified], outputs: &mut [TxOutSimplified]) {
+    let asm = |hex_script: &str, disassemble: fn(&bitcoin::Script) -> String| match hex::decode(hex_script) {
+        Ok(bytes) => disassemble(bitcoin::Script::from_bytes(&bytes)),
+        Err(_) => "[error]".to_string(),
+    };
+    for input in inputs {
+        input.script_sig_asm = Some(asm(&input.script_sig, script_sig_to_asm));
+    }
+    for output in outputs {
+        output.script_pubkey_asm = Some(asm(&output.script_pubkey, script_to_asm));
+    }
+}
+
+// Height and miner tag of a coinbase input. A leading number that isn't the block's height
+// is pre-BIP34 data, not a height.
+fn add_coinbase_details(inputs: &mut [TxInSimplified], block_height: Option<u32>) {
+    for input in inputs.iter_mut().filter(|input| input.coinbase) {
+        let Ok(bytes) = hex::decode(&input.script_sig) else { continue };
+        let script_sig = bitcoin::Script::from_bytes(&bytes);
+        input.coinbase_height = coinbase_height(script_sig)
+            .filter(|&height| block_height.is_none_or(|block_height| block_height == height));
+        input.coinbase_tag = coinbase_tag(script_sig);
     }
 }
 
-pub fn query_latest_blocks(conn: &Connection, limit: usize) -> Result<Vec<BlockSummary>> {
+// Mark each output spent by an indexed transaction. Outputs whose spender hasn't been indexed
+// yet stay unspent until it is.
+fn resolve_output_spends(conn: &Connection, txs: &mut [TxResponse]) -> Result<()> {
+    if txs.is_empty() {
+        return Ok(());
+    }
+    let txids: Vec<&str> = txs.iter().map(|tx| tx.txid.as_str()).collect();
+    let txids = serde_json::to_string(&txids).unwrap_or_default();
+
     let mut stmt = conn.prepare(
-        "SELECT hash, height, timestamp FROM blocks ORDER BY height DESC LIMIT ?1"
+        "SELECT prev_txid, vout, spending_txid FROM spends WHERE prev_txid IN (SELECT value FROM json_each(?1))"
     )?;
-    
-    let rows = stmt.query_map([limit], |row| {
-        let hash: String = row.get(0)?;
-        let height: u32 = row.get(1)?;
-        let timestamp: u32 = row.get(2)?;
-        
-        let tx_count: usize = conn.query_row(
-            "SELECT COUNT(*) FROM transactions WHERE block_hash = ?1",
-            [&hash],
-            |r| r.get(0)
-        )?;
-        
-        Ok(BlockSummary {
-            hash,
-            height,
-            timestamp,
-            tx_count,
-        })
+    let rows = stmt.query_map([txids], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, usize>(1)?, row.get::<_, String>(2)?))
     })?;
-    
-    let mut blocks = Vec::new();
+    let positions: HashMap<String, usize> = txs.iter().enumerate().map(|(i, tx)| (tx.txid.clone(), i)).collect();
     for row in rows {
-        blocks.push(row?);
+        let (prev_txid, vout, spending_txid) = row?;
+        if let Some(output) = positions.get(&prev_txid).and_then(|&i| txs[i].outputs.get_mut(vout)) {
+            output.spent = true;
+            output.spent_by_txid = Some(spending_txid);
+        }
     }
-    Ok(blocks)
+    Ok(())
 }
 
 pub fn query_block_count(conn: &Connection) -> Result<u32> {
-    conn.query_row("SELECT COUNT(*) FROM blocks", [], |row| row.get(0))
+    Ok(query_counts_fast(conn)?.blocks as u32)
 }
 
 pub fn query_transaction_count(conn: &Connection) -> Result<u64> {
-    conn.query_row("SELECT COUNT(*) FROM transactions", [], |row| row.get(0))
+    Ok(query_counts_fast(conn)?.transactions as u64)
+}
+
+// Each running total's key in the metadata table
+pub fn counter_entries(counts: &ChainCounts) -> [(&'static str, i64); 7] {
+    [
+        ("blocks", counts.blocks),
+        ("block_bytes", counts.block_bytes),
+        ("transactions", counts.transactions),
+        ("fees", counts.fees),
+        ("non_coinbase_transactions", counts.non_coinbase_transactions),
+        ("segwit_transactions", counts.segwit_transactions),
+        ("taproot_transactions", counts.taproot_transactions),
+    ]
+}
+
+fn counts_diff(new: &ChainCounts, old: &ChainCounts) -> ChainCounts {
+    ChainCounts {
+        blocks: new.blocks - old.blocks,
+        block_bytes: new.block_bytes - old.block_bytes,
+        transactions: new.transactions - old.transactions,
+        fees: new.fees - old.fees,
+        non_coinbase_transactions: new.non_coinbase_transactions - old.non_coinbase_transactions,
+        segwit_transactions: new.segwit_transactions - old.segwit_transactions,
+        taproot_transactions: new.taproot_transactions - old.taproot_transactions,
+    }
+}
+
+// What one transaction row adds to the running totals
+fn tx_counts(fee: Option<u64>, is_coinbase: bool, flags: u32) -> ChainCounts {
+    let spends = !is_coinbase as i64;
+    ChainCounts {
+        transactions: 1,
+        fees: fee.unwrap_or(0) as i64,
+        non_coinbase_transactions: spends,
+        segwit_transactions: spends * (flags & TX_FLAG_SEGWIT != 0) as i64,
+        taproot_transactions: spends * (flags & TX_FLAG_TAPROOT != 0) as i64,
+        ..Default::default()
+    }
+}
+
+// Apply a change to the running totals; called in the same SQLite transaction as the rows it
+// accounts for, so the totals never disagree with committed data
+fn add_to_counts(conn: &Connection, delta: &ChainCounts) -> Result<()> {
+    for (key, value) in counter_entries(delta) {
+        if value != 0 {
+            conn.execute("UPDATE metadata SET value = value + ?2 WHERE key = ?1", rusqlite::params![key, value])?;
+        }
+    }
+    Ok(())
+}
+
+// Running totals in O(1), without scanning the blocks or transactions tables
+pub fn query_counts_fast(conn: &Connection) -> Result<ChainCounts> {
+    let mut stmt = conn.prepare_cached("SELECT key, value FROM metadata")?;
+    let values: HashMap<String, i64> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_>>()?;
+    let value = |key: &str| values.get(key).copied().unwrap_or(0);
+    Ok(ChainCounts {
+        blocks: value("blocks"),
+        block_bytes: value("block_bytes"),
+        transactions: value("transactions"),
+        fees: value("fees"),
+        non_coinbase_transactions: value("non_coinbase_transactions"),
+        segwit_transactions: value("segwit_transactions"),
+        taproot_transactions: value("taproot_transactions"),
+    })
+}
+
+// Totals over blocks at or above `from_height` and the transactions in them. With None, every
+// row is counted, including transactions whose block isn't stored. Scans both tables.
+fn count_rows(conn: &Connection, from_height: Option<u32>) -> Result<ChainCounts> {
+    let (blocks, block_bytes) = conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(size), 0) FROM blocks WHERE ?1 IS NULL OR height >= ?1",
+        [from_height],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(fee), 0), COALESCE(SUM(is_coinbase = 0), 0),
+                COALESCE(SUM(is_coinbase = 0 AND tx_flags & ?2 != 0), 0),
+                COALESCE(SUM(is_coinbase = 0 AND tx_flags & ?3 != 0), 0)
+         FROM transactions
+         WHERE ?1 IS NULL OR block_hash IN (SELECT hash FROM blocks WHERE height >= ?1)",
+        rusqlite::params![from_height, TX_FLAG_SEGWIT, TX_FLAG_TAPROOT],
+        |row| Ok(ChainCounts {
+            blocks,
+            block_bytes,
+            transactions: row.get(0)?,
+            fees: row.get(1)?,
+            non_coinbase_transactions: row.get(2)?,
+            segwit_transactions: row.get(3)?,
+            taproot_transactions: row.get(4)?,
+        }),
+    )
+}
+
+// The running totals recomputed with full table scans, for reconciling them in `verify`
+pub fn query_counts_exact(conn: &Connection) -> Result<ChainCounts> {
+    count_rows(conn, None)
+}
+
+// Overwrite the running totals, e.g. with query_counts_exact after they've drifted
+pub fn reset_counts(conn: &Connection, counts: &ChainCounts) -> Result<()> {
+    for (key, value) in counter_entries(counts) {
+        conn.execute(
+            "INSERT OR REPLACE INTO metadata (key, value) VALUES (?1, ?2)",
+            rusqlite::params![key, value],
+        )?;
+    }
+    Ok(())
+}
+
+// Whole-chain aggregates for /stats, derived from the running totals
+pub fn query_chain_stats(conn: &Connection) -> Result<ChainStats> {
+    let counts = query_counts_fast(conn)?;
+    let per_block = |total: i64| if counts.blocks > 0 { total as f64 / counts.blocks as f64 } else { 0.0 };
+    // Shares of non-coinbase transactions, in percent
+    let percent = |part: i64| if counts.non_coinbase_transactions > 0 {
+        100.0 * part as f64 / counts.non_coinbase_transactions as f64
+    } else {
+        0.0
+    };
+
+    Ok(ChainStats {
+        total_blocks: counts.blocks as u32,
+        total_transactions: counts.transactions as u64,
+        total_fees: counts.fees as u64,
+        avg_block_size: per_block(counts.block_bytes),
+        avg_tx_per_block: per_block(counts.transactions),
+        chain_size_bytes: counts.block_bytes as u64,
+        segwit_tx_percent: percent(counts.segwit_transactions),
+        taproot_tx_percent: percent(counts.taproot_transactions),
+    })
+}
+
+// Per-day block count, transaction count, block weight and size, output volume and block rewards for the `days` UTC days ending
+// on the day of the newest block, oldest first. Days without blocks are included with zeros.
+pub fn query_daily_stats(conn: &Connection, days: u32) -> Result<Vec<DailyStats>> {
+    let mut stmt = conn.prepare(
+        "WITH RECURSIVE
+         bounds(first_day, last_day) AS (
+             SELECT date(MAX(timestamp), 'unixepoch', '-' || (?1 - 1) || ' days'), date(MAX(timestamp), 'unixepoch')
+             FROM blocks
+         ),
+         days(day) AS (
+             SELECT first_day FROM bounds WHERE first_day IS NOT NULL
+             UNION ALL
+             SELECT date(day, '+1 day') FROM days, bounds WHERE day < last_day
+         ),
+         block_days AS (
+             SELECT date(timestamp, 'unixepoch') AS day, COUNT(*) AS blocks, COALESCE(SUM(tx_count), 0) AS transactions,
+                    SUM(total_output_value) AS volume, SUM(subsidy_plus_fees) AS subsidy_plus_fees,
+                    COALESCE(SUM(weight), 0) AS weight, COALESCE(SUM(stripped_size), 0) AS stripped_size
+             FROM blocks
+             WHERE timestamp >= (SELECT strftime('%s', first_day) FROM bounds)
+             GROUP BY day
+         )
+         SELECT d.day, COALESCE(b.blocks, 0), COALESCE(b.transactions, 0), COALESCE(b.volume, 0),
+                COALESCE(b.weight, 0), COALESCE(b.stripped_size, 0), COALESCE(b.subsidy_plus_fees, 0)
+         FROM days d
+         LEFT JOIN block_days b ON b.day = d.day
+         ORDER BY d.day"
+    )?;
+
+    let rows = stmt.query_map([days], |row| {
+        Ok(DailyStats {
+            date: row.get(0)?,
+            blocks: row.get(1)?,
+            transactions: row.get(2)?,
+            output_volume: row.get(3)?,
+            weight: row.get(4)?,
+            stripped_size: row.get(5)?,
+            subsidy_plus_fees: row.get(6)?,
+        })
+    })?;
+    rows.collect()
+}
+
+// Fee rates in sat/vB of the non-coinbase transactions in the newest `blocks` blocks, lowest
+// first. Transactions whose fee isn't known (an input spends an unindexed output) are left out.
+pub fn query_recent_fee_rates(conn: &Connection, blocks: u32) -> Result<Vec<f64>> {
+    let mut stmt = conn.prepare(
+        "SELECT CAST(t.fee AS REAL) / t.vsize AS fee_rate FROM transactions t
+         JOIN blocks b ON b.hash = t.block_hash
+         WHERE b.height > (SELECT MAX(height) FROM blocks) - ?1
+           AND t.is_coinbase = 0 AND t.fee IS NOT NULL AND t.vsize > 0
+         ORDER BY fee_rate"
+    )?;
+    let rates = stmt.query_map([blocks], |row| row.get(0))?;
+    rates.collect()
 }
 
 pub fn query_latest_block(conn: &Connection) -> Result<Option<(u32, String)>> {
@@ -255,27 +1033,107 @@ pub fn query_latest_block(conn: &Connection) -> Result<Option<(u32, String)>> {
     }
 }
 
-pub fn query_all_blocks(conn: &Connection, limit: usize, offset: usize) -> Result<Vec<BlockSummary>> {
-    let mut stmt = conn.prepare(
-        "SELECT hash, height, timestamp FROM blocks ORDER BY height DESC LIMIT ?1 OFFSET ?2"
-    )?;
+// query_latest_block with the tip's timestamp
+pub fn query_tip(conn: &Connection) -> Result<Option<ChainTip>> {
+    conn.query_row(
+        "SELECT height, hash, timestamp FROM blocks ORDER BY height DESC LIMIT 1",
+        [],
+        |row| Ok(ChainTip {
+            height: row.get(0)?,
+            hash: row.get(1)?,
+            timestamp: row.get(2)?,
+        })
+    ).optional()
+}
+
+// Columns /blocks can be sorted by. Only these ever reach the ORDER BY clause.
+#[derive(Clone, Copy)]
+pub enum BlockSort {
+    Height,
+    Timestamp,
+    TxCount,
+    Size,
+}
+
+impl BlockSort {
+    fn column(self) -> &'static str {
+        match self {
+            BlockSort::Height => "height",
+            BlockSort::Timestamp => "timestamp",
+            BlockSort::TxCount => "tx_count",
+            BlockSort::Size => "size",
+        }
+    }
+}
+
+impl std::str::FromStr for BlockSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "height" => Ok(BlockSort::Height),
+            "timestamp" => Ok(BlockSort::Timestamp),
+            "tx_count" => Ok(BlockSort::TxCount),
+            "size" => Ok(BlockSort::Size),
+            other => Err(format!("unknown sort '{}' (expected height, timestamp, tx_count or size)", other)),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    fn keyword(self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+}
+
+impl std::str::FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "asc" => Ok(SortOrder::Asc),
+            "desc" => Ok(SortOrder::Desc),
+            other => Err(format!("unknown order '{}' (expected asc or desc)", other)),
+        }
+    }
+}
+
+pub fn query_all_blocks(
+    conn: &Connection,
+    sort: BlockSort,
+    order: SortOrder,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<BlockSummary>> {
+    // Height and hash break ties so pages stay stable
+    let mut stmt = conn.prepare(&format!(
+        "SELECT hash, height, timestamp, tx_count, size FROM blocks ORDER BY {column} {order}, height {order}, hash LIMIT ?1 OFFSET ?2",
+        column = sort.column(),
+        order = order.keyword(),
+    ))?;
     
     let rows = stmt.query_map([limit, offset], |row| {
         let hash: String = row.get(0)?;
         let height: u32 = row.get(1)?;
         let timestamp: u32 = row.get(2)?;
-        
-        let tx_count: usize = conn.query_row(
-            "SELECT COUNT(*) FROM transactions WHERE block_hash = ?1",
-            [&hash],
-            |r| r.get(0)
-        )?;
+        let tx_count: usize = row.get(3)?;
+        let size: usize = row.get(4)?;
         
         Ok(BlockSummary {
             hash,
             height,
             timestamp,
             tx_count,
+            size,
         })
     })?;
     
@@ -284,4 +1142,916 @@ pub fn query_all_blocks(conn: &Connection, limit: usize, offset: usize) -> Resul
         blocks.push(row?);
     }
     Ok(blocks)
-}
\ No newline at end of file
+}
+
+// query_all_blocks restricted to blocks with after <= timestamp <= before
+pub fn query_blocks_filtered(
+    conn: &Connection,
+    after: u32,
+    before: u32,
+    sort: BlockSort,
+    order: SortOrder,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<BlockSummary>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT hash, height, timestamp, tx_count, size FROM blocks
+         WHERE timestamp BETWEEN ?3 AND ?4
+         ORDER BY {column} {order}, height {order}, hash LIMIT ?1 OFFSET ?2",
+        column = sort.column(),
+        order = order.keyword(),
+    ))?;
+    let rows = stmt.query_map(rusqlite::params![limit, offset, after, before], |row| Ok(BlockSummary {
+        hash: row.get(0)?,
+        height: row.get(1)?,
+        timestamp: row.get(2)?,
+        tx_count: row.get(3)?,
+        size: row.get(4)?,
+    }))?;
+    rows.collect()
+}
+
+// Number of blocks with after <= timestamp <= before
+pub fn query_block_count_filtered(conn: &Connection, after: u32, before: u32) -> Result<u32> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM blocks WHERE timestamp BETWEEN ?1 AND ?2",
+        [after, before],
+        |row| row.get(0)
+    )
+}
+
+// Blocks with from <= height <= to, in ascending height order
+pub fn query_blocks_range(conn: &Connection, from: u32, to: u32) -> Result<Vec<BlockSummary>> {
+    let mut stmt = conn.prepare(
+        "SELECT hash, height, timestamp, tx_count, size FROM blocks WHERE height BETWEEN ?1 AND ?2 ORDER BY height ASC"
+    )?;
+
+    let rows = stmt.query_map([from, to], |row| {
+        Ok(BlockSummary {
+            hash: row.get(0)?,
+            height: row.get(1)?,
+            timestamp: row.get(2)?,
+            tx_count: row.get(3)?,
+            size: row.get(4)?,
+        })
+    })?;
+
+    let mut blocks = Vec::new();
+    for row in rows {
+        blocks.push(row?);
+    }
+    Ok(blocks)
+}
+
+// Summary of the first stored block above `height`. Gaps in the stored heights are skipped.
+pub fn query_next_block(conn: &Connection, height: u32) -> Result<Option<BlockSummary>> {
+    query_block_summary_where(conn, "height > ?1 ORDER BY height ASC", height)
+}
+
+// Summary of the last stored block below `height`. Gaps in the stored heights are skipped.
+pub fn query_prev_block(conn: &Connection, height: u32) -> Result<Option<BlockSummary>> {
+    query_block_summary_where(conn, "height < ?1 ORDER BY height DESC", height)
+}
+
+fn query_block_summary_where(conn: &Connection, condition: &str, height: u32) -> Result<Option<BlockSummary>> {
+    conn.query_row(
+        &format!("SELECT hash, height, timestamp, tx_count, size FROM blocks WHERE {} LIMIT 1", condition),
+        [height],
+        |row| Ok(BlockSummary {
+            hash: row.get(0)?,
+            height: row.get(1)?,
+            timestamp: row.get(2)?,
+            tx_count: row.get(3)?,
+            size: row.get(4)?,
+        })
+    ).optional()
+}
+
+// Txids of the transactions funding or spending from the address bound to ?1
+const ADDRESS_TXIDS: &str = "SELECT txid FROM address_index WHERE address = ?1
+         UNION
+         SELECT spent_by FROM address_index WHERE address = ?1 AND spent_by IS NOT NULL";
+
+// Balance summary for an address plus one page of the transactions touching it,
+// newest first. Both funding and spending transactions are included.
+pub fn query_address(conn: &Connection, address: &str, limit: usize, offset: usize) -> Result<AddressSummary> {
+    let (total_received, balance): (u64, u64) = conn.query_row(
+        "SELECT COALESCE(SUM(value), 0), COALESCE(SUM(CASE WHEN spent = 0 THEN value ELSE 0 END), 0)
+         FROM address_index WHERE address = ?1",
+        [address],
+        |row| Ok((row.get(0)?, row.get(1)?))
+    )?;
+
+    let tx_count: usize = conn.query_row(
+        &format!("SELECT COUNT(*) FROM ({})", ADDRESS_TXIDS),
+        [address],
+        |row| row.get(0)
+    )?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT a.txid FROM ({}) a
+         JOIN transactions t ON t.txid = a.txid
+         LEFT JOIN blocks b ON b.hash = t.block_hash
+         ORDER BY b.height DESC, t.index_in_block DESC
+         LIMIT ?2 OFFSET ?3",
+        ADDRESS_TXIDS
+    ))?;
+    let rows = stmt.query_map(rusqlite::params![address, limit, offset], |row| row.get(0))?;
+
+    let mut transactions = Vec::new();
+    for row in rows {
+        transactions.push(row?);
+    }
+
+    Ok(AddressSummary {
+        address: address.to_string(),
+        balance,
+        total_received,
+        total_sent: total_received - balance,
+        tx_count,
+        transactions,
+    })
+}
+
+// Up to `limit` transactions touching an address, newest first, starting after `after`.
+// Seeking past the cursor keeps pages stable as new blocks arrive, and doesn't read and throw
+// away every earlier row the way an OFFSET does.
+pub fn query_address_history(
+    conn: &Connection,
+    address: &str,
+    limit: usize,
+    after: Option<&HistoryCursor>,
+) -> Result<Vec<AddressHistoryEntry>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT a.txid, b.height FROM ({}) a
+         JOIN transactions t ON t.txid = a.txid
+         JOIN blocks b ON b.hash = t.block_hash
+         WHERE ?2 IS NULL OR (b.height, a.txid) < (?2, ?3)
+         ORDER BY b.height DESC, a.txid DESC
+         LIMIT ?4",
+        ADDRESS_TXIDS
+    ))?;
+    let rows = stmt.query_map(
+        rusqlite::params![address, after.map(|c| c.height), after.map(|c| &c.txid), limit],
+        |row| Ok(AddressHistoryEntry {
+            txid: row.get(0)?,
+            block_height: row.get(1)?,
+        })
+    )?;
+    rows.collect()
+}
+
+// The `limit` addresses with the largest balance, richest first, ties broken by address.
+// Balances are computed the same way as query_address's, from every output paying the address.
+pub fn query_rich_list(conn: &Connection, limit: usize) -> Result<Vec<RichListEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT address, SUM(value) AS balance, COUNT(*) FROM address_index
+         WHERE spent = 0
+         GROUP BY address
+         ORDER BY balance DESC, address
+         LIMIT ?1"
+    )?;
+    let rows = stmt.query_map([limit], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+    rows.enumerate()
+        .map(|(i, row)| row.map(|(address, balance, utxo_count)| RichListEntry { rank: i + 1, address, balance, utxo_count }))
+        .collect()
+}
+
+// Outputs paying to an address that no indexed transaction spends, oldest first,
+// keeping those with at least `min_confirmations`
+pub fn query_address_utxos(conn: &Connection, address: &str, min_confirmations: u64) -> Result<Vec<Utxo>> {
+    let tip_height = query_latest_block(conn)?.map(|(height, _)| height);
+    let mut stmt = conn.prepare(
+        "SELECT a.txid, a.vout, a.value, b.height FROM address_index a
+         LEFT JOIN spends s ON s.prev_txid = a.txid AND s.vout = a.vout
+         JOIN transactions t ON t.txid = a.txid
+         LEFT JOIN blocks b ON b.hash = t.block_hash
+         WHERE a.address = ?1 AND s.spending_txid IS NULL
+         ORDER BY b.height, t.index_in_block, a.vout"
+    )?;
+    let rows = stmt.query_map([address], |row| {
+        let block_height: Option<u32> = row.get(3)?;
+        let confirmations = match (block_height, tip_height) {
+            (Some(height), Some(tip)) => (tip.saturating_sub(height) + 1) as u64,
+            _ => 0,
+        };
+        Ok(Utxo {
+            txid: row.get(0)?,
+            vout: row.get(1)?,
+            value: row.get(2)?,
+            block_height,
+            confirmations,
+        })
+    })?;
+
+    let mut utxos = Vec::new();
+    for row in rows {
+        let utxo = row?;
+        if utxo.confirmations >= min_confirmations {
+            utxos.push(utxo);
+        }
+    }
+    Ok(utxos)
+}
+
+// OP_RETURN payloads, newest first
+pub fn query_op_returns(conn: &Connection, limit: usize, offset: usize) -> Result<Vec<OpReturnEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT o.txid, o.vout, b.height, o.data, o.ascii FROM op_returns o
+         JOIN transactions t ON t.txid = o.txid
+         LEFT JOIN blocks b ON b.hash = t.block_hash
+         ORDER BY b.height DESC, t.index_in_block DESC, o.vout DESC
+         LIMIT ?1 OFFSET ?2"
+    )?;
+    let rows = stmt.query_map(rusqlite::params![limit, offset], |row| {
+        Ok(OpReturnEntry {
+            txid: row.get(0)?,
+            vout: row.get(1)?,
+            block_height: row.get(2)?,
+            data: row.get(3)?,
+            ascii: row.get(4)?,
+        })
+    })?;
+    rows.collect()
+}
+
+pub fn query_op_return_count(conn: &Connection) -> Result<u64> {
+    conn.query_row("SELECT COUNT(*) FROM op_returns", [], |row| row.get(0))
+}
+
+// Store a pending transaction with the outputs it spends, so conflicting ones can be found.
+// Re-inserting a known txid keeps its original first_seen.
+pub fn insert_mempool_tx(conn: &Connection, tx: &bitcoin::Transaction, first_seen: u64, fee: u64, vsize: usize) -> Result<()> {
+    let txid = tx.compute_txid().to_string();
+    let db_tx = conn.unchecked_transaction()?;
+    db_tx.execute(
+        "INSERT OR IGNORE INTO mempool (txid, first_seen, fee, vsize, raw_data, rbf) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        rusqlite::params![&txid, &first_seen, &fee, &vsize, bitcoin::consensus::serialize(tx), tx.is_explicitly_rbf()],
+    )?;
+    insert_mempool_spends(&db_tx, &txid, tx)?;
+    db_tx.commit()
+}
+
+pub fn insert_mempool_spends(conn: &Connection, txid: &str, tx: &bitcoin::Transaction) -> Result<()> {
+    for input in &tx.input {
+        conn.execute(
+            "INSERT OR IGNORE INTO mempool_spends (txid, prev_txid, vout) VALUES (?1, ?2, ?3)",
+            rusqlite::params![txid, &input.previous_output.txid.to_string(), &input.previous_output.vout],
+        )?;
+    }
+    Ok(())
+}
+
+// Pending transactions spending `outpoint`; more than one means a double-spend
+fn query_mempool_spenders(conn: &Connection, outpoint: &bitcoin::OutPoint) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT txid FROM mempool_spends WHERE prev_txid = ?1 AND vout = ?2")?;
+    let rows = stmt.query_map(rusqlite::params![&outpoint.txid.to_string(), &outpoint.vout], |row| row.get(0))?;
+    rows.collect()
+}
+
+pub fn query_mempool_txids(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT txid FROM mempool")?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+
+    let mut txids = Vec::new();
+    for row in rows {
+        txids.push(row?);
+    }
+    Ok(txids)
+}
+
+pub fn delete_mempool_tx(conn: &Connection, txid: &str) -> Result<()> {
+    conn.execute("DELETE FROM mempool WHERE txid = ?1", [txid])?;
+    conn.execute("DELETE FROM mempool_spends WHERE txid = ?1", [txid])?;
+    Ok(())
+}
+
+// Pending transaction count and total fees
+pub fn query_mempool_stats(conn: &Connection) -> Result<(usize, u64)> {
+    conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(fee), 0) FROM mempool",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?))
+    )
+}
+
+// One page of pending transactions, newest first, each with the other pending transactions
+// that spend one of the same outputs
+pub fn query_mempool(conn: &Connection, limit: usize, offset: usize) -> Result<Vec<MempoolEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT m.txid, m.first_seen, m.fee, m.vsize, m.rbf,
+                (SELECT json_group_array(DISTINCT other.txid) FROM mempool_spends own
+                 JOIN mempool_spends other ON other.prev_txid = own.prev_txid AND other.vout = own.vout
+                 WHERE own.txid = m.txid AND other.txid != m.txid)
+         FROM mempool m ORDER BY m.first_seen DESC, m.txid LIMIT ?1 OFFSET ?2"
+    )?;
+
+    let rows = stmt.query_map([limit, offset], |row| {
+        let fee: u64 = row.get(2)?;
+        let vsize: usize = row.get(3)?;
+        let conflicts: Vec<String> = serde_json::from_str(&row.get::<_, String>(5)?).unwrap_or_default();
+        Ok(MempoolEntry {
+            txid: row.get(0)?,
+            first_seen: row.get(1)?,
+            fee,
+            vsize,
+            fee_rate: if vsize > 0 { fee as f64 / vsize as f64 } else { 0.0 },
+            rbf: row.get(4)?,
+            double_spend: !conflicts.is_empty(),
+            conflicts,
+        })
+    })?;
+
+    let mut entries = Vec::new();
+    for row in rows {
+        entries.push(row?);
+    }
+    Ok(entries)
+}
+
+// Rewrite the database without its free pages (left behind by reorgs and re-indexing) and
+// refresh the statistics the query planner uses, then fold the WAL back into the main file so
+// its size shows the result. PRAGMA optimize additionally lets SQLite act on what it has
+// learned from the queries run on this connection.
+pub fn vacuum_and_analyze(conn: &Connection, optimize: bool) -> Result<()> {
+    conn.execute_batch("VACUUM; ANALYZE;")?;
+    if optimize {
+        conn.execute_batch("PRAGMA optimize;")?;
+    }
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+}
+
+// Recount each block's transactions and return the blocks whose stored tx_count disagrees,
+// as (hash, height, stored, actual)
+pub fn verify_tx_counts(conn: &Connection) -> Result<Vec<(String, u32, usize, usize)>> {
+    let mut stmt = conn.prepare(
+        "SELECT b.hash, b.height, COALESCE(b.tx_count, 0), COUNT(t.txid)
+         FROM blocks b LEFT JOIN transactions t ON t.block_hash = b.hash
+         GROUP BY b.hash
+         HAVING COALESCE(b.tx_count, 0) != COUNT(t.txid)
+         ORDER BY b.height"
+    )?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?;
+
+    let mut mismatches = Vec::new();
+    for row in rows {
+        mismatches.push(row?);
+    }
+    Ok(mismatches)
+}
+
+// Decode every block's raw_data and compare it with the columns derived from it. Blocks
+// stored without raw data (index --no-raw) have nothing to compare and are skipped.
+// Returns (hash, height, problem) for each disagreement found.
+pub fn verify_raw_blocks(conn: &Connection) -> Result<Vec<(String, u32, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT hash, height, merkle_root, COALESCE(tx_count, 0), raw_data FROM blocks
+         WHERE raw_data IS NOT NULL ORDER BY height"
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut problems = Vec::new();
+    while let Some(row) = rows.next()? {
+        let hash: String = row.get(0)?;
+        let height: u32 = row.get(1)?;
+        let merkle_root: String = row.get(2)?;
+        let tx_count: usize = row.get(3)?;
+        let raw_data: Vec<u8> = row.get(4)?;
+
+        let block: bitcoin::Block = match bitcoin::consensus::deserialize(&raw_data) {
+            Ok(block) => block,
+            Err(e) => {
+                problems.push((hash, height, format!("raw_data doesn't decode: {}", e)));
+                continue;
+            }
+        };
+
+        let actual_hash = block.block_hash().to_string();
+        if actual_hash != hash {
+            problems.push((hash.clone(), height, format!("raw_data hashes to {}", actual_hash)));
+        }
+        match block.compute_merkle_root() {
+            Some(root) if root.to_string() == merkle_root => {}
+            Some(root) => problems.push((hash.clone(), height, format!(
+                "stored merkle_root {}, raw_data's transactions hash to {}", merkle_root, root
+            ))),
+            None => problems.push((hash.clone(), height, "raw_data has no transactions".to_string())),
+        }
+        if block.txdata.len() != tx_count {
+            problems.push((hash, height, format!(
+                "stored tx_count {}, raw_data has {} transactions", tx_count, block.txdata.len()
+            )));
+        }
+    }
+    Ok(problems)
+}
+
+// Transactions whose block_hash doesn't match any stored block, as (txid, block_hash)
+pub fn query_orphaned_txs(conn: &Connection) -> Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.txid, t.block_hash FROM transactions t
+         LEFT JOIN blocks b ON b.hash = t.block_hash
+         WHERE t.block_hash IS NOT NULL AND b.hash IS NULL
+         ORDER BY t.block_hash, t.index_in_block"
+    )?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    const SUBSIDY: u64 = 50 * 100_000_000;
+
+    #[test]
+    fn node_reported_fees_and_input_values_are_kept_without_indexed_prevouts() {
+        let conn = test_db();
+        let config = ChainConfig::default();
+        // Spends an output of a block that was never indexed
+        let unindexed = coinbase_tx(7, 5_000);
+        let spend = spend_tx(&unindexed, 0, 4_500);
+        let block = test_block(bitcoin::BlockHash::all_zeros(), 0, vec![coinbase_tx(0, SUBSIDY), spend.clone()]);
+        let node_txs = [
+            NodeTxInfo::default(),
+            NodeTxInfo { fee: Some(500), input_values: vec![Some(5_000)] },
+        ];
+        insert_block_with_fees(&conn, &block, 0, Some(&node_txs), &config).unwrap();
+
+        let tx = query_tx(&conn, &spend.compute_txid().to_string()).unwrap().unwrap();
+        assert_eq!(tx.fee, Some(500));
+        assert_eq!(tx.fee_rate, Some(500.0 / tx.vsize as f64));
+        assert_eq!(tx.inputs[0].value, Some(5_000));
+
+        // Without the node's report there's nothing to compute the fee from
+        let other = spend_tx(&unindexed, 0, 4_000);
+        let block = test_block(block.block_hash(), 1, vec![coinbase_tx(1, SUBSIDY), other.clone()]);
+        insert_block(&conn, &block, 1, &config).unwrap();
+        let tx = query_tx(&conn, &other.compute_txid().to_string()).unwrap().unwrap();
+        assert_eq!((tx.fee, tx.inputs[0].value), (None, None));
+    }
+
+    #[test]
+    fn daily_stats_fill_days_without_blocks() {
+        let conn = test_db();
+        let config = ChainConfig::default();
+        // Ten minutes apart from 2023-11-14 22:13:20 UTC, so the 12th lands on the 15th
+        let chain = store_chain(&conn, 12);
+        let mut late = test_block(chain[11].block_hash(), 12, vec![coinbase_tx(12, SUBSIDY)]);
+        late.header.time = 1_700_000_000 + 4 * 86_400;
+        insert_block(&conn, &late, 12, &config).unwrap();
+
+        let days = query_daily_stats(&conn, 5).unwrap();
+        let summary: Vec<(&str, u32)> = days.iter().map(|day| (day.date.as_str(), day.blocks)).collect();
+        assert_eq!(summary, [("2023-11-14", 11), ("2023-11-15", 1), ("2023-11-16", 0), ("2023-11-17", 0), ("2023-11-18", 1)]);
+        assert_eq!((days[0].transactions, days[0].output_volume), (11, 11 * 5_000));
+        assert_eq!((days[2].transactions, days[2].output_volume, days[2].weight), (0, 0, 0));
+
+        let days = query_daily_stats(&conn, 2).unwrap();
+        assert_eq!(days.iter().map(|day| day.date.as_str()).collect::<Vec<_>>(), ["2023-11-17", "2023-11-18"]);
+        assert!(query_daily_stats(&test_db(), 30).unwrap().is_empty());
+    }
+
+    #[test]
+    fn recent_fee_rates_cover_the_newest_blocks_without_coinbases() {
+        let conn = test_db();
+        let config = ChainConfig::default();
+        let block0 = test_block(BlockHash::all_zeros(), 0, vec![coinbase_tx(0, SUBSIDY)]);
+        // Fees of 1,000 and then 3,000 sats, on transactions of the same size
+        let cheap = spend_tx(&block0.txdata[0], 0, SUBSIDY - 1_000);
+        let block1 = test_block(block0.block_hash(), 1, vec![coinbase_tx(1, SUBSIDY), cheap.clone()]);
+        let dear = spend_tx(&block1.txdata[0], 0, SUBSIDY - 3_000);
+        let block2 = test_block(block1.block_hash(), 2, vec![coinbase_tx(2, SUBSIDY), dear]);
+        for (height, block) in [&block0, &block1, &block2].into_iter().enumerate() {
+            insert_block(&conn, block, height as u32, &config).unwrap();
+        }
+
+        let vsize = cheap.vsize() as f64;
+        assert_eq!(query_recent_fee_rates(&conn, 1).unwrap(), vec![3_000.0 / vsize]);
+        assert_eq!(query_recent_fee_rates(&conn, 2).unwrap(), vec![1_000.0 / vsize, 3_000.0 / vsize]);
+        assert_eq!(query_recent_fee_rates(&conn, 1008).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn handle_reorg_removes_every_trace_of_the_orphaned_blocks() {
+        let conn = test_db();
+        let config = ChainConfig::default();
+        // Chain A up to height 3. Block 2 spends block 1's coinbase and carries an OP_RETURN.
+        let mut chain: Vec<bitcoin::Block> = Vec::new();
+        for height in 0..=3 {
+            let mut txdata = vec![coinbase_tx(height, SUBSIDY)];
+            if height == 2 {
+                let mut spend = spend_tx(&chain[1].txdata[0], 0, SUBSIDY - 1_000);
+                spend.output.push(bitcoin::TxOut {
+                    value: bitcoin::Amount::ZERO,
+                    script_pubkey: bitcoin::ScriptBuf::new_op_return(b"orphaned"),
+                });
+                txdata.push(spend);
+            }
+            let prev = chain.last().map_or(BlockHash::all_zeros(), |block| block.block_hash());
+            let block = test_block(prev, height, txdata);
+            insert_block(&conn, &block, height, &config).unwrap();
+            chain.push(block);
+        }
+
+        let orphaned_blocks: Vec<String> = chain[2..].iter().map(|block| block.block_hash().to_string()).collect();
+        let orphaned_txids: Vec<String> = chain[2..].iter()
+            .flat_map(|block| &block.txdata)
+            .map(|tx| tx.compute_txid().to_string())
+            .collect();
+        let count = |sql: &str, ids: &[String]| -> i64 {
+            let sql = format!("{} IN (SELECT value FROM json_each(?1))", sql);
+            conn.query_row(&sql, [serde_json::to_string(ids).unwrap()], |row| row.get(0)).unwrap()
+        };
+        let traces = || [
+            count("SELECT COUNT(*) FROM blocks WHERE hash", &orphaned_blocks),
+            count("SELECT COUNT(*) FROM block_filters WHERE block_hash", &orphaned_blocks),
+            count("SELECT COUNT(*) FROM transactions WHERE txid", &orphaned_txids),
+            count("SELECT COUNT(*) FROM spends WHERE spending_txid", &orphaned_txids),
+            count("SELECT COUNT(*) FROM address_index WHERE txid", &orphaned_txids),
+            count("SELECT COUNT(*) FROM op_returns WHERE txid", &orphaned_txids),
+        ];
+        assert_eq!(traces(), [2, 2, 3, 1, 3, 1]);
+
+        // A competing chain forking at height 2, on top of A's block 1. Its block at height 3
+        // doesn't build on the stored block 2, which is how the indexer notices.
+        let competing = test_block(chain[1].block_hash(), 2, vec![coinbase_tx(2, SUBSIDY - 1)]);
+        let competing_tip = test_block(competing.block_hash(), 3, vec![coinbase_tx(3, SUBSIDY - 1)]);
+        assert!(extends_stored_chain(&conn, &competing, 2).unwrap());
+        assert!(!extends_stored_chain(&conn, &competing_tip, 3).unwrap());
+        assert_eq!(handle_reorg(&conn, 2).unwrap(), 2);
+
+        assert_eq!(traces(), [0; 6]);
+        // The output the orphaned block spent is unspent again
+        let spent: bool = conn.query_row(
+            "SELECT spent FROM address_index WHERE txid = ?1",
+            [chain[1].txdata[0].compute_txid().to_string()],
+            |row| row.get(0),
+        ).unwrap();
+        assert!(!spent);
+
+        insert_block(&conn, &competing, 2, &config).unwrap();
+        assert!(extends_stored_chain(&conn, &competing_tip, 3).unwrap());
+        insert_block(&conn, &competing_tip, 3, &config).unwrap();
+        assert_eq!(query_block_count(&conn).unwrap(), 4);
+        assert_eq!(query_latest_block(&conn).unwrap(), Some((3, competing_tip.block_hash().to_string())));
+    }
+
+    #[test]
+    fn insert_block_round_trips() {
+        let conn = test_db();
+        let block = test_block(bitcoin::BlockHash::all_zeros(), 0, vec![coinbase_tx(0, SUBSIDY)]);
+        insert_block(&conn, &block, 0, &ChainConfig::default()).unwrap();
+
+        let hash = block.block_hash().to_string();
+        let stored = query_block(&conn, &hash).unwrap().expect("block is stored");
+        assert_eq!(stored.hash, hash);
+        assert_eq!(stored.height, 0);
+        assert_eq!(stored.prev_block, block.header.prev_blockhash.to_string());
+        assert_eq!(stored.merkle_root, block.header.merkle_root.to_string());
+        assert_eq!(stored.timestamp, block.header.time);
+        assert_eq!(stored.bits, block.header.bits.to_consensus());
+        assert_eq!(stored.tx_count, 1);
+        assert_eq!(stored.size, block.total_size());
+        assert_eq!(stored.weight, Some(block.weight().to_wu()));
+        assert_eq!((stored.total_output_value, stored.subsidy_plus_fees), (SUBSIDY, SUBSIDY));
+
+        let by_height = query_block_by_height(&conn, 0).unwrap().expect("block is stored");
+        assert_eq!(by_height.hash, hash);
+        assert_eq!(query_block_raw(&conn, &hash).unwrap(), Some(Some(bitcoin::consensus::serialize(&block))));
+    }
+
+    #[test]
+    fn difficulty_and_chainwork() {
+        let conn = test_db();
+        let config = ChainConfig::default();
+        let block0 = test_block(bitcoin::BlockHash::all_zeros(), 0, vec![coinbase_tx(0, SUBSIDY)]);
+        insert_block(&conn, &block0, 0, &config).unwrap();
+        let block1 = test_block(block0.block_hash(), 1, vec![coinbase_tx(1, SUBSIDY)]);
+        insert_block(&conn, &block1, 1, &config).unwrap();
+
+        // Regtest's minimum difficulty; `bitcoin-cli -regtest getblock` rounds it to
+        // 4.656542373906925e-10. Each block at that target is worth 2 hashes.
+        let stored = query_block(&conn, &block1.block_hash().to_string()).unwrap().unwrap();
+        assert_eq!(stored.difficulty, 4.6565423739069247e-10);
+        assert_eq!(stored.chainwork, Some(format!("{:064x}", 4)));
+
+        // A block whose parent isn't stored has no chainwork
+        let detached = test_block(bitcoin::BlockHash::from_byte_array([1; 32]), 5, vec![coinbase_tx(5, SUBSIDY)]);
+        insert_block(&conn, &detached, 5, &config).unwrap();
+        let stored = query_block(&conn, &detached.block_hash().to_string()).unwrap().unwrap();
+        assert_eq!(stored.chainwork, None);
+    }
+
+    #[test]
+    fn address_balance_follows_spends() {
+        let conn = test_db();
+        let config = ChainConfig::default();
+        let funding = coinbase_tx(0, 5_000);
+        let block0 = test_block(BlockHash::all_zeros(), 0, vec![funding.clone()]);
+        // Pays 4,000 of the 5,000 on to another address
+        let spend = spend_tx(&funding, 0, 4_000);
+        let block1 = test_block(block0.block_hash(), 1, vec![coinbase_tx(1, 0), spend.clone()]);
+        insert_block(&conn, &block0, 0, &config).unwrap();
+        insert_block(&conn, &block1, 1, &config).unwrap();
+        let address = |seed| bitcoin::Address::from_script(&p2wpkh_script(seed), config.network).unwrap().to_string();
+
+        let payer = query_address(&conn, &address(1), 10, 0).unwrap();
+        assert_eq!((payer.total_received, payer.total_sent, payer.balance), (5_000, 5_000, 0));
+        // Newest first, and last in the block first; the zero-value coinbase of block 1 pays
+        // this address too
+        assert_eq!(payer.tx_count, 3);
+        assert_eq!(payer.transactions[0], spend.compute_txid().to_string());
+        assert_eq!(payer.transactions[2], funding.compute_txid().to_string());
+
+        let payee = query_address(&conn, &address(2), 10, 0).unwrap();
+        assert_eq!((payee.total_received, payee.balance, payee.tx_count), (4_000, 4_000, 1));
+        assert_eq!(query_address(&conn, &address(1), 1, 2).unwrap().transactions, vec![funding.compute_txid().to_string()]);
+    }
+
+    #[test]
+    fn address_history_seeks_past_cursor() {
+        let conn = test_db();
+        let config = ChainConfig::default();
+        let mut prev = bitcoin::BlockHash::all_zeros();
+        for height in 0..3 {
+            let block = test_block(prev, height, vec![coinbase_tx(height, SUBSIDY)]);
+            insert_block(&conn, &block, height, &config).unwrap();
+            prev = block.block_hash();
+        }
+        let address = bitcoin::Address::from_script(&p2wpkh_script(1), config.network).unwrap().to_string();
+
+        let first = query_address_history(&conn, &address, 2, None).unwrap();
+        assert_eq!(first.iter().map(|e| e.block_height).collect::<Vec<_>>(), [2, 1]);
+        let last = first.last().unwrap();
+        let cursor = HistoryCursor { height: last.block_height, txid: last.txid.clone() };
+        let rest = query_address_history(&conn, &address, 2, Some(&cursor)).unwrap();
+        assert_eq!(rest.iter().map(|e| e.block_height).collect::<Vec<_>>(), [0]);
+    }
+
+    #[test]
+    fn coinbase_height_and_tag() {
+        let conn = test_db();
+        let config = ChainConfig::default();
+        // Genesis-style coinbase from before BIP34: it starts with the bits, not the height
+        let mut genesis = coinbase_tx(0, SUBSIDY);
+        genesis.input[0].script_sig = bitcoin::script::Builder::new()
+            .push_int(486604799)
+            .push_slice(b"\x04The Times 03/Jan/2009 Chancellor on brink of second bailout for banks")
+            .into_script();
+        let block0 = test_block(bitcoin::BlockHash::all_zeros(), 0, vec![genesis.clone()]);
+        insert_block(&conn, &block0, 0, &config).unwrap();
+        let mut tagged = coinbase_tx(300, SUBSIDY);
+        tagged.input[0].script_sig = bitcoin::script::Builder::new()
+            .push_int(300)
+            .push_slice([0xfa, 0xbe, b'm', b'm'])
+            .push_slice(b"/slush/\x00\x01Mined by AntPool")
+            .into_script();
+        let block300 = test_block(block0.block_hash(), 300, vec![tagged.clone()]);
+        insert_block(&conn, &block300, 300, &config).unwrap();
+
+        let input = &query_tx(&conn, &genesis.compute_txid().to_string()).unwrap().unwrap().inputs[0];
+        assert_eq!(input.coinbase_height, None);
+        assert_eq!(input.coinbase_tag.as_deref(), Some("The Times 03/Jan/2009 Chancellor on brink of second bailout for banks"));
+        let input = &query_tx(&conn, &tagged.compute_txid().to_string()).unwrap().unwrap().inputs[0];
+        assert_eq!(input.coinbase_height, Some(300));
+        assert_eq!(input.coinbase_tag.as_deref(), Some("/slush/ Mined by AntPool"));
+    }
+
+    #[test]
+    fn unknown_block_is_none() {
+        let conn = test_db();
+        assert!(query_block(&conn, &"00".repeat(32)).unwrap().is_none());
+        assert!(query_block_by_height(&conn, 0).unwrap().is_none());
+    }
+
+    #[test]
+    fn insert_tx_round_trips() {
+        let conn = test_db();
+        let config = ChainConfig::default();
+        let funding = coinbase_tx(0, SUBSIDY);
+        let block0 = test_block(bitcoin::BlockHash::all_zeros(), 0, vec![funding.clone()]);
+        insert_block(&conn, &block0, 0, &config).unwrap();
+        let block1 = test_block(block0.block_hash(), 1, vec![coinbase_tx(1, SUBSIDY)]);
+        insert_block(&conn, &block1, 1, &config).unwrap();
+        let spend = spend_tx(&funding, 0, SUBSIDY - 10_000);
+        insert_tx(&conn, &spend, &block1.block_hash().to_string(), 1, None, &config).unwrap();
+
+        let txid = spend.compute_txid().to_string();
+        let stored = query_tx(&conn, &txid).unwrap().expect("tx is stored");
+        assert_eq!(stored.txid, txid);
+        assert_eq!(stored.block_hash, Some(block1.block_hash().to_string()));
+        assert_eq!(stored.block_height, Some(1));
+        assert_eq!(stored.confirmations, Some(1));
+        assert_eq!(stored.size, spend.total_size());
+        assert_eq!(stored.vsize, spend.vsize());
+        assert_eq!(stored.weight, spend.weight().to_wu() as usize);
+        assert!(!stored.is_coinbase);
+        assert!(stored.has_witness);
+
+        assert_eq!(stored.inputs.len(), 1);
+        assert_eq!(stored.inputs[0].prev_txid, funding.compute_txid().to_string());
+        assert_eq!(stored.inputs[0].vout, 0);
+        assert_eq!(stored.inputs[0].value, Some(SUBSIDY));
+        assert_eq!(stored.outputs.len(), 1);
+        assert_eq!(stored.outputs[0].value, SUBSIDY - 10_000);
+        assert_eq!(stored.outputs[0].script_type, "p2wpkh");
+        assert!(stored.outputs[0].address.is_some());
+        assert_eq!(stored.fee, Some(10_000));
+
+        // The funding coinbase is now a block deep and its output is spent
+        let funding = query_tx(&conn, &funding.compute_txid().to_string()).unwrap().expect("tx is stored");
+        assert!(funding.is_coinbase);
+        assert_eq!(funding.fee, None);
+        assert_eq!(funding.confirmations, Some(2));
+        assert!(funding.outputs[0].spent);
+        assert_eq!(funding.outputs[0].spent_by_txid, Some(txid));
+    }
+
+    #[test]
+    fn segwit_tx_size_breakdown() {
+        // Signed native P2WPKH example from BIP143: one legacy input, one P2WPKH input
+        let tx: bitcoin::Transaction = bitcoin::consensus::encode::deserialize_hex(concat!(
+            "01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000",
+            "0000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cdd30be",
+            "022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeffffffef51",
+            "e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff0220",
+            "2cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d00000000",
+            "1976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247304402203609e17b84f6a7d3",
+            "0c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f",
+            "3358f51928d43c212a8caed02de67eebee0121025476c2e83188368da1ff3e292e7acafcdb3566bb0a",
+            "d253f62fc70f07aeee635711000000",
+        )).unwrap();
+        let conn = test_db();
+        let block = test_block(bitcoin::BlockHash::all_zeros(), 0, vec![coinbase_tx(0, SUBSIDY), tx.clone()]);
+        insert_block(&conn, &block, 0, &ChainConfig::default()).unwrap();
+
+        let stored = query_tx(&conn, &tx.compute_txid().to_string()).unwrap().expect("tx is stored");
+        assert_eq!(stored.size, 343);
+        assert_eq!(stored.base_size, 233);
+        // Marker and flag, an empty witness, and a signature and public key
+        assert_eq!(stored.witness_size, 110);
+        assert_eq!(stored.weight, 233 * 4 + 110);
+        assert_eq!(stored.vsize, 261);
+    }
+
+    #[test]
+    fn next_and_prev_skip_gaps() {
+        let conn = test_db();
+        let config = ChainConfig::default();
+        let mut prev = bitcoin::BlockHash::all_zeros();
+        let mut hashes = Vec::new();
+        for height in [0, 1, 3] {
+            let block = test_block(prev, height, vec![coinbase_tx(height, SUBSIDY)]);
+            insert_block(&conn, &block, height, &config).unwrap();
+            prev = block.block_hash();
+            hashes.push(prev.to_string());
+        }
+
+        assert_eq!(query_next_block(&conn, 0).unwrap().map(|b| b.hash), Some(hashes[1].clone()));
+        assert_eq!(query_next_block(&conn, 1).unwrap().map(|b| b.height), Some(3));
+        assert!(query_next_block(&conn, 3).unwrap().is_none());
+        assert_eq!(query_prev_block(&conn, 3).unwrap().map(|b| b.height), Some(1));
+        assert_eq!(query_prev_block(&conn, 1).unwrap().map(|b| b.hash), Some(hashes[0].clone()));
+        assert!(query_prev_block(&conn, 0).unwrap().is_none());
+    }
+
+    #[test]
+    fn txid_at_index_follows_block_order() {
+        let conn = test_db();
+        let funding = coinbase_tx(0, SUBSIDY);
+        let spend = spend_tx(&funding, 0, SUBSIDY - 10_000);
+        let block = test_block(bitcoin::BlockHash::all_zeros(), 0, vec![funding.clone(), spend.clone()]);
+        insert_block(&conn, &block, 0, &ChainConfig::default()).unwrap();
+
+        let hash = block.block_hash().to_string();
+        assert_eq!(query_txid_at_index(&conn, &hash, 0).unwrap(), Some(funding.compute_txid().to_string()));
+        assert_eq!(query_txid_at_index(&conn, &hash, 1).unwrap(), Some(spend.compute_txid().to_string()));
+        assert_eq!(query_txid_at_index(&conn, &hash, 2).unwrap(), None);
+    }
+
+    #[test]
+    fn block_filter_covers_spent_and_created_scripts() {
+        let conn = test_db();
+        let config = ChainConfig::default();
+        let coinbase0 = coinbase_tx(0, SUBSIDY);
+        let block0 = test_block(bitcoin::BlockHash::all_zeros(), 0, vec![coinbase0.clone()]);
+        insert_block(&conn, &block0, 0, &config).unwrap();
+        let block1 = test_block(block0.block_hash(), 1, vec![coinbase_tx(1, SUBSIDY), spend_tx(&coinbase0, 0, 1_000)]);
+        insert_block(&conn, &block1, 1, &config).unwrap();
+
+        let hash = block1.block_hash();
+        let filter = query_block_filter(&conn, &hash.to_string()).unwrap().unwrap().expect("filter is stored");
+        let filter = BlockFilter::new(&filter);
+        for script in [p2wpkh_script(1), p2wpkh_script(2)] {
+            assert!(filter.match_any(&hash, [script.as_bytes()].into_iter()).unwrap());
+        }
+        assert!(!filter.match_any(&hash, [p2wpkh_script(9).as_bytes()].into_iter()).unwrap());
+
+        // Spending an output that isn't indexed leaves the block without a filter
+        let unknown = coinbase_tx(7, SUBSIDY);
+        let block2 = test_block(hash, 2, vec![coinbase_tx(2, SUBSIDY), spend_tx(&unknown, 0, 1_000)]);
+        insert_block(&conn, &block2, 2, &config).unwrap();
+        assert_eq!(query_block_filter(&conn, &block2.block_hash().to_string()).unwrap(), Some(None));
+        assert_eq!(query_block_filter(&conn, &"00".repeat(32)).unwrap(), None);
+    }
+
+    #[test]
+    fn mempool_conflicts_until_one_confirms() {
+        let conn = test_db();
+        let config = ChainConfig::default();
+        let coinbase0 = coinbase_tx(0, SUBSIDY);
+        let block0 = test_block(bitcoin::BlockHash::all_zeros(), 0, vec![coinbase0.clone()]);
+        insert_block(&conn, &block0, 0, &config).unwrap();
+
+        let replaceable = spend_tx(&coinbase0, 0, 1_000);
+        let mut final_tx = spend_tx(&coinbase0, 0, 2_000);
+        final_tx.input[0].sequence = bitcoin::Sequence::MAX;
+        insert_mempool_tx(&conn, &replaceable, 1, 100, 110).unwrap();
+        insert_mempool_tx(&conn, &final_tx, 2, 100, 110).unwrap();
+
+        let entries = query_mempool(&conn, 10, 0).unwrap();
+        let flags: Vec<_> = entries.iter().map(|entry| (entry.rbf, entry.double_spend, entry.conflicts.clone())).collect();
+        assert_eq!(flags, vec![
+            (false, true, vec![replaceable.compute_txid().to_string()]),
+            (true, true, vec![final_tx.compute_txid().to_string()]),
+        ]);
+
+        // Once one of them is mined, the other can never confirm and is dropped
+        let block1 = test_block(block0.block_hash(), 1, vec![coinbase_tx(1, SUBSIDY), final_tx]);
+        insert_block(&conn, &block1, 1, &config).unwrap();
+        assert!(query_mempool(&conn, 10, 0).unwrap().is_empty());
+        let leftover: i64 = conn.query_row("SELECT COUNT(*) FROM mempool_spends", [], |row| row.get(0)).unwrap();
+        assert_eq!(leftover, 0);
+    }
+
+    #[test]
+    fn storing_a_block_twice_leaves_no_stale_transactions() {
+        let conn = test_db();
+        let config = ChainConfig::default();
+        let coinbase0 = coinbase_tx(0, SUBSIDY);
+        let block0 = test_block(bitcoin::BlockHash::all_zeros(), 0, vec![coinbase0.clone()]);
+        insert_block(&conn, &block0, 0, &config).unwrap();
+        let block1 = test_block(block0.block_hash(), 1, vec![coinbase_tx(1, SUBSIDY), spend_tx(&coinbase0, 0, 1_000)]);
+        assert!(insert_block(&conn, &block1, 1, &config).unwrap());
+        let counts = query_counts_fast(&conn).unwrap();
+
+        // Skipped by default
+        assert!(!insert_block(&conn, &block1, 1, &config).unwrap());
+        assert_eq!(query_counts_fast(&conn).unwrap(), counts);
+
+        // A row filed under the block that it doesn't contain is dropped when the block is rewritten
+        let hash = block1.block_hash().to_string();
+        insert_tx(&conn, &coinbase_tx(99, 1_000), &hash, 2, None, &config).unwrap();
+        let update = ChainConfig { on_conflict: OnConflict::Update, ..config };
+        assert!(insert_block(&conn, &block1, 1, &update).unwrap());
+
+        let stored: usize = conn
+            .query_row("SELECT COUNT(*) FROM transactions WHERE block_hash = ?1", [&hash], |row| row.get(0))
+            .unwrap();
+        assert_eq!(stored, block1.txdata.len());
+        assert!(query_orphaned_txs(&conn).unwrap().is_empty());
+        assert_eq!(query_counts_fast(&conn).unwrap(), counts);
+        assert_eq!(query_counts_exact(&conn).unwrap(), counts);
+    }
+
+    #[test]
+    fn no_raw_keeps_everything_but_the_raw_bytes() {
+        let conn = test_db();
+        let config = ChainConfig { store_raw: false, ..ChainConfig::default() };
+        let coinbase = coinbase_tx(0, SUBSIDY);
+        let block = test_block(bitcoin::BlockHash::all_zeros(), 0, vec![coinbase.clone()]);
+        insert_block(&conn, &block, 0, &config).unwrap();
+
+        let hash = block.block_hash().to_string();
+        let txid = coinbase.compute_txid().to_string();
+        assert_eq!(query_block_raw(&conn, &hash).unwrap(), Some(None));
+        assert_eq!(query_tx_raw(&conn, &txid).unwrap(), Some(None));
+        assert_eq!(query_block(&conn, &hash).unwrap().unwrap().size, block.total_size());
+        assert_eq!(query_tx(&conn, &txid).unwrap().unwrap().outputs[0].value, SUBSIDY);
+    }
+
+    #[test]
+    fn tip_is_the_highest_block() {
+        let conn = test_db();
+        assert!(query_tip(&conn).unwrap().is_none());
+
+        let config = ChainConfig::default();
+        let block0 = test_block(bitcoin::BlockHash::all_zeros(), 0, vec![coinbase_tx(0, SUBSIDY)]);
+        insert_block(&conn, &block0, 0, &config).unwrap();
+        let block1 = test_block(block0.block_hash(), 1, vec![coinbase_tx(1, SUBSIDY)]);
+        insert_block(&conn, &block1, 1, &config).unwrap();
+
+        let tip = query_tip(&conn).unwrap().expect("blocks are stored");
+        assert_eq!(tip.height, 1);
+        assert_eq!(tip.hash, block1.block_hash().to_string());
+        assert_eq!(tip.timestamp, block1.header.time);
+    }
+
+    #[test]
+    fn unknown_tx_is_none() {
+        let conn = test_db();
+        assert!(query_tx(&conn, &"00".repeat(32)).unwrap().is_none());
+    }
+}
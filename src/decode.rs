@@ -0,0 +1,74 @@
+use bitcoin::{Script, Transaction};
+use crate::models::*;
+use crate::script::{output_address, output_script_type, script_sig_to_asm, script_to_asm};
+
+// Full view of a transaction in the layout of Bitcoin Core's decoderawtransaction
+pub fn decode_transaction(tx: &Transaction, network: bitcoin::Network) -> DecodedTx {
+    let is_coinbase = tx.is_coinbase();
+    let vin = tx.input.iter()
+        .map(|input| {
+            let txinwitness = input.witness.iter().map(hex::encode).collect();
+            if is_coinbase {
+                return DecodedInput {
+                    coinbase: Some(hex::encode(input.script_sig.as_bytes())),
+                    txid: None,
+                    vout: None,
+                    script_sig: None,
+                    txinwitness,
+                    sequence: input.sequence.0,
+                };
+            }
+            DecodedInput {
+                coinbase: None,
+                txid: Some(input.previous_output.txid.to_string()),
+                vout: Some(input.previous_output.vout),
+                script_sig: Some(DecodedScriptSig {
+                    asm: script_sig_to_asm(&input.script_sig),
+                    hex: hex::encode(input.script_sig.as_bytes()),
+                }),
+                txinwitness,
+                sequence: input.sequence.0,
+            }
+        })
+        .collect();
+
+    let vout = (0..).zip(&tx.output)
+        .map(|(n, output)| DecodedOutput {
+            value: output.value.to_btc(),
+            n,
+            script_pubkey: DecodedScriptPubKey {
+                asm: script_to_asm(&output.script_pubkey),
+                hex: hex::encode(output.script_pubkey.as_bytes()),
+                address: output_address(&output.script_pubkey, network),
+                script_type: core_script_type(&output.script_pubkey).to_string(),
+            },
+        })
+        .collect();
+
+    DecodedTx {
+        txid: tx.compute_txid().to_string(),
+        hash: tx.compute_wtxid().to_string(),
+        version: tx.version.0,
+        size: tx.total_size(),
+        vsize: tx.vsize(),
+        weight: tx.weight().to_wu() as usize,
+        locktime: tx.lock_time.to_consensus_u32(),
+        vin,
+        vout,
+    }
+}
+
+// output_script_type under the names Core reports
+fn core_script_type(script: &Script) -> &'static str {
+    match output_script_type(script) {
+        "p2pkh" => "pubkeyhash",
+        "p2sh" => "scripthash",
+        "p2wpkh" => "witness_v0_keyhash",
+        "p2wsh" => "witness_v0_scripthash",
+        "p2tr" => "witness_v1_taproot",
+        "op_return" => "nulldata",
+        _ if script.is_witness_program() => "witness_unknown",
+        _ if script.is_p2pk() => "pubkey",
+        _ => "nonstandard",
+    }
+}
@@ -0,0 +1,97 @@
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde_json::json;
+use std::fmt;
+use thiserror::Error;
+
+// Errors that can occur while fetching and decoding blocks from the node
+#[derive(Debug, Error)]
+pub enum IndexError {
+    #[error("RPC request failed: {0}")]
+    Transport(#[from] reqwest::Error),
+    #[error("RPC error: {0}")]
+    RpcError(String),
+    #[error("RPC server returned HTTP {0}: {1}")]
+    HttpStatus(u16, String),
+    #[error("Invalid RPC response: {0}")]
+    InvalidResponse(#[from] serde_json::Error),
+    #[error("Missing field in RPC response: {0}")]
+    MissingField(&'static str),
+    #[error("Hex decode failed: {0}")]
+    DecodeError(#[from] hex::FromHexError),
+    #[error("Consensus decode failed: {0}")]
+    ConsensusError(#[from] bitcoin::consensus::encode::Error),
+}
+
+// An error answered by the HTTP API. Every one has the same body,
+// {"error": {"code": ..., "message": ..., "details": ...}}: `code` is a stable snake_case
+// string for clients to branch on, `message` is for people and may change, and `details`
+// holds what the error is about, like the hash that wasn't found, or null.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    details: serde_json::Value,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self { status, code, message: message.into(), details: serde_json::Value::Null }
+    }
+
+    // 400 for a path segment, query parameter or body that doesn't parse or is out of range
+    pub fn invalid(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, message)
+    }
+
+    pub fn not_found(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, code, message)
+    }
+
+    // 409 for something that exists but was indexed without what's asked for
+    pub fn conflict(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, code, message)
+    }
+
+    pub fn internal(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, code, message)
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = details;
+        self
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(json!({
+            "error": {
+                "code": self.code,
+                "message": self.message,
+                "details": self.details
+            }
+        }))
+    }
+}
+
+impl From<rusqlite::Error> for ApiError {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::internal("database_error", e.to_string())
+    }
+}
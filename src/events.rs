@@ -0,0 +1,117 @@
+use rusqlite::{Connection, Result};
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use crate::db::{query_block_hash_at_height, query_blocks_range, query_latest_block, DbPool};
+use crate::models::BlockSummary;
+
+// Blocks a subscriber may fall behind before it's dropped
+const CHANNEL_CAPACITY: usize = 64;
+
+// Announced blocks the watcher remembers. A reorg deeper than this is announced again
+// from the oldest one it still knows.
+const REORG_DEPTH: usize = 100;
+
+fn channel() -> &'static broadcast::Sender<BlockSummary> {
+    static SENDER: OnceLock<broadcast::Sender<BlockSummary>> = OnceLock::new();
+    SENDER.get_or_init(|| broadcast::channel(CHANNEL_CAPACITY).0)
+}
+
+// Announce a newly indexed block to every subscriber
+pub fn publish_block(summary: BlockSummary) {
+    // Sending only fails when nobody is subscribed
+    let _ = channel().send(summary);
+}
+
+pub fn subscribe_blocks() -> broadcast::Receiver<BlockSummary> {
+    channel().subscribe()
+}
+
+// The indexer usually runs as a separate process, so the server picks up its blocks by
+// polling the database and publishing anything it hasn't announced yet
+pub async fn watch_new_blocks(pool: DbPool, interval: Duration) {
+    let mut announced = None;
+    loop {
+        if let Ok(conn) = pool.get() {
+            match unannounced_blocks(&conn, &mut announced) {
+                Ok(blocks) => blocks.into_iter().for_each(publish_block),
+                Err(e) => log::warn!("Block watcher query failed: {}", e),
+            }
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+// Stored blocks not announced yet, oldest first. `announced` maps the heights of the last
+// announced blocks to their hashes; when a reorg has replaced some of them, the new chain is
+// announced again from the lowest replaced height. None until the first poll, which only
+// records the tip.
+fn unannounced_blocks(conn: &Connection, announced: &mut Option<BTreeMap<u32, String>>) -> Result<Vec<BlockSummary>> {
+    let tip = query_latest_block(conn)?;
+    let Some(known) = announced else {
+        *announced = Some(tip.into_iter().collect());
+        return Ok(Vec::new());
+    };
+
+    let mut from = known.last_key_value().map_or(0, |(height, _)| height + 1);
+    for (&height, hash) in known.iter().rev() {
+        if query_block_hash_at_height(conn, height)?.as_ref() == Some(hash) {
+            break;
+        }
+        from = height;
+    }
+    // Replaced or rolled back; the latter follows the tip down until new blocks arrive
+    known.split_off(&from);
+
+    let blocks = match tip {
+        Some((tip_height, _)) if tip_height >= from => query_blocks_range(conn, from, tip_height)?,
+        _ => Vec::new(),
+    };
+    known.extend(blocks.iter().map(|block| (block.height, block.hash.clone())));
+    while known.len() > REORG_DEPTH {
+        known.pop_first();
+    }
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{handle_reorg, insert_block};
+    use crate::config::ChainConfig;
+    use crate::test_utils::*;
+
+    #[test]
+    fn reorged_blocks_are_announced_again_from_the_fork() {
+        let conn = test_db();
+        let chain = store_chain(&conn, 5);
+        let hashes = |blocks: Vec<BlockSummary>| blocks.iter().map(|b| (b.height, b.hash.clone())).collect::<Vec<_>>();
+        let mut announced = None;
+        assert!(unannounced_blocks(&conn, &mut announced).unwrap().is_empty());
+        assert!(unannounced_blocks(&conn, &mut announced).unwrap().is_empty());
+
+        let fifth = test_block(chain[4].block_hash(), 5, vec![coinbase_tx(5, 5_000)]);
+        insert_block(&conn, &fifth, 5, &ChainConfig::default()).unwrap();
+        assert_eq!(hashes(unannounced_blocks(&conn, &mut announced).unwrap()), vec![(5, fifth.block_hash().to_string())]);
+
+        // A competing branch from height 4, as long as the old chain
+        handle_reorg(&conn, 4).unwrap();
+        let fourth = test_block(chain[3].block_hash(), 4, vec![coinbase_tx(4, 4_000)]);
+        let fifth = test_block(fourth.block_hash(), 5, vec![coinbase_tx(5, 4_000)]);
+        insert_block(&conn, &fourth, 4, &ChainConfig::default()).unwrap();
+        insert_block(&conn, &fifth, 5, &ChainConfig::default()).unwrap();
+        assert_eq!(hashes(unannounced_blocks(&conn, &mut announced).unwrap()), vec![
+            (4, fourth.block_hash().to_string()),
+            (5, fifth.block_hash().to_string()),
+        ]);
+        assert!(unannounced_blocks(&conn, &mut announced).unwrap().is_empty());
+
+        // A rollback with nothing new announces nothing, then the replacement is announced
+        handle_reorg(&conn, 5).unwrap();
+        assert!(unannounced_blocks(&conn, &mut announced).unwrap().is_empty());
+        let fifth = test_block(fourth.block_hash(), 5, vec![coinbase_tx(5, 3_000)]);
+        insert_block(&conn, &fifth, 5, &ChainConfig::default()).unwrap();
+        assert_eq!(hashes(unannounced_blocks(&conn, &mut announced).unwrap()), vec![(5, fifth.block_hash().to_string())]);
+    }
+}
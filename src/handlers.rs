@@ -1,124 +1,1162 @@
-use actix_web::{web, HttpResponse, Responder};
-use rusqlite::Connection;
+use actix_web::error::{InternalError, PathError};
+use actix_web::http::header::{self, EntityTag, Header};
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use actix_web::{middleware, web, HttpRequest, HttpResponse, Responder, ResponseError};
+use actix_ws::{CloseCode, Message};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use futures_util::{stream, StreamExt};
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use serde::Serialize;
 use serde_json::json;
-use std::sync::Mutex;
 use std::collections::HashMap;
-use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use crate::cache::{LookupCache, RichListCache};
+use crate::config::{ApiConfig, ChainConfig};
 use crate::db::*;
+use crate::decode::decode_transaction;
+use crate::error::{ApiError, IndexError};
+use crate::events::subscribe_blocks;
+use crate::merkle::{merkle_branch, merkle_root_from_branch};
 use crate::models::*;
+use crate::rpc::BitcoinRpcClient;
 
+// Largest number of blocks a single range request may cover
+pub const MAX_RANGE_SPAN: u32 = 2000;
+
+// Blocks /export/blocks.csv reads per query, so a wide range is never held in memory at once
+const EXPORT_PAGE_BLOCKS: u32 = 100;
+
+// Check out a connection for this request, or a 503 if the pool is exhausted
+pub(crate) fn get_conn(db: &DbPool) -> Result<PooledConnection<SqliteConnectionManager>, ApiError> {
+    db.get().map_err(|e| {
+        log::error!("Database unavailable: {}", e);
+        ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "database_unavailable", e.to_string())
+    })
+}
+
+// Block hashes and txids are 64 lowercase hex characters
+pub fn is_valid_hash(value: &str) -> bool {
+    value.len() == 64 && value.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+// Malformed hashes and txids get a 400 before the database is touched, so a 404 always means
+// a well-formed id that isn't indexed
+fn validate_hash(value: &str, field: &str) -> Result<(), ApiError> {
+    if is_valid_hash(value) {
+        return Ok(());
+    }
+    let what = if field == "txid" { "Txid" } else { "Block hash" };
+    Err(ApiError::invalid("invalid_parameter", format!("{} must be 64 lowercase hex characters", what))
+        .with_details(json!({ field: value })))
+}
+
+// An address that parses and is for `network`
+fn is_valid_address(address: &str, network: bitcoin::Network) -> bool {
+    address.parse::<bitcoin::Address<bitcoin::address::NetworkUnchecked>>()
+        .is_ok_and(|a| a.is_valid_for_network(network))
+}
+
+// Like hashes, malformed addresses and ones for another network get a 400 up front
+fn validate_address(address: &str, network: bitcoin::Network) -> Result<(), ApiError> {
+    if is_valid_address(address, network) {
+        return Ok(());
+    }
+    Err(ApiError::invalid("invalid_address", "Not an address on this network").with_details(json!({ "address": address })))
+}
+
+// Path segments that don't parse, like a height that isn't a number, get a JSON 400
+// instead of actix's default plain-text 404
+pub fn path_error(err: PathError, _req: &HttpRequest) -> actix_web::Error {
+    let response = ApiError::invalid("invalid_parameter", err.to_string()).error_response();
+    InternalError::from_response(err, response).into()
+}
+
+// serve --compression: gzip, deflate, brotli or zstd, whichever the client's Accept-Encoding
+// prefers, or responses sent as they are when it's off
+pub fn compress_responses(enabled: bool) -> middleware::Condition<middleware::Compress> {
+    middleware::Condition::new(enabled, middleware::Compress::default())
+}
+
+#[utoipa::path(
+    get,
+    path = "/block/{hash}",
+    params(
+        ("hash" = String, Path, description = "Block hash"),
+        ("v" = Option<u8>, Query, description = "Response shape: 1 (default) or 2; Accept: application/vnd.explorer.v2+json also selects 2"),
+    ),
+    responses(
+        (status = 200, description = "Block header fields", content(
+            ("application/json" = BlockResponse),
+            ("application/vnd.explorer.v2+json" = BlockResponseV2),
+        )),
+        (status = 304, description = "Not modified: If-None-Match holds the current ETag"),
+        (status = 400, description = "Malformed block hash", body = ErrorResponse),
+        (status = 404, description = "Block not found", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
 pub async fn get_block(
-    db: web::Data<Arc<Mutex<Connection>>>,
+    req: HttpRequest,
+    db: web::Data<DbPool>,
+    cache: web::Data<LookupCache>,
     hash: web::Path<String>,
-) -> impl Responder {
+) -> Result<HttpResponse, ApiError> {
     let hash = hash.into_inner();
-    let conn = db.lock().unwrap();
+    validate_hash(&hash, "hash")?;
+    let shape = requested_shape(&req)?;
+    let conn = get_conn(&db)?;
     
-    match query_block(&conn, &hash) {
-        Ok(Some(block)) => HttpResponse::Ok().json(block),
-        Ok(None) => HttpResponse::NotFound().json(json!({
-            "error": "Block not found",
-            "hash": hash
-        })),
-        Err(e) => HttpResponse::InternalServerError().json(json!({
-            "error": "Database error",
-            "message": e.to_string()
+    match cache.block(&conn, &hash)? {
+        Some(block) => Ok(with_etag(&req, shape.tag(block.hash.clone()), || {
+            shape.ok(shape.json::<_, BlockResponseV2>(block))
         })),
+        None => Err(block_not_found("hash", json!(hash))),
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/block/height/{height}",
+    params(
+        ("height" = u32, Path, description = "Block height"),
+        ("v" = Option<u8>, Query, description = "Response shape: 1 (default) or 2; Accept: application/vnd.explorer.v2+json also selects 2"),
+    ),
+    responses(
+        (status = 200, description = "Block header fields", content(
+            ("application/json" = BlockResponse),
+            ("application/vnd.explorer.v2+json" = BlockResponseV2),
+        )),
+        (status = 304, description = "Not modified: If-None-Match holds the current ETag"),
+        (status = 400, description = "Height isn't a number", body = ErrorResponse),
+        (status = 404, description = "Block not found", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
 pub async fn get_block_by_height(
-    db: web::Data<Arc<Mutex<Connection>>>,
+    req: HttpRequest,
+    db: web::Data<DbPool>,
+    cache: web::Data<LookupCache>,
     height: web::Path<u32>,
-) -> impl Responder {
+) -> Result<HttpResponse, ApiError> {
     let height = height.into_inner();
-    let conn = db.lock().unwrap();
-    
-    match query_block_by_height(&conn, height) {
-        Ok(Some(block)) => HttpResponse::Ok().json(block),
-        Ok(None) => HttpResponse::NotFound().json(json!({
-            "error": "Block not found",
-            "height": height
-        })),
-        Err(e) => HttpResponse::InternalServerError().json(json!({
-            "error": "Database error",
-            "message": e.to_string()
+    let shape = requested_shape(&req)?;
+    let conn = get_conn(&db)?;
+
+    let block = match query_block_hash_at_height(&conn, height)? {
+        Some(hash) => cache.block(&conn, &hash)?,
+        None => None,
+    };
+    match block {
+        // The hash identifies the block even here: a reorg that replaces it changes the tag
+        Some(block) => Ok(with_etag(&req, shape.tag(block.hash.clone()), || {
+            shape.ok(shape.json::<_, BlockResponseV2>(block))
         })),
+        None => Err(block_not_found("height", json!(height))),
+    }
+}
+
+// Summary of the stored block after (`next`) or before the block with `hash`
+fn adjacent_block_response(db: &DbPool, hash: &str, next: bool) -> Result<HttpResponse, ApiError> {
+    validate_hash(hash, "hash")?;
+    let conn = get_conn(db)?;
+
+    let adjacent = match query_block_height(&conn, hash)? {
+        Some(height) if next => query_next_block(&conn, height)?,
+        Some(height) => query_prev_block(&conn, height)?,
+        None => return Err(block_not_found("hash", json!(hash))),
+    };
+    match adjacent {
+        Some(block) => Ok(HttpResponse::Ok().json(block)),
+        None if next => Err(ApiError::not_found("no_next_block", "Block is the newest one indexed")
+            .with_details(json!({ "hash": hash }))),
+        None => Err(ApiError::not_found("no_previous_block", "Block is the oldest one indexed")
+            .with_details(json!({ "hash": hash }))),
     }
 }
 
+// GET /block/{hash}/next - The block after this one
+#[utoipa::path(
+    get,
+    path = "/block/{hash}/next",
+    params(("hash" = String, Path, description = "Block hash")),
+    responses(
+        (status = 200, description = "The next stored block, skipping any gap in heights", body = BlockSummary),
+        (status = 400, description = "Malformed block hash", body = ErrorResponse),
+        (status = 404, description = "Block not found, or it's the tip", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_next_block(
+    db: web::Data<DbPool>,
+    hash: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    adjacent_block_response(&db, &hash, true)
+}
+
+// GET /block/{hash}/prev - The block before this one
+#[utoipa::path(
+    get,
+    path = "/block/{hash}/prev",
+    params(("hash" = String, Path, description = "Block hash")),
+    responses(
+        (status = 200, description = "The previous stored block, skipping any gap in heights", body = BlockSummary),
+        (status = 400, description = "Malformed block hash", body = ErrorResponse),
+        (status = 404, description = "Block not found, or it's the oldest stored block", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_prev_block(
+    db: web::Data<DbPool>,
+    hash: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    adjacent_block_response(&db, &hash, false)
+}
+
+// Keep one page of a transaction's inputs or outputs, returning the pagination metadata
+fn paginate<T>(items: &mut Vec<T>, page: usize, page_size: usize) -> Pagination {
+    let pagination = Pagination::new(page, page_size, items.len());
+    *items = items.drain(..).skip(page_offset(page, page_size)).take(page_size).collect();
+    pagination
+}
+
+// Fill in each input's prevout for ?prevout=true. The spent outputs were already looked up
+// with the transaction, in one query for all of its inputs.
+fn add_prevouts(tx: &mut TxResponse) {
+    for input in tx.inputs.iter_mut().filter(|input| !input.coinbase) {
+        input.prevout = Some(TxPrevout {
+            value: input.value,
+            script_type: input.prev_script_type.clone(),
+            address: input.prev_address.clone(),
+        });
+    }
+}
+
+// GET /tx/{txid}[?inputs_page=1&outputs_page=1&page_size=100][&prevout=true]
+// Inputs and outputs are only paginated when one of the parameters is given
+#[utoipa::path(
+    get,
+    path = "/tx/{txid}",
+    params(
+        ("txid" = String, Path, description = "Transaction id"),
+        ("inputs_page" = Option<usize>, Query, description = "Page of inputs to return; enables pagination"),
+        ("outputs_page" = Option<usize>, Query, description = "Page of outputs to return; enables pagination"),
+        ("page_size" = Option<usize>, Query, description = "Inputs/outputs per page (default 100, at most --max-page-size); enables pagination"),
+        ("prevout" = Option<bool>, Query, description = "Include the output each input spends (value, script type, address); v2 always does"),
+        ("v" = Option<u8>, Query, description = "Response shape: 1 (default) or 2; Accept: application/vnd.explorer.v2+json also selects 2"),
+    ),
+    responses(
+        (status = 200, description = "Decoded transaction", content(
+            ("application/json" = TxResponse),
+            ("application/vnd.explorer.v2+json" = TxResponseV2),
+        )),
+        (status = 304, description = "Not modified: If-None-Match holds the current ETag"),
+        (status = 400, description = "Malformed txid", body = ErrorResponse),
+        (status = 404, description = "Transaction not found", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
 pub async fn get_tx(
-    db: web::Data<Arc<Mutex<Connection>>>,
+    req: HttpRequest,
+    db: web::Data<DbPool>,
+    cache: web::Data<LookupCache>,
     txid: web::Path<String>,
-) -> impl Responder {
+    query: web::Query<HashMap<String, String>>,
+    api: web::Data<ApiConfig>,
+) -> Result<HttpResponse, ApiError> {
     let txid = txid.into_inner();
-    let conn = db.lock().unwrap();
+    validate_hash(&txid, "txid")?;
+    let shape = requested_shape(&req)?;
+    let conn = get_conn(&db)?;
+
+    let paginated = ["inputs_page", "outputs_page", "page_size"].iter().any(|key| query.contains_key(*key));
+    let prevout = shape == Shape::V1 && matches!(query.get("prevout").map(String::as_str), Some("true" | "1"));
+    let tag = |tag: String| shape.tag(if prevout { format!("{}-prevout", tag) } else { tag });
     
-    match query_tx(&conn, &txid) {
-        Ok(Some(tx)) => HttpResponse::Ok().json(tx),
-        Ok(None) => HttpResponse::NotFound().json(json!({
-            "error": "Transaction not found",
-            "txid": txid
-        })),
-        Err(e) => HttpResponse::InternalServerError().json(json!({
-            "error": "Database error",
-            "message": e.to_string()
-        })),
+    // Confirmations and spent outputs move with the tip, so it's part of the tag
+    let tip = query_latest_block(&conn)?.unwrap_or_default().1;
+    let Some(mut tx) = cache.tx(&conn, &txid)? else {
+        return Err(tx_not_found(&txid));
+    };
+    if prevout {
+        add_prevouts(&mut tx);
+    }
+    if paginated {
+        let page_size = page_size(&query, "page_size", 100, &api);
+        let (inputs_page, outputs_page) = (page_number(&query, "inputs_page"), page_number(&query, "outputs_page"));
+        let tag = tag(format!("{}-{}-{}-{}-{}", txid, tip, inputs_page, outputs_page, page_size));
+        Ok(with_etag(&req, tag, || {
+            tx.inputs_pagination = Some(paginate(&mut tx.inputs, inputs_page, page_size));
+            tx.outputs_pagination = Some(paginate(&mut tx.outputs, outputs_page, page_size));
+            shape.ok(shape.json::<_, TxResponseV2>(tx))
+        }))
+    } else {
+        Ok(with_etag(&req, tag(format!("{}-{}", txid, tip)), || {
+            shape.ok(shape.json::<_, TxResponseV2>(tx))
+        }))
     }
 }
 
-pub async fn get_latest_blocks(
-    db: web::Data<Arc<Mutex<Connection>>>,
+// 409 for a block or transaction stored without its raw bytes (index --no-raw). `field` and
+// `value` identify it as in a 404.
+fn raw_data_not_stored(field: &str, value: serde_json::Value) -> ApiError {
+    ApiError::conflict(
+        "raw_data_not_stored",
+        "The database was indexed with --no-raw, so raw blocks and transactions aren't available",
+    ).with_details(json!({ field: value }))
+}
+
+// 404 for a block, identified by `field` ("hash" or "height") and `value`
+fn block_not_found(field: &str, value: serde_json::Value) -> ApiError {
+    ApiError::not_found("block_not_found", "Block not found").with_details(json!({ field: value }))
+}
+
+fn tx_not_found(txid: &str) -> ApiError {
+    ApiError::not_found("transaction_not_found", "Transaction not found").with_details(json!({ "txid": txid }))
+}
+
+// `response`, tagged with a strong ETag, or a bodyless 304 if the client's If-None-Match
+// already holds that tag. The tag must change whenever the representation does: a hash for
+// immutable data, plus the tip for anything derived from it.
+fn with_etag(req: &HttpRequest, tag: String, response: impl FnOnce() -> HttpResponse) -> HttpResponse {
+    let etag = EntityTag::new_strong(tag);
+    let fresh = match header::IfNoneMatch::parse(req) {
+        Ok(header::IfNoneMatch::Any) => true,
+        Ok(header::IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        Err(_) => false,
+    };
+    if fresh {
+        return HttpResponse::NotModified().insert_header(header::ETag(etag)).finish();
+    }
+    let mut response = response();
+    if response.status().is_success()
+        && let Ok(value) = etag.to_string().parse()
+    {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    response
+}
+
+// Which JSON shape of a block or transaction the client asked for: ?v=1|2, or the v2 media
+// type in Accept. v1, the original flat shape, is the default.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Shape {
+    V1,
+    V2,
+}
+
+const V2_MEDIA_TYPE: &str = "application/vnd.explorer.v2+json";
+
+fn requested_shape(req: &HttpRequest) -> Result<Shape, ApiError> {
+    let query = web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .map(web::Query::into_inner)
+        .unwrap_or_default();
+    match query.get("v").map(String::as_str) {
+        Some("1") => Ok(Shape::V1),
+        Some("2") => Ok(Shape::V2),
+        Some(v) => Err(ApiError::invalid("invalid_parameter", "v must be 1 or 2").with_details(json!({ "v": v }))),
+        None => {
+            let accept = req.headers().get(header::ACCEPT).and_then(|accept| accept.to_str().ok());
+            Ok(match accept {
+                Some(accept) if accept.contains(V2_MEDIA_TYPE) => Shape::V2,
+                _ => Shape::V1,
+            })
+        }
+    }
+}
+
+impl Shape {
+    // ETags differ between shapes of the same resource
+    fn tag(self, tag: String) -> String {
+        match self {
+            Shape::V1 => tag,
+            Shape::V2 => format!("{}-v2", tag),
+        }
+    }
+
+    // `value` in this shape. V is its v2 form.
+    fn json<T: serde::Serialize, V: serde::Serialize + From<T>>(self, value: T) -> serde_json::Value {
+        match self {
+            Shape::V1 => json!(value),
+            Shape::V2 => json!(V::from(value)),
+        }
+    }
+
+    // A 200 with `body`, which was built in this shape. Accept can pick the shape, so caches
+    // must key on it.
+    fn ok(self, body: impl Serialize) -> HttpResponse {
+        let mut response = HttpResponse::Ok();
+        response.insert_header((header::VARY, "Accept"));
+        if self == Shape::V2 {
+            response.content_type(V2_MEDIA_TYPE);
+        }
+        response.json(body)
+    }
+}
+
+// Raw block as hex text, or as bytes with ?format=bin
+fn raw_block_response(req: &HttpRequest, hash: &str, raw_data: Vec<u8>, query: &HashMap<String, String>) -> HttpResponse {
+    match query.get("format").map(String::as_str) {
+        Some("bin") => with_etag(req, format!("{}-bin", hash), || HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .body(raw_data)),
+        _ => with_etag(req, hash.to_string(), || HttpResponse::Ok()
+            .content_type("text/plain")
+            .body(hex::encode(raw_data))),
+    }
+}
+
+// GET /block/{hash}/filter - BIP158 basic filter, for BIP157 (neutrino) light clients
+#[utoipa::path(
+    get,
+    path = "/block/{hash}/filter",
+    params(("hash" = String, Path, description = "Block hash")),
+    responses(
+        (status = 200, description = "Serialized basic filter as hex", body = String, content_type = "text/plain"),
+        (status = 304, description = "Not modified: If-None-Match holds the current ETag"),
+        (status = 400, description = "Malformed block hash", body = ErrorResponse),
+        (status = 404, description = "Block not found", body = ErrorResponse),
+        (status = 409, description = "An output the block spends isn't indexed", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_block_filter(
+    req: HttpRequest,
+    db: web::Data<DbPool>,
+    hash: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let hash = hash.into_inner();
+    validate_hash(&hash, "hash")?;
+    let conn = get_conn(&db)?;
+
+    match query_block_filter(&conn, &hash)? {
+        Some(Some(filter)) => Ok(with_etag(&req, hash.clone(), || HttpResponse::Ok()
+            .content_type("text/plain")
+            .body(hex::encode(filter)))),
+        Some(None) => Err(ApiError::conflict(
+            "filter_not_available",
+            "The filter covers the outputs the block spends, and not all of them are indexed (e.g. after index --start-height), or the block was stored with --no-raw before filters were added",
+        ).with_details(json!({ "hash": hash }))),
+        None => Err(block_not_found("hash", json!(hash))),
+    }
+}
+
+// GET /block/{hash}/raw[?format=bin] - Raw serialized block
+#[utoipa::path(
+    get,
+    path = "/block/{hash}/raw",
+    params(
+        ("hash" = String, Path, description = "Block hash"),
+        ("format" = Option<String>, Query, description = "bin for raw bytes, hex text otherwise"),
+    ),
+    responses(
+        (status = 200, description = "Serialized block as hex, or its bytes with format=bin", content(
+            ("text/plain" = String),
+            ("application/octet-stream" = Vec<u8>),
+        )),
+        (status = 304, description = "Not modified: If-None-Match holds the current ETag"),
+        (status = 400, description = "Malformed block hash", body = ErrorResponse),
+        (status = 404, description = "Block not found", body = ErrorResponse),
+        (status = 409, description = "Indexed with --no-raw", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_block_raw(
+    req: HttpRequest,
+    db: web::Data<DbPool>,
+    hash: web::Path<String>,
     query: web::Query<HashMap<String, String>>,
-) -> impl Responder {
-    let limit: usize = query.get("limit")
+) -> Result<HttpResponse, ApiError> {
+    let hash = hash.into_inner();
+    validate_hash(&hash, "hash")?;
+    let conn = get_conn(&db)?;
+
+    match query_block_raw(&conn, &hash)? {
+        Some(Some(raw_data)) => Ok(raw_block_response(&req, &hash, raw_data, &query)),
+        Some(None) => Err(raw_data_not_stored("hash", json!(hash))),
+        None => Err(block_not_found("hash", json!(hash))),
+    }
+}
+
+// GET /block/height/{height}/raw[?format=bin] - Raw serialized block at a height
+#[utoipa::path(
+    get,
+    path = "/block/height/{height}/raw",
+    params(
+        ("height" = u32, Path, description = "Block height"),
+        ("format" = Option<String>, Query, description = "bin for raw bytes, hex text otherwise"),
+    ),
+    responses(
+        (status = 200, description = "Serialized block as hex, or its bytes with format=bin", content(
+            ("text/plain" = String),
+            ("application/octet-stream" = Vec<u8>),
+        )),
+        (status = 304, description = "Not modified: If-None-Match holds the current ETag"),
+        (status = 400, description = "Height isn't a number", body = ErrorResponse),
+        (status = 404, description = "Block not found", body = ErrorResponse),
+        (status = 409, description = "Indexed with --no-raw", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_block_raw_by_height(
+    req: HttpRequest,
+    db: web::Data<DbPool>,
+    height: web::Path<u32>,
+    query: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let height = height.into_inner();
+    let conn = get_conn(&db)?;
+
+    let Some(hash) = query_block_hash_at_height(&conn, height)? else {
+        return Err(block_not_found("height", json!(height)));
+    };
+    match query_block_raw(&conn, &hash)? {
+        Some(Some(raw_data)) => Ok(raw_block_response(&req, &hash, raw_data, &query)),
+        Some(None) => Err(raw_data_not_stored("height", json!(height))),
+        None => Err(block_not_found("height", json!(height))),
+    }
+}
+
+// ?page= (from 1) and ?limit= (default 20), with the limit kept between 1 and --max-page-size.
+// The clamped limit is what gets reported as per_page.
+fn page_params(query: &HashMap<String, String>, api: &ApiConfig) -> (usize, usize) {
+    (page_number(query, "page"), page_size(query, "limit", 20, api))
+}
+
+// A page number from `key`, from 1
+fn page_number(query: &HashMap<String, String>, key: &str) -> usize {
+    query.get(key)
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(1)
+        .max(1)
+}
+
+// Items per page from `key`, or `default`, kept between 1 and --max-page-size
+fn page_size(query: &HashMap<String, String>, key: &str, default: usize, api: &ApiConfig) -> usize {
+    query.get(key)
         .and_then(|l| l.parse().ok())
-        .unwrap_or(10);
-    let conn = db.lock().unwrap();
-    
-    match query_latest_blocks(&conn, limit) {
-        Ok(blocks) => {
-            let total_count = blocks.len() as u32;  // Calculate BEFORE moving
-            HttpResponse::Ok().json(LatestBlocksResponse {
-                blocks,  // Now move
-                total_count,
-            })
+        .unwrap_or(default)
+        .clamp(1, api.max_page_size)
+}
+
+// Rows to skip before `page`. A huge ?page= saturates instead of overflowing, and stays
+// within what SQLite takes as an OFFSET, so it just reads past the end.
+fn page_offset(page: usize, limit: usize) -> usize {
+    (page - 1).saturating_mul(limit).min(i64::MAX as usize)
+}
+
+// An ?after= or ?before= bound on block timestamps: unix seconds, or a UTC date (YYYY-MM-DD)
+// standing for its first second, or with `end_of_day` its last, so a date bound includes the
+// whole day
+fn parse_time_bound(value: &str, end_of_day: bool) -> Result<u32, String> {
+    if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+        return value.parse().map_err(|_| format!("{} is out of range", value));
+    }
+    let invalid = || format!("'{}' is neither a unix timestamp nor a date like 2024-01-31", value);
+    let fields: Vec<u64> = value.split('-')
+        .map(|field| field.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let [year, month, day] = fields[..] else { return Err(invalid()) };
+    let is_leap = |year: u64| year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    let month_days = [31, if is_leap(year) { 29 } else { 28 }, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+    if !(1970..=2106).contains(&year) || !(1..=12).contains(&month) || day < 1 || day > month_days[month as usize - 1] {
+        return Err(invalid());
+    }
+    let days = (1970..year).map(|year| if is_leap(year) { 366 } else { 365 }).sum::<u64>()
+        + month_days[..month as usize - 1].iter().sum::<u64>()
+        + day - 1;
+    let start = days * 86_400;
+    let bound = if end_of_day { start + 86_399 } else { start };
+    u32::try_from(bound).map_err(|_| format!("{} is out of range", value))
+}
+
+// ?cursor= tokens for cursor-paged endpoints: base64url JSON, so clients can treat them as
+// opaque strings
+fn encode_cursor(cursor: &HistoryCursor) -> String {
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(cursor).expect("cursor serializes"))
+}
+
+fn decode_cursor(token: &str) -> Option<HistoryCursor> {
+    let json = URL_SAFE_NO_PAD.decode(token).ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+// One page of a block's txids, in block order
+fn block_txids_response(
+    conn: &rusqlite::Connection,
+    hash: &str,
+    query: &HashMap<String, String>,
+    api: &ApiConfig,
+) -> Result<HttpResponse, ApiError> {
+    let (page, limit) = page_params(query, api);
+
+    let Some(txids) = query_block_txids(conn, hash)? else {
+        return Err(block_not_found("hash", json!(hash)));
+    };
+    let total = txids.len();
+    let txids: Vec<String> = txids.into_iter().skip(page_offset(page, limit)).take(limit).collect();
+    Ok(HttpResponse::Ok().json(PaginatedResponse::new(json!({
+        "hash": hash,
+        "txids": txids,
+    }), page, limit, total)))
+}
+
+// GET /block/{hash}/txids?page=1&limit=20 - Txids of a block in block order
+#[utoipa::path(
+    get,
+    path = "/block/{hash}/txids",
+    params(
+        ("hash" = String, Path, description = "Block hash"),
+        ("page" = Option<usize>, Query, description = "Page number, from 1"),
+        ("limit" = Option<usize>, Query, description = "Items per page (default 20, at most --max-page-size)"),
+    ),
+    responses(
+        (status = 200, description = "One page of txids in block order", body = BlockTxidsPage),
+        (status = 400, description = "Malformed block hash", body = ErrorResponse),
+        (status = 404, description = "Block not found", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_block_txids(
+    db: web::Data<DbPool>,
+    hash: web::Path<String>,
+    query: web::Query<HashMap<String, String>>,
+    api: web::Data<ApiConfig>,
+) -> Result<HttpResponse, ApiError> {
+    validate_hash(&hash, "hash")?;
+    let conn = get_conn(&db)?;
+    block_txids_response(&conn, &hash, &query, &api)
+}
+
+// GET /block/height/{height}/txids?page=1&limit=20 - Txids of the block at a height
+#[utoipa::path(
+    get,
+    path = "/block/height/{height}/txids",
+    params(
+        ("height" = u32, Path, description = "Block height"),
+        ("page" = Option<usize>, Query, description = "Page number, from 1"),
+        ("limit" = Option<usize>, Query, description = "Items per page (default 20, at most --max-page-size)"),
+    ),
+    responses(
+        (status = 200, description = "One page of txids in block order", body = BlockTxidsPage),
+        (status = 400, description = "Height isn't a number", body = ErrorResponse),
+        (status = 404, description = "Block not found", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_block_txids_by_height(
+    db: web::Data<DbPool>,
+    height: web::Path<u32>,
+    query: web::Query<HashMap<String, String>>,
+    api: web::Data<ApiConfig>,
+) -> Result<HttpResponse, ApiError> {
+    let height = height.into_inner();
+    let conn = get_conn(&db)?;
+
+    match query_block_hash_at_height(&conn, height)? {
+        Some(hash) => block_txids_response(&conn, &hash, &query, &api),
+        None => Err(block_not_found("height", json!(height))),
+    }
+}
+
+// GET /block/{hash}/transactions?page=1&limit=20 - Full details of a block's transactions
+#[utoipa::path(
+    get,
+    path = "/block/{hash}/transactions",
+    params(
+        ("hash" = String, Path, description = "Block hash"),
+        ("page" = Option<usize>, Query, description = "Page number, from 1"),
+        ("limit" = Option<usize>, Query, description = "Items per page (default 20, at most --max-page-size)"),
+        ("v" = Option<u8>, Query, description = "Response shape: 1 (default) or 2; Accept: application/vnd.explorer.v2+json also selects 2"),
+    ),
+    responses(
+        (status = 200, description = "One page of transactions in block order", content(
+            ("application/json" = BlockTransactionsPage),
+            ("application/vnd.explorer.v2+json" = BlockTransactionsPageV2),
+        )),
+        (status = 400, description = "Malformed block hash", body = ErrorResponse),
+        (status = 404, description = "Block not found", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_block_transactions(
+    req: HttpRequest,
+    db: web::Data<DbPool>,
+    hash: web::Path<String>,
+    query: web::Query<HashMap<String, String>>,
+    api: web::Data<ApiConfig>,
+) -> Result<HttpResponse, ApiError> {
+    validate_hash(&hash, "hash")?;
+    let shape = requested_shape(&req)?;
+    let conn = get_conn(&db)?;
+
+    let (page, limit) = page_params(&query, &api);
+
+    let Some((total, transactions)) = query_block_transactions(&conn, &hash, limit, page_offset(page, limit))? else {
+        return Err(block_not_found("hash", json!(hash.as_str())));
+    };
+    let transactions: Vec<serde_json::Value> = transactions.into_iter()
+        .map(|tx| shape.json::<_, TxResponseV2>(tx))
+        .collect();
+    Ok(shape.ok(PaginatedResponse::new(json!({
+        "hash": hash.as_str(),
+        "transactions": transactions,
+    }), page, limit, total)))
+}
+
+// GET /block/height/{height}/tx/{index} - The transaction at a position in a block
+#[utoipa::path(
+    get,
+    path = "/block/height/{height}/tx/{index}",
+    params(
+        ("height" = u32, Path, description = "Block height"),
+        ("index" = usize, Path, description = "Position of the transaction in the block; 0 is the coinbase"),
+        ("v" = Option<u8>, Query, description = "Response shape: 1 (default) or 2; Accept: application/vnd.explorer.v2+json also selects 2"),
+    ),
+    responses(
+        (status = 200, description = "Decoded transaction", content(
+            ("application/json" = TxResponse),
+            ("application/vnd.explorer.v2+json" = TxResponseV2),
+        )),
+        (status = 400, description = "Height or index isn't a number", body = ErrorResponse),
+        (status = 404, description = "Block not found, or it has no transaction at that index", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_block_tx_at_index(
+    req: HttpRequest,
+    db: web::Data<DbPool>,
+    cache: web::Data<LookupCache>,
+    path: web::Path<(u32, usize)>,
+) -> Result<HttpResponse, ApiError> {
+    let (height, index) = path.into_inner();
+    let shape = requested_shape(&req)?;
+    let conn = get_conn(&db)?;
+
+    let Some(hash) = query_block_hash_at_height(&conn, height)? else {
+        return Err(block_not_found("height", json!(height)));
+    };
+
+    let tx = match query_txid_at_index(&conn, &hash, index)? {
+        Some(txid) => cache.tx(&conn, &txid)?,
+        None => None,
+    };
+    match tx {
+        Some(tx) => Ok(shape.ok(shape.json::<_, TxResponseV2>(tx))),
+        None => Err(ApiError::not_found(
+            "transaction_not_found",
+            format!("Block {} at height {} has no transaction at index {}", hash, height, index),
+        ).with_details(json!({ "height": height, "index": index }))),
+    }
+}
+
+// GET /tx/{txid}/raw - Hex-encoded raw transaction, like `getrawtransaction`
+#[utoipa::path(
+    get,
+    path = "/tx/{txid}/raw",
+    params(("txid" = String, Path, description = "Transaction id")),
+    responses(
+        (status = 200, description = "Serialized transaction as hex", body = String, content_type = "text/plain"),
+        (status = 304, description = "Not modified: If-None-Match holds the current ETag"),
+        (status = 400, description = "Malformed txid", body = ErrorResponse),
+        (status = 404, description = "Transaction not found", body = ErrorResponse),
+        (status = 409, description = "Indexed with --no-raw", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_tx_raw(
+    req: HttpRequest,
+    db: web::Data<DbPool>,
+    txid: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let txid = txid.into_inner();
+    validate_hash(&txid, "txid")?;
+    let conn = get_conn(&db)?;
+
+    match query_tx_raw(&conn, &txid)? {
+        Some(Some(raw_data)) => Ok(with_etag(&req, txid.clone(), || HttpResponse::Ok()
+            .content_type("text/plain")
+            .body(hex::encode(raw_data)))),
+        Some(None) => Err(raw_data_not_stored("txid", json!(txid))),
+        None => Err(tx_not_found(&txid)),
+    }
+}
+
+// GET /tx/{txid}/hex - The same as /tx/{txid}/raw, under the name other explorers use
+#[utoipa::path(
+    get,
+    path = "/tx/{txid}/hex",
+    params(("txid" = String, Path, description = "Transaction id")),
+    responses(
+        (status = 200, description = "Serialized transaction as hex", body = String, content_type = "text/plain"),
+        (status = 304, description = "Not modified: If-None-Match holds the current ETag"),
+        (status = 400, description = "Malformed txid", body = ErrorResponse),
+        (status = 404, description = "Transaction not found", body = ErrorResponse),
+        (status = 409, description = "Indexed with --no-raw", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_tx_hex(
+    req: HttpRequest,
+    db: web::Data<DbPool>,
+    txid: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    get_tx_raw(req, db, txid).await
+}
+
+// A POST /tx body: one transaction as hex, surrounding whitespace allowed. It's decoded here
+// so malformed input is rejected without a round trip to the node.
+fn parse_raw_tx(body: &[u8]) -> Result<(String, bitcoin::Transaction), ApiError> {
+    let hex = std::str::from_utf8(body).unwrap_or_default().trim();
+    let bytes = hex::decode(hex)
+        .map_err(|e| ApiError::invalid("invalid_hex", e.to_string()))?;
+    let tx = bitcoin::consensus::deserialize(&bytes)
+        .map_err(|e| ApiError::invalid("invalid_transaction", e.to_string()))?;
+    Ok((hex.to_string(), tx))
+}
+
+// POST /tx - Broadcast a raw transaction through the node given to serve --rpc-url
+#[utoipa::path(
+    post,
+    path = "/tx",
+    request_body(content = String, description = "Raw transaction as hex", content_type = "text/plain"),
+    responses(
+        (status = 200, description = "Accepted by the node", body = BroadcastResult),
+        (status = 400, description = "Body isn't a hex-encoded transaction", body = ErrorResponse),
+        (status = 422, description = "Rejected by the node; code and message are the node's", body = ErrorResponse),
+        (status = 501, description = "Broadcasting isn't enabled (no --rpc-url)", body = ErrorResponse),
+        (status = 502, description = "Node unreachable", body = ErrorResponse),
+    )
+)]
+pub async fn post_tx(
+    rpc: Option<web::Data<BitcoinRpcClient>>,
+    body: Bytes,
+) -> Result<HttpResponse, ApiError> {
+    let (hex, tx) = parse_raw_tx(&body)?;
+    let Some(rpc) = rpc else {
+        return Err(ApiError::new(
+            StatusCode::NOT_IMPLEMENTED,
+            "broadcast_not_enabled",
+            "Start the server with --rpc-url to relay transactions to a node",
+        ));
+    };
+
+    match rpc.send_raw_transaction(&hex).await {
+        Ok(txid) => Ok(HttpResponse::Ok().json(BroadcastResult { txid })),
+        // The node's error object, e.g. {"code": -26, "message": "min relay fee not met"}
+        Err(IndexError::RpcError(error)) => {
+            let error: serde_json::Value = serde_json::from_str(&error).unwrap_or(json!({"message": error}));
+            let message = error["message"].as_str().unwrap_or("Rejected by the node").to_string();
+            Err(ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "transaction_rejected", message)
+                .with_details(json!({ "txid": tx.compute_txid().to_string(), "rpc_code": error["code"] })))
         }
-        Err(e) => HttpResponse::InternalServerError().json(json!({
-            "error": "Database error",
-            "message": e.to_string()
-        })),
+        Err(e) => Err(ApiError::new(StatusCode::BAD_GATEWAY, "node_unavailable", e.to_string())),
     }
 }
+
+// GET /tx/{txid}/decode - Fully decoded transaction, like `decoderawtransaction`
+#[utoipa::path(
+    get,
+    path = "/tx/{txid}/decode",
+    params(("txid" = String, Path, description = "Transaction id")),
+    responses(
+        (status = 200, description = "Decoded transaction with disassembled scripts", body = DecodedTx),
+        (status = 400, description = "Malformed txid", body = ErrorResponse),
+        (status = 404, description = "Transaction not found", body = ErrorResponse),
+        (status = 409, description = "Indexed with --no-raw", body = ErrorResponse),
+        (status = 500, description = "Database error, or the stored transaction doesn't decode", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_tx_decode(
+    db: web::Data<DbPool>,
+    chain: web::Data<ChainConfig>,
+    txid: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let txid = txid.into_inner();
+    validate_hash(&txid, "txid")?;
+    let conn = get_conn(&db)?;
+
+    let raw_data = match query_tx_raw(&conn, &txid)? {
+        Some(Some(raw_data)) => raw_data,
+        Some(None) => return Err(raw_data_not_stored("txid", json!(txid))),
+        None => return Err(tx_not_found(&txid)),
+    };
+
+    let tx = bitcoin::consensus::deserialize::<bitcoin::Transaction>(&raw_data)
+        .map_err(|e| ApiError::internal("corrupt_data", format!("Stored transaction doesn't decode: {}", e)))?;
+    Ok(HttpResponse::Ok().json(decode_transaction(&tx, chain.network)))
+}
+
+// GET /tx/{txid}/merkle-proof - Merkle branch proving a confirmed transaction is in its block
+#[utoipa::path(
+    get,
+    path = "/tx/{txid}/merkle-proof",
+    params(("txid" = String, Path, description = "Transaction id")),
+    responses(
+        (status = 200, description = "Merkle branch checked against the block's merkle root", body = MerkleProof),
+        (status = 400, description = "Malformed txid", body = ErrorResponse),
+        (status = 404, description = "Transaction not found or unconfirmed", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_tx_merkle_proof(
+    db: web::Data<DbPool>,
+    txid: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let txid = txid.into_inner();
+    validate_hash(&txid, "txid")?;
+    let conn = get_conn(&db)?;
+
+    let Some((block_hash, block_height, merkle_root, index)) = query_tx_location(&conn, &txid)? else {
+        return Err(ApiError::not_found("transaction_not_found", "Transaction not found or unconfirmed")
+            .with_details(json!({ "txid": txid })));
+    };
+
+    let txids: Vec<bitcoin::Txid> = query_block_txids(&conn, &block_hash)?.unwrap_or_default().iter()
+        .map(|txid| txid.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|e: bitcoin::hex::HexToArrayError| ApiError::internal("corrupt_data", e.to_string()))?;
+    if index >= txids.len() {
+        return Err(ApiError::internal(
+            "corrupt_data",
+            format!("transaction index {} is outside block {}", index, block_hash),
+        ));
+    }
+
+    // Only hand out proofs that lead back to the root in the stored header
+    let branch = merkle_branch(&txids, index);
+    let computed_root = merkle_root_from_branch(txids[index], index, &branch);
+    if computed_root.to_string() != merkle_root {
+        return Err(ApiError::internal("merkle_proof_mismatch", format!(
+            "stored transactions of block {} hash to {}, not its merkle root {}; run verify",
+            block_hash, computed_root, merkle_root
+        )));
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "txid": txid,
+        "block_hash": block_hash,
+        "block_height": block_height,
+        "merkle_root": merkle_root,
+        "pos": index,
+        "merkle": branch.iter().map(|node| node.to_string()).collect::<Vec<_>>()
+    })))
+}
+
+// GET /tip - Just the best block, a cheap target for clients polling for new blocks
+#[utoipa::path(
+    get,
+    path = "/tip",
+    responses(
+        (status = 200, description = "Height, hash and timestamp of the best stored block", body = ChainTip),
+        (status = 404, description = "No blocks indexed yet", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_tip(db: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let conn = get_conn(&db)?;
+
+    match query_tip(&conn)? {
+        Some(tip) => Ok(HttpResponse::Ok().json(tip)),
+        None => Err(ApiError::not_found(
+            "no_blocks_indexed",
+            "The chain tip is available once the first block has been indexed",
+        )),
+    }
+}
+
+// ?limit= for /blocks/latest: 10 by default, cut to --max-page-size like /blocks. 0 or a value
+// that isn't a number is a 400 rather than an empty or default page.
+fn latest_blocks_limit(query: &HashMap<String, String>, api: &ApiConfig) -> Result<usize, ApiError> {
+    match query.get("limit").map(|limit| limit.parse::<usize>()) {
+        None => Ok(10.min(api.max_page_size)),
+        Some(Ok(limit)) if limit > 0 => Ok(limit.min(api.max_page_size)),
+        Some(_) => Err(ApiError::invalid("invalid_parameter", "limit must be a whole number of at least 1")
+            .with_details(json!({ "limit": query.get("limit") }))),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/blocks/latest",
+    params(("limit" = Option<usize>, Query, description = "Number of blocks (default 10, at least 1, at most --max-page-size)")),
+    responses(
+        (status = 200, description = "Newest blocks first, as page 1 of /blocks sorted by height descending; pagination.per_page is the limit applied", body = BlocksPage),
+        (status = 400, description = "limit is 0 or not a number", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_latest_blocks(
+    db: web::Data<DbPool>,
+    query: web::Query<HashMap<String, String>>,
+    api: web::Data<ApiConfig>,
+) -> Result<HttpResponse, ApiError> {
+    let limit = latest_blocks_limit(&query, &api)?;
+    let conn = get_conn(&db)?;
+    
+    // Page 1 of /blocks in its default order
+    let blocks = crate::db::query_all_blocks(&conn, BlockSort::Height, SortOrder::Desc, limit, 0)?;
+    let total_blocks = query_block_count(&conn)?;
+    Ok(HttpResponse::Ok().json(PaginatedResponse::new(
+        BlockList { blocks, total_blocks: Some(total_blocks), limit: Some(limit) },
+        1, limit, total_blocks as usize,
+    )))
+}
 // GET /stats - Get blockchain statistics
+#[utoipa::path(
+    get,
+    path = "/stats",
+    responses(
+        (status = 200, description = "Chain statistics. Before any block is indexed, only total_blocks, total_transactions and a message are returned.", body = StatsResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
 pub async fn get_stats(
-    db: web::Data<Arc<Mutex<Connection>>>,
-) -> impl Responder {
-    let conn = db.lock().unwrap();
-    
-    let total_blocks = crate::db::query_block_count(&conn).unwrap_or(0);
-    let total_txs = crate::db::query_transaction_count(&conn).unwrap_or(0);
+    db: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = get_conn(&db)?;
     
-    let latest = crate::db::query_latest_block(&conn);
+    let stats = query_chain_stats(&conn)?;
     
-    match latest {
-        Ok(Some((height, hash))) => {
-            HttpResponse::Ok().json(StatsResponse {
-                total_blocks,
-                total_transactions: total_txs,
+    match crate::db::query_latest_block(&conn)? {
+        Some((height, hash)) => {
+            Ok(HttpResponse::Ok().json(StatsResponse {
+                total_blocks: stats.total_blocks,
+                total_transactions: stats.total_transactions,
                 latest_block_height: height,
                 latest_block_hash: hash,
-            })
+                total_fees: stats.total_fees,
+                avg_block_size: stats.avg_block_size,
+                avg_tx_per_block: stats.avg_tx_per_block,
+                chain_size_bytes: stats.chain_size_bytes,
+                segwit_tx_percent: stats.segwit_tx_percent,
+                taproot_tx_percent: stats.taproot_tx_percent,
+            }))
         }
-        _ => HttpResponse::Ok().json(serde_json::json!({
-            "total_blocks": total_blocks,
-            "total_transactions": total_txs,
+        None => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "total_blocks": stats.total_blocks,
+            "total_transactions": stats.total_transactions,
             "message": "No blocks indexed yet"
-        })),
+        }))),
     }
 }
 
+// GET /stats/daily?days=30 - Per-day activity for charts
+#[utoipa::path(
+    get,
+    path = "/stats/daily",
+    params(("days" = Option<u32>, Query, description = "Number of days (default 30, max 365)")),
+    responses(
+        (status = 200, description = "Per-day totals, oldest first", body = DailyStatsSeries),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_daily_stats(
+    db: web::Data<DbPool>,
+    query: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let days: u32 = query.get("days")
+        .and_then(|d| d.parse().ok())
+        .unwrap_or(30)
+        .clamp(1, 365);
+    let conn = get_conn(&db)?;
+
+    let series = query_daily_stats(&conn, days)?;
+    Ok(HttpResponse::Ok().json(json!({ "days": series })))
+}
+
+// Most recent blocks /fee-estimates looks back over; about a week of blocks
+pub const MAX_FEE_ESTIMATE_BLOCKS: u32 = 1008;
+
+// Median of rates sorted lowest first; the mean of the middle two for an even count
+fn median(sorted: &[f64]) -> Option<f64> {
+    let mid = sorted.len() / 2;
+    match sorted.len() {
+        0 => None,
+        len if len % 2 == 1 => Some(sorted[mid]),
+        _ => Some((sorted[mid - 1] + sorted[mid]) / 2.0),
+    }
+}
+
+// GET /fee-estimates?blocks=6 - Fee rates paid in the newest blocks
+#[utoipa::path(
+    get,
+    path = "/fee-estimates",
+    params(("blocks" = Option<u32>, Query, description = "Number of recent blocks to look at (default 6, max 1008)")),
+    responses(
+        (status = 200, description = "Min, median and max fee rate in sat/vB, coinbase transactions excluded", body = FeeEstimates),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_fee_estimates(
+    db: web::Data<DbPool>,
+    query: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let blocks: u32 = query.get("blocks")
+        .and_then(|b| b.parse().ok())
+        .unwrap_or(6)
+        .clamp(1, MAX_FEE_ESTIMATE_BLOCKS);
+    let conn = get_conn(&db)?;
+
+    let rates = query_recent_fee_rates(&conn, blocks)?;
+    Ok(HttpResponse::Ok().json(FeeEstimates {
+        blocks,
+        transactions: rates.len(),
+        min: rates.first().copied(),
+        median: median(&rates),
+        max: rates.last().copied(),
+    }))
+}
+
+// GET /metrics - Prometheus metrics
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Prometheus text format", body = String, content_type = "text/plain"),
+        (status = 500, description = "Metrics error", body = ErrorResponse),
+    )
+)]
+pub async fn get_metrics() -> Result<HttpResponse, ApiError> {
+    let body = crate::metrics::render()
+        .map_err(|e| ApiError::internal("metrics_error", e.to_string()))?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body))
+}
+
 // GET /health - Health check endpoint
+// GET /health/live (and /health) - The process is up and serving requests
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    responses(
+        (status = 200, description = "The process is serving requests (also at /health)", body = HealthResponse),
+    )
+)]
 pub async fn health_check() -> impl Responder {
     HttpResponse::Ok().json(serde_json::json!({
         "status": "healthy",
@@ -126,45 +1164,1002 @@ pub async fn health_check() -> impl Responder {
     }))
 }
 
+// GET /health/ready - The database is reachable; 503 otherwise
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    responses(
+        (status = 200, description = "The database is reachable", body = HealthResponse),
+        (status = 503, description = "The database is unreachable", body = ErrorResponse),
+    )
+)]
+pub async fn readiness_check(db: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let unavailable = |message: String| ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "database_unavailable", message);
+    let conn = db.get().map_err(|e| unavailable(e.to_string()))?;
+    conn.query_row("SELECT 1", [], |row| row.get::<_, i64>(0))
+        .map_err(|e| unavailable(e.to_string()))?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ready",
+        "service": "block-explorer-backend"
+    })))
+}
+
 // GET /blocks?page=1&limit=20 - Get all blocks with pagination
+#[utoipa::path(
+    get,
+    path = "/blocks",
+    params(
+        ("page" = Option<usize>, Query, description = "Page number, from 1"),
+        ("limit" = Option<usize>, Query, description = "Items per page (default 20, at most --max-page-size)"),
+        ("sort" = Option<String>, Query, description = "height, timestamp, tx_count or size (default height)"),
+        ("order" = Option<String>, Query, description = "asc or desc (default desc)"),
+        ("after" = Option<String>, Query, description = "Only blocks at or after this unix timestamp or UTC date (YYYY-MM-DD, from its start)"),
+        ("before" = Option<String>, Query, description = "Only blocks at or before this unix timestamp or UTC date (YYYY-MM-DD, to its end)"),
+    ),
+    responses(
+        (status = 200, description = "One page of blocks", body = BlocksPage),
+        (status = 400, description = "Unknown sort or order, or a bad after/before", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
 pub async fn get_all_blocks(
-    db: web::Data<Arc<Mutex<Connection>>>,
+    db: web::Data<DbPool>,
     query: web::Query<HashMap<String, String>>,
-) -> impl Responder {
-    let page: usize = query.get("page")
-        .and_then(|p| p.parse().ok())
-        .unwrap_or(1)
-        .max(1); // Minimum page 1
-    
-    let limit: usize = query.get("limit")
-        .and_then(|l| l.parse().ok())
-        .unwrap_or(20)
-        .min(100); // Max 100 blocks per page
+    api: web::Data<ApiConfig>,
+) -> Result<HttpResponse, ApiError> {
+    let (page, limit) = page_params(&query, &api);
     
-    let offset = (page - 1) * limit;
+    let offset = page_offset(page, limit);
+
+    let invalid = |name: &str, message: String| {
+        ApiError::invalid("invalid_parameter", message).with_details(json!({ name: query.get(name) }))
+    };
+    let sort: BlockSort = query.get("sort").map_or(Ok(BlockSort::Height), |s| s.parse())
+        .map_err(|message| invalid("sort", message))?;
+    let order: SortOrder = query.get("order").map_or(Ok(SortOrder::Desc), |o| o.parse())
+        .map_err(|message| invalid("order", message))?;
     
-    let conn = db.lock().unwrap();
+    let [after, before] = [("after", false), ("before", true)].map(|(name, end_of_day)| {
+        query.get(name).map(|value| parse_time_bound(value, end_of_day).map_err(|message| invalid(name, message))).transpose()
+    });
+    let (after, before) = (after?, before?);
+    // Unfiltered pages keep using the running block count instead of a COUNT(*)
+    let filter = (after.is_some() || before.is_some()).then(|| (after.unwrap_or(0), before.unwrap_or(u32::MAX)));
+    if let Some((after, before)) = filter
+        && after > before
+    {
+        return Err(ApiError::invalid("invalid_range", "after must not be later than before")
+            .with_details(json!({ "after": after, "before": before })));
+    }
+
+    let conn = get_conn(&db)?;
     
-    match crate::db::query_all_blocks(&conn, limit, offset) {
-        Ok(blocks) => {
-            let total = crate::db::query_block_count(&conn).unwrap_or(0);
-            let total_pages = (total as f64 / limit as f64).ceil() as usize;
-            
-            HttpResponse::Ok().json(serde_json::json!({
-                "blocks": blocks,
-                "pagination": {
-                    "current_page": page,
-                    "per_page": limit,
-                    "total_blocks": total,
-                    "total_pages": total_pages,
-                    "has_next": page < total_pages,
-                    "has_prev": page > 1
+    let blocks = match filter {
+        Some((after, before)) => crate::db::query_blocks_filtered(&conn, after, before, sort, order, limit, offset),
+        None => crate::db::query_all_blocks(&conn, sort, order, limit, offset),
+    }?;
+    let total = match filter {
+        Some((after, before)) => crate::db::query_block_count_filtered(&conn, after, before),
+        None => crate::db::query_block_count(&conn),
+    }?;
+    Ok(HttpResponse::Ok().json(PaginatedResponse::new(
+        BlockList { blocks, total_blocks: None, limit: None },
+        page, limit, total as usize,
+    )))
+}
+
+// GET /op_returns?page=1&limit=20 - OP_RETURN payloads, newest first
+#[utoipa::path(
+    get,
+    path = "/op_returns",
+    params(
+        ("page" = Option<usize>, Query, description = "Page number, from 1"),
+        ("limit" = Option<usize>, Query, description = "Items per page (default 20, at most --max-page-size)"),
+    ),
+    responses(
+        (status = 200, description = "One page of OP_RETURN payloads, newest first", body = OpReturnsPage),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_op_returns(
+    db: web::Data<DbPool>,
+    query: web::Query<HashMap<String, String>>,
+    api: web::Data<ApiConfig>,
+) -> Result<HttpResponse, ApiError> {
+    let (page, limit) = page_params(&query, &api);
+    let offset = page_offset(page, limit);
+
+    let conn = get_conn(&db)?;
+
+    let op_returns = query_op_returns(&conn, limit, offset)?;
+    let total = query_op_return_count(&conn)?;
+
+    Ok(HttpResponse::Ok().json(PaginatedResponse::new(json!({
+        "op_returns": op_returns,
+    }), page, limit, total as usize)))
+}
+
+// Resolve a search box query: digits are a height, 64 hex chars a block hash or txid,
+// anything else is tried as an address
+fn search(conn: &rusqlite::Connection, chain: &ChainConfig, query: &str) -> rusqlite::Result<serde_json::Value> {
+    if !query.is_empty() && query.bytes().all(|b| b.is_ascii_digit()) {
+        if let Ok(height) = query.parse::<u32>()
+            && let Some(block) = query_block_by_height(conn, height)?
+        {
+            return Ok(json!({ "type": "block", "block": block }));
+        }
+    } else if query.len() == 64 && query.bytes().all(|b| b.is_ascii_hexdigit()) {
+        let query = query.to_ascii_lowercase();
+        if let Some(block) = query_block(conn, &query)? {
+            return Ok(json!({ "type": "block", "block": block }));
+        }
+        if let Some(tx) = query_tx(conn, &query)? {
+            return Ok(json!({ "type": "tx", "tx": tx }));
+        }
+    } else if is_valid_address(query, chain.network) {
+        let summary = query_address(conn, query, 20, 0)?;
+        return Ok(json!({ "type": "address", "address": summary }));
+    }
+    Ok(json!({ "type": "not_found", "query": query }))
+}
+
+// GET /search/{query} - Find a block, transaction or address
+#[utoipa::path(
+    get,
+    path = "/search/{query}",
+    params(("query" = String, Path, description = "Block height, block hash, txid or address")),
+    responses(
+        (status = 200, description = "What the query matched, or type not_found", body = SearchResult),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_search(
+    db: web::Data<DbPool>,
+    chain: web::Data<ChainConfig>,
+    query: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = get_conn(&db)?;
+
+    let result = search(&conn, &chain, query.trim())?;
+    Ok(HttpResponse::Ok().json(result))
+}
+
+// GET /address/{address}?page=1&limit=20 - Get address balance and transaction history
+#[utoipa::path(
+    get,
+    path = "/address/{address}",
+    params(
+        ("address" = String, Path, description = "Address on the selected network"),
+        ("page" = Option<usize>, Query, description = "Page number, from 1"),
+        ("limit" = Option<usize>, Query, description = "Items per page (default 20, at most --max-page-size)"),
+    ),
+    responses(
+        (status = 200, description = "Balance and one page of txids, newest first", body = AddressPage),
+        (status = 400, description = "Invalid address", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_address(
+    db: web::Data<DbPool>,
+    chain: web::Data<ChainConfig>,
+    address: web::Path<String>,
+    query: web::Query<HashMap<String, String>>,
+    api: web::Data<ApiConfig>,
+) -> Result<HttpResponse, ApiError> {
+    let address = address.into_inner();
+
+    validate_address(&address, chain.network)?;
+
+    let (page, limit) = page_params(&query, &api);
+
+    let offset = page_offset(page, limit);
+
+    let conn = get_conn(&db)?;
+
+    let summary = query_address(&conn, &address, limit, offset)?;
+
+    Ok(HttpResponse::Ok().json(PaginatedResponse::new(json!({
+        "address": summary.address,
+        "balance": summary.balance,
+        "total_received": summary.total_received,
+        "total_sent": summary.total_sent,
+        "transactions": summary.transactions,
+    }), page, limit, summary.tx_count)))
+}
+
+// GET /address/{address}/history?limit=20&cursor=... - Transactions touching an address,
+// newest first, paged by cursor rather than page number
+#[utoipa::path(
+    get,
+    path = "/address/{address}/history",
+    params(
+        ("address" = String, Path, description = "Address on the selected network"),
+        ("limit" = Option<usize>, Query, description = "Items per page (default 20, at most --max-page-size)"),
+        ("cursor" = Option<String>, Query, description = "next_cursor from the previous page; omit for the newest transactions"),
+    ),
+    responses(
+        (status = 200, description = "One page of the address's transactions", body = AddressHistory),
+        (status = 400, description = "Invalid address or cursor", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_address_history(
+    db: web::Data<DbPool>,
+    chain: web::Data<ChainConfig>,
+    address: web::Path<String>,
+    query: web::Query<HashMap<String, String>>,
+    api: web::Data<ApiConfig>,
+) -> Result<HttpResponse, ApiError> {
+    let address = address.into_inner();
+
+    validate_address(&address, chain.network)?;
+
+    let (_, limit) = page_params(&query, &api);
+    let cursor = match query.get("cursor").map(|token| decode_cursor(token).ok_or(token)) {
+        None => None,
+        Some(Ok(cursor)) => Some(cursor),
+        Some(Err(token)) => return Err(ApiError::invalid("invalid_parameter", "cursor isn't one this server handed out")
+            .with_details(json!({ "cursor": token }))),
+    };
+
+    let conn = get_conn(&db)?;
+
+    // One extra row says whether there is a next page
+    let mut transactions = query_address_history(&conn, &address, limit + 1, cursor.as_ref())?;
+    let next_cursor = if transactions.len() > limit {
+        transactions.truncate(limit);
+        transactions.last().map(|last| encode_cursor(&HistoryCursor {
+            height: last.block_height,
+            txid: last.txid.clone(),
+        }))
+    } else {
+        None
+    };
+    Ok(HttpResponse::Ok().json(AddressHistory {
+        address,
+        transactions,
+        next_cursor,
+    }))
+}
+
+// GET /rich-list?page=1&limit=20 - Addresses ranked by balance, from a periodically
+// recomputed ranking
+#[utoipa::path(
+    get,
+    path = "/rich-list",
+    params(
+        ("page" = Option<usize>, Query, description = "Page number, from 1"),
+        ("limit" = Option<usize>, Query, description = "Items per page (default 20, at most --max-page-size)"),
+    ),
+    responses(
+        (status = 200, description = "One page of the richest addresses, richest first", body = RichListPage),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_rich_list(
+    db: web::Data<DbPool>,
+    rich_list: web::Data<RichListCache>,
+    query: web::Query<HashMap<String, String>>,
+    api: web::Data<ApiConfig>,
+) -> Result<HttpResponse, ApiError> {
+    let (page, limit) = page_params(&query, &api);
+
+    let conn = get_conn(&db)?;
+
+    let (computed_at, list) = rich_list.get(&conn)?;
+    let addresses = list.iter().skip(page_offset(page, limit)).take(limit).cloned().collect();
+    Ok(HttpResponse::Ok().json(PaginatedResponse::new(
+        RichList { addresses, computed_at },
+        page, limit, list.len(),
+    )))
+}
+
+// GET /utxos/{address}?min_confirmations=0 - Unspent outputs paying to an address
+#[utoipa::path(
+    get,
+    path = "/utxos/{address}",
+    params(
+        ("address" = String, Path, description = "Address on the selected network"),
+        ("min_confirmations" = Option<u64>, Query, description = "Leave out outputs with fewer confirmations (default 0)"),
+    ),
+    responses(
+        (status = 200, description = "Unspent outputs, oldest first", body = AddressUtxos),
+        (status = 400, description = "Invalid address", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_address_utxos(
+    db: web::Data<DbPool>,
+    chain: web::Data<ChainConfig>,
+    address: web::Path<String>,
+    query: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let address = address.into_inner();
+
+    validate_address(&address, chain.network)?;
+
+    let min_confirmations: u64 = query.get("min_confirmations")
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(0);
+
+    let conn = get_conn(&db)?;
+
+    let utxos = query_address_utxos(&conn, &address, min_confirmations)?;
+    let balance: u64 = utxos.iter().map(|utxo| utxo.value).sum();
+    Ok(HttpResponse::Ok().json(json!({
+        "address": address,
+        "balance": balance,
+        "utxos": utxos
+    })))
+}
+
+// GET /mempool?page=1&limit=20 - Get pending transactions
+#[utoipa::path(
+    get,
+    path = "/mempool",
+    params(
+        ("page" = Option<usize>, Query, description = "Page number, from 1"),
+        ("limit" = Option<usize>, Query, description = "Items per page (default 20, at most --max-page-size)"),
+    ),
+    responses(
+        (status = 200, description = "One page of pending transactions, newest first", body = MempoolPage),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_mempool(
+    db: web::Data<DbPool>,
+    query: web::Query<HashMap<String, String>>,
+    api: web::Data<ApiConfig>,
+) -> Result<HttpResponse, ApiError> {
+    let (page, limit) = page_params(&query, &api);
+
+    let offset = page_offset(page, limit);
+
+    let conn = get_conn(&db)?;
+
+    let (size, total_fees) = query_mempool_stats(&conn)?;
+    let transactions = query_mempool(&conn, limit, offset)?;
+
+    Ok(HttpResponse::Ok().json(PaginatedResponse::new(json!({
+        "size": size,
+        "total_fees": total_fees,
+        "transactions": transactions,
+    }), page, limit, size)))
+}
+
+// Parse and check ?from=X&to=Y, answering with a 400 when the range is missing, reversed
+// or wider than MAX_RANGE_SPAN
+fn height_range(query: &HashMap<String, String>) -> Result<(u32, u32), ApiError> {
+    let from: Option<u32> = query.get("from").and_then(|f| f.parse().ok());
+    let to: Option<u32> = query.get("to").and_then(|t| t.parse().ok());
+
+    let (from, to) = match (from, to) {
+        (Some(from), Some(to)) if from <= to => (from, to),
+        (Some(from), Some(to)) => return Err(ApiError::invalid(
+            "invalid_range",
+            format!("from ({}) must not be greater than to ({})", from, to),
+        ).with_details(json!({ "from": from, "to": to }))),
+        _ => return Err(ApiError::invalid("invalid_range", "from and to must both be given as block heights")
+            .with_details(json!({ "from": query.get("from"), "to": query.get("to") }))),
+    };
+
+    if to - from >= MAX_RANGE_SPAN {
+        return Err(ApiError::invalid("invalid_range", format!("range may cover at most {} blocks", MAX_RANGE_SPAN))
+            .with_details(json!({ "from": from, "to": to })));
+    }
+    Ok((from, to))
+}
+
+// GET /blocks/range?from=X&to=Y - Get blocks with from <= height <= to
+#[utoipa::path(
+    get,
+    path = "/blocks/range",
+    params(
+        ("from" = u32, Query, description = "First height"),
+        ("to" = u32, Query, description = "Last height; at most 2000 blocks after from"),
+    ),
+    responses(
+        (status = 200, description = "Blocks in ascending height order", body = BlocksRange),
+        (status = 400, description = "Invalid range", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn get_blocks_range(
+    db: web::Data<DbPool>,
+    query: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let (from, to) = height_range(&query)?;
+
+    let conn = get_conn(&db)?;
+
+    let blocks = query_blocks_range(&conn, from, to)?;
+    Ok(HttpResponse::Ok().json(json!({
+        "from": from,
+        "to": to,
+        "blocks": blocks
+    })))
+}
+
+// GET /export/blocks.csv?from=X&to=Y - Blocks in a height range as a CSV download.
+// The range is read EXPORT_PAGE_BLOCKS heights at a time as the body is sent, one chunk per page.
+#[utoipa::path(
+    get,
+    path = "/export/blocks.csv",
+    params(
+        ("from" = u32, Query, description = "First height"),
+        ("to" = u32, Query, description = "Last height; at most 2000 blocks after from"),
+    ),
+    responses(
+        (status = 200, description = "hash,height,timestamp,tx_count,size rows as an attachment", body = String, content_type = "text/csv"),
+        (status = 400, description = "Invalid range", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn export_blocks_csv(
+    db: web::Data<DbPool>,
+    query: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let (from, to) = height_range(&query)?;
+
+    let conn = get_conn(&db)?;
+
+    let columns = stream::once(async { Ok::<_, actix_web::Error>(Bytes::from_static(b"hash,height,timestamp,tx_count,size\n")) });
+    // The connection stays checked out until the last page is read
+    let rows = stream::try_unfold((conn, Some(from)), move |(conn, page_from)| async move {
+        let Some(page_from) = page_from else {
+            return Ok(None);
+        };
+        let page_to = page_from.saturating_add(EXPORT_PAGE_BLOCKS - 1).min(to);
+        let blocks = query_blocks_range(&conn, page_from, page_to).map_err(ApiError::from)?;
+        let csv: String = blocks.iter()
+            .map(|block| format!("{},{},{},{},{}\n", block.hash, block.height, block.timestamp, block.tx_count, block.size))
+            .collect();
+        let next = (page_to < to).then(|| page_to + 1);
+        Ok(Some((Bytes::from(csv), (conn, next))))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"blocks-{}-{}.csv\"", from, to),
+        ))
+        .streaming(columns.chain(rows)))
+}
+
+// GET /ws/blocks - WebSocket stream of newly indexed blocks
+pub async fn ws_blocks(
+    req: HttpRequest,
+    body: web::Payload,
+) -> actix_web::Result<HttpResponse> {
+    let (response, mut session, mut messages) = actix_ws::handle(&req, body)?;
+    let mut blocks = subscribe_blocks();
+
+    actix_web::rt::spawn(async move {
+        let code = loop {
+            tokio::select! {
+                block = blocks.recv() => match block {
+                    Ok(summary) => {
+                        let text = serde_json::to_string(&summary).unwrap();
+                        if session.text(text).await.is_err() {
+                            return;
+                        }
+                    }
+                    // A client that can't keep up is dropped rather than buffered for
+                    Err(RecvError::Lagged(_)) => break CloseCode::Policy,
+                    Err(RecvError::Closed) => break CloseCode::Normal,
+                },
+                message = messages.recv() => match message {
+                    Some(Ok(Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            return;
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | None => break CloseCode::Normal,
+                    Some(Err(_)) => break CloseCode::Protocol,
+                    Some(Ok(_)) => {}
+                },
+            }
+        };
+        let _ = session.close(Some(code.into())).await;
+    });
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn validate_hash_accepts_only_lowercase_hex_of_64_chars() {
+        assert!(validate_hash(&"0a".repeat(32), "hash").is_ok());
+        assert!(validate_hash(&"0A".repeat(32), "hash").is_err());
+        assert!(validate_hash(&"0a".repeat(31), "txid").is_err());
+        assert!(validate_hash(&format!("{}zz", "0a".repeat(31)), "txid").is_err());
+        assert!(validate_hash("", "hash").is_err());
+    }
+
+    #[test]
+    fn validate_address_rejects_other_networks_and_garbage() {
+        let mainnet = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+        assert!(validate_address(mainnet, bitcoin::Network::Bitcoin).is_ok());
+        for (address, network) in [(mainnet, bitcoin::Network::Regtest), ("not-an-address", bitcoin::Network::Bitcoin)] {
+            let error = validate_address(address, network).unwrap_err();
+            assert_eq!((error.status_code(), error.code()), (StatusCode::BAD_REQUEST, "invalid_address"));
+        }
+    }
+
+    #[test]
+    fn invalid_hash_is_a_bad_request() {
+        let error = validate_hash("abc", "txid").unwrap_err();
+        assert_eq!((error.status_code(), error.code()), (StatusCode::BAD_REQUEST, "invalid_parameter"));
+    }
+
+    #[actix_web::test]
+    async fn errors_share_one_envelope() {
+        let error = block_not_found("height", json!(7));
+        let response = error.error_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, json!({
+            "error": {"code": "block_not_found", "message": "Block not found", "details": {"height": 7}}
+        }));
+
+        let error = ApiError::from(rusqlite::Error::InvalidQuery);
+        assert_eq!((error.status_code(), error.code()), (StatusCode::INTERNAL_SERVER_ERROR, "database_error"));
+    }
+
+    #[actix_web::test]
+    async fn failed_count_queries_are_database_errors() {
+        let pool = crate::test_utils::test_pool();
+        pool.get().unwrap().execute_batch("DROP TABLE metadata").unwrap();
+        let api = web::Data::new(ApiConfig::new(50));
+        let latest = get_latest_blocks(web::Data::new(pool.clone()), web::Query(HashMap::new()), api.clone()).await;
+        assert_eq!(latest.unwrap_err().code(), "database_error");
+        let stats = get_stats(web::Data::new(pool.clone())).await;
+        assert_eq!(stats.unwrap_err().code(), "database_error");
+
+        // Stats read their totals from metadata, but the tip from blocks
+        let pool = crate::test_utils::test_pool();
+        pool.get().unwrap().execute_batch("DROP TABLE blocks").unwrap();
+        let stats = get_stats(web::Data::new(pool)).await;
+        assert_eq!(stats.unwrap_err().code(), "database_error");
+    }
+
+    #[test]
+    fn parse_raw_tx_rejects_bad_hex_and_bad_transactions() {
+        let tx = crate::test_utils::coinbase_tx(1, 5_000);
+        let hex = bitcoin::consensus::encode::serialize_hex(&tx);
+        let (parsed_hex, parsed) = parse_raw_tx(format!("{}\n", hex).as_bytes()).unwrap();
+        assert_eq!((parsed_hex, parsed), (hex.clone(), tx));
+
+        assert_eq!(parse_raw_tx(b"zz").unwrap_err().code(), "invalid_hex");
+        assert_eq!(parse_raw_tx(&hex.as_bytes()[..20]).unwrap_err().code(), "invalid_transaction");
+    }
+
+    #[test]
+    fn with_etag_answers_matching_if_none_match_with_not_modified() {
+        use actix_web::test::TestRequest;
+        let ok = || HttpResponse::Ok().body("block");
+
+        let response = with_etag(&TestRequest::default().to_http_request(), "abc".into(), ok);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get(header::ETAG).unwrap(), "\"abc\"");
+
+        for if_none_match in ["\"abc\"", "\"def\", W/\"abc\"", "*"] {
+            let req = TestRequest::default()
+                .insert_header((header::IF_NONE_MATCH, if_none_match))
+                .to_http_request();
+            let response = with_etag(&req, "abc".into(), ok);
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED, "{}", if_none_match);
+            assert_eq!(response.headers().get(header::ETAG).unwrap(), "\"abc\"");
+        }
+
+        let req = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, "\"def\""))
+            .to_http_request();
+        assert_eq!(with_etag(&req, "abc".into(), ok).status(), StatusCode::OK);
+        let not_found = with_etag(&TestRequest::default().to_http_request(), "abc".into(), || HttpResponse::NotFound().finish());
+        assert!(not_found.headers().get(header::ETAG).is_none());
+    }
+
+    #[test]
+    fn prevouts_come_from_indexed_transactions_or_are_null() {
+        use crate::script::output_address;
+        use bitcoin::hashes::Hash;
+        let conn = test_db();
+        let config = ChainConfig::default();
+        let coinbase = coinbase_tx(0, 5_000);
+        let block = test_block(bitcoin::BlockHash::all_zeros(), 0, vec![coinbase.clone()]);
+        insert_block(&conn, &block, 0, &config).unwrap();
+        let mut spend = spend_tx(&coinbase, 0, 4_000);
+        let mut unknown = spend_tx(&coinbase, 0, 1_000);
+        unknown.input[0].previous_output.txid = bitcoin::Txid::from_byte_array([7; 32]);
+        spend.input.push(unknown.input[0].clone());
+        insert_tx(&conn, &spend, &block.block_hash().to_string(), 1, None, &config).unwrap();
+
+        let mut tx = query_tx(&conn, &spend.compute_txid().to_string()).unwrap().unwrap();
+        add_prevouts(&mut tx);
+        let prevouts: Vec<_> = tx.inputs.iter()
+            .map(|input| input.prevout.as_ref().map(|p| (p.value, p.script_type.clone(), p.address.clone())))
+            .collect();
+        let address = output_address(&p2wpkh_script(1), config.network);
+        assert!(address.is_some());
+        assert_eq!(prevouts, vec![
+            Some((Some(5_000), Some("p2wpkh".to_string()), address)),
+            Some((None, None, None)),
+        ]);
+
+        let mut tx = query_tx(&conn, &coinbase.compute_txid().to_string()).unwrap().unwrap();
+        add_prevouts(&mut tx);
+        assert!(tx.inputs[0].prevout.is_none());
+    }
+
+    #[test]
+    fn latest_blocks_limit_is_capped_and_rejects_zero() {
+        let api = ApiConfig::new(50);
+        let limit = |value: Option<&str>| {
+            let query: HashMap<String, String> = value.map(|v| ("limit".to_string(), v.to_string())).into_iter().collect();
+            latest_blocks_limit(&query, &api).map_err(|error| error.status_code())
+        };
+        assert_eq!(limit(None), Ok(10));
+        assert_eq!(limit(Some("25")), Ok(25));
+        assert_eq!(limit(Some("9999999")), Ok(50));
+        assert_eq!(limit(Some("0")), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(limit(Some("ten")), Err(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn requested_shape_prefers_query_over_accept() {
+        use actix_web::test::TestRequest;
+        let shape = |uri: &str, accept: Option<&str>| {
+            let mut req = TestRequest::with_uri(uri);
+            if let Some(accept) = accept {
+                req = req.insert_header((header::ACCEPT, accept));
+            }
+            requested_shape(&req.to_http_request()).map_err(|error| error.status_code())
+        };
+
+        assert_eq!(shape("/tx/x", None), Ok(Shape::V1));
+        assert_eq!(shape("/tx/x?v=2", None), Ok(Shape::V2));
+        assert_eq!(shape("/tx/x", Some("application/vnd.explorer.v2+json")), Ok(Shape::V2));
+        assert_eq!(shape("/tx/x?v=1", Some("application/vnd.explorer.v2+json")), Ok(Shape::V1));
+        assert_eq!(shape("/tx/x", Some("application/json")), Ok(Shape::V1));
+        assert_eq!(shape("/tx/x?v=3", None), Err(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn parse_time_bound_accepts_timestamps_and_dates() {
+        assert_eq!(parse_time_bound("1700000000", false), Ok(1_700_000_000));
+        assert_eq!(parse_time_bound("1970-01-01", false), Ok(0));
+        assert_eq!(parse_time_bound("2024-02-29", false), Ok(1_709_164_800));
+        assert_eq!(parse_time_bound("2024-02-29", true), Ok(1_709_164_800 + 86_399));
+        assert!(parse_time_bound("2023-02-29", false).is_err());
+        assert!(parse_time_bound("2024-1-5x", false).is_err());
+        assert!(parse_time_bound("99999999999", false).is_err());
+        assert!(parse_time_bound("", false).is_err());
+    }
+
+    #[test]
+    fn history_cursor_round_trips() {
+        let cursor = HistoryCursor { height: 42, txid: "ab".repeat(32) };
+        assert_eq!(decode_cursor(&encode_cursor(&cursor)), Some(cursor));
+        assert_eq!(decode_cursor("not a cursor"), None);
+        assert_eq!(decode_cursor(&URL_SAFE_NO_PAD.encode(b"{}")), None);
+    }
+
+    #[actix_web::test]
+    async fn page_params_keep_limit_positive_and_offsets_in_range() {
+        let api = ApiConfig::new(50);
+        let params = |pairs: &[(&str, &str)]| {
+            let query: HashMap<String, String> = pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+            page_params(&query, &api)
+        };
+        assert_eq!(params(&[]), (1, 20));
+        assert_eq!(params(&[("limit", "0"), ("page", "0")]), (1, 1));
+        assert_eq!(params(&[("limit", "500")]), (1, 50));
+
+        assert_eq!(page_offset(3, 20), 40);
+        assert_eq!(page_offset(usize::MAX, 50), i64::MAX as usize);
+
+        let pool = crate::test_utils::test_pool();
+        let query = [("limit", "0"), ("page", &usize::MAX.to_string())].map(|(k, v)| (k.to_string(), v.to_string()));
+        let response = get_op_returns(web::Data::new(pool), web::Query(HashMap::from(query)), web::Data::new(api)).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!((&body["op_returns"], &body["pagination"]["per_page"]), (&json!([]), &json!(1)));
+    }
+
+    #[actix_web::test]
+    async fn csv_export_reads_the_range_a_page_at_a_time() {
+        use actix_web::body::MessageBody;
+        use bitcoin::hashes::Hash;
+        let pool = test_pool();
+        {
+            let conn = pool.get().unwrap();
+            let mut prev = bitcoin::BlockHash::all_zeros();
+            for height in 0..250 {
+                let block = test_block(prev, height, vec![coinbase_tx(height, 5_000)]);
+                prev = block.block_hash();
+                // A gap the export skips over
+                if height != 120 {
+                    insert_block(&conn, &block, height, &ChainConfig::default()).unwrap();
                 }
-            }))
+            }
         }
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Database error",
-            "message": e.to_string()
-        })),
+        let query = HashMap::from([("from".to_string(), "0".to_string()), ("to".to_string(), "249".to_string())]);
+        let response = export_blocks_csv(web::Data::new(pool.clone()), web::Query(query)).await.unwrap();
+
+        let mut body = std::pin::pin!(response.into_body());
+        let mut chunks = Vec::new();
+        while let Some(chunk) = std::future::poll_fn(|cx| body.as_mut().poll_next(cx)).await {
+            chunks.push(String::from_utf8(chunk.unwrap().to_vec()).unwrap());
+        }
+        // The header, then one chunk per EXPORT_PAGE_BLOCKS heights
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[0], "hash,height,timestamp,tx_count,size\n");
+        assert_eq!(chunks.iter().map(|chunk| chunk.lines().count()).collect::<Vec<_>>(), vec![1, 100, 99, 50]);
+
+        let conn = pool.get().unwrap();
+        let heights: Vec<u32> = chunks[1..].iter()
+            .flat_map(|chunk| chunk.lines().map(|line| line.split(',').nth(1).unwrap().parse().unwrap()))
+            .collect();
+        let stored: Vec<u32> = query_blocks_range(&conn, 0, 249).unwrap().iter().map(|block| block.height).collect();
+        assert_eq!(heights, stored);
+    }
+
+    #[actix_web::test]
+    async fn large_responses_are_gzipped_unless_compression_is_off() {
+        use actix_web::{test, App};
+        let pool = test_pool();
+        store_chain(&pool.get().unwrap(), 100);
+
+        let mut sizes = Vec::new();
+        for compression in [true, false] {
+            let app = test::init_service(
+                App::new()
+                    .wrap(compress_responses(compression))
+                    .app_data(web::Data::new(pool.clone()))
+                    .app_data(web::Data::new(ApiConfig::new(100)))
+                    .route("/blocks", web::get().to(get_all_blocks)),
+            ).await;
+            let request = test::TestRequest::get()
+                .uri("/blocks?limit=100")
+                .insert_header((header::ACCEPT_ENCODING, "gzip"))
+                .to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let encoding = response.headers().get(header::CONTENT_ENCODING).map(|value| value.to_str().unwrap().to_string());
+            assert_eq!(encoding.as_deref(), compression.then_some("gzip"), "--compression {}", compression);
+            sizes.push(test::read_body(response).await.len());
+        }
+        assert!(sizes[0] < sizes[1] / 2, "gzipped {} bytes, plain {}", sizes[0], sizes[1]);
+    }
+
+    #[actix_web::test]
+    async fn latest_blocks_total_does_not_depend_on_the_limit() {
+        let pool = test_pool();
+        store_chain(&pool.get().unwrap(), 7);
+        let api = web::Data::new(ApiConfig::new(50));
+        let mut totals = Vec::new();
+        for limit in ["2", "5"] {
+            let query = web::Query(HashMap::from([("limit".to_string(), limit.to_string())]));
+            let response = get_latest_blocks(web::Data::new(pool.clone()), query, api.clone()).await.unwrap();
+            let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["blocks"].as_array().unwrap().len().to_string(), limit);
+            assert_eq!(body["blocks"][0]["height"], 6);
+            totals.push((body["total_blocks"].clone(), body["pagination"]["total_items"].clone()));
+        }
+        assert_eq!(totals[0], totals[1]);
+        assert_eq!(totals[0], (json!(7), json!(7)));
+    }
+
+    #[actix_web::test]
+    async fn raw_blocks_decode_to_the_requested_block() {
+        let pool = test_pool();
+        let chain = store_chain(&pool.get().unwrap(), 3);
+        let block = &chain[1];
+        let hash = block.block_hash().to_string();
+        let req = actix_web::test::TestRequest::default().to_http_request();
+
+        for format in [None, Some("bin")] {
+            let query = || web::Query(format.map(|f| ("format".to_string(), f.to_string())).into_iter().collect::<HashMap<_, _>>());
+            let by_hash = get_block_raw(req.clone(), web::Data::new(pool.clone()), web::Path::from(hash.clone()), query()).await.unwrap();
+            let by_height = get_block_raw_by_height(req.clone(), web::Data::new(pool.clone()), web::Path::from(1), query()).await.unwrap();
+            for response in [by_hash, by_height] {
+                let content_type = response.headers().get(header::CONTENT_TYPE).unwrap().to_str().unwrap().to_string();
+                let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+                let bytes = match format {
+                    Some(_) => body.to_vec(),
+                    None => hex::decode(&body).unwrap(),
+                };
+                let decoded: bitcoin::Block = bitcoin::consensus::deserialize(&bytes).unwrap();
+                assert_eq!(decoded.block_hash(), block.block_hash(), "format {:?}", format);
+                assert_eq!(content_type, if format.is_some() { "application/octet-stream" } else { "text/plain" });
+            }
+        }
+
+        let missing = get_block_raw_by_height(req, web::Data::new(pool), web::Path::from(3), web::Query(HashMap::new())).await;
+        assert_eq!(missing.unwrap_err().code(), "block_not_found");
+    }
+
+    #[actix_web::test]
+    async fn fee_estimates_report_min_median_and_max() {
+        assert_eq!(median(&[]), None);
+        assert_eq!(median(&[1.0, 2.0, 9.0]), Some(2.0));
+        assert_eq!(median(&[1.0, 2.0, 4.0, 9.0]), Some(3.0));
+
+        // Nothing but coinbases: no rates to report
+        let pool = crate::test_utils::test_pool();
+        crate::test_utils::store_chain(&pool.get().unwrap(), 2);
+        let query = web::Query(HashMap::from([("blocks".to_string(), "5000".to_string())]));
+        let response = get_fee_estimates(web::Data::new(pool), query).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, json!({
+            "blocks": MAX_FEE_ESTIMATE_BLOCKS, "transactions": 0, "min": null, "median": null, "max": null
+        }));
+    }
+
+    #[actix_web::test]
+    async fn blocks_range_is_inclusive_and_bounded() {
+        let range = |from: &str, to: &str| {
+            let query = HashMap::from([("from".to_string(), from.to_string()), ("to".to_string(), to.to_string())]);
+            height_range(&query).map_err(|error| error.code())
+        };
+        assert_eq!(range("5", "5"), Ok((5, 5)));
+        assert_eq!(range("0", &(MAX_RANGE_SPAN - 1).to_string()), Ok((0, MAX_RANGE_SPAN - 1)));
+        assert_eq!(range("0", &MAX_RANGE_SPAN.to_string()), Err("invalid_range"));
+        assert_eq!(range("6", "5"), Err("invalid_range"));
+        assert_eq!(range("x", "5"), Err("invalid_range"));
+        assert_eq!(height_range(&HashMap::new()).unwrap_err().code(), "invalid_range");
+
+        let pool = crate::test_utils::test_pool();
+        crate::test_utils::store_chain(&pool.get().unwrap(), 5);
+        let query = HashMap::from([("from".to_string(), "1".to_string()), ("to".to_string(), "9".to_string())]);
+        let response = get_blocks_range(web::Data::new(pool), web::Query(query)).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let heights: Vec<u64> = body["blocks"].as_array().unwrap().iter().map(|block| block["height"].as_u64().unwrap()).collect();
+        assert_eq!((&body["from"], &body["to"], heights), (&json!(1), &json!(9), vec![1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn search_tells_heights_hashes_txids_and_addresses_apart() {
+        let conn = test_db();
+        let chain = store_chain(&conn, 2);
+        let config = ChainConfig::default();
+        let kind = |query: &str| search(&conn, &config, query).unwrap()["type"].as_str().unwrap().to_string();
+
+        let found = search(&conn, &config, "1").unwrap();
+        assert_eq!((found["type"].as_str(), &found["block"]["hash"]), (Some("block"), &json!(chain[1].block_hash().to_string())));
+        assert_eq!(kind(&chain[0].block_hash().to_string().to_uppercase()), "block");
+        let txid = chain[1].txdata[0].compute_txid().to_string();
+        assert_eq!(search(&conn, &config, &txid).unwrap()["tx"]["txid"], json!(txid));
+        let address = bitcoin::Address::from_script(&p2wpkh_script(1), config.network).unwrap().to_string();
+        assert_eq!(search(&conn, &config, &address).unwrap()["address"]["tx_count"], json!(2));
+
+        assert_eq!(kind("2"), "not_found");
+        assert_eq!(kind(&"ab".repeat(32)), "not_found");
+        // A mainnet address on a regtest explorer
+        assert_eq!(kind("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"), "not_found");
+        assert_eq!(kind(""), "not_found");
+    }
+
+    #[test]
+    fn pagination_counts_partial_last_page() {
+        let pagination = Pagination::new(1, 10, 25);
+        assert_eq!((pagination.total_pages, pagination.has_next, pagination.has_prev), (3, true, false));
+        let pagination = Pagination::new(3, 10, 25);
+        assert_eq!((pagination.total_pages, pagination.has_next, pagination.has_prev), (3, false, true));
+        assert_eq!(Pagination::new(1, 10, 0).total_pages, 0);
+        assert_eq!(Pagination::new(1, 20, 40).total_pages, 2);
+    }
+
+    #[actix_web::test]
+    async fn paginated_endpoints_share_one_pagination_object() {
+        let pool = test_pool();
+        store_chain(&pool.get().unwrap(), 3);
+        let db = web::Data::new(pool);
+        let api = web::Data::new(ApiConfig::new(50));
+        let query = || web::Query(HashMap::from([("limit".to_string(), "2".to_string())]));
+        let body = |response: HttpResponse| async {
+            let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        };
+        let expected = json!({
+            "current_page": 1, "per_page": 2, "total_items": 3, "total_pages": 2, "has_next": true, "has_prev": false
+        });
+
+        let blocks = body(get_all_blocks(db.clone(), query(), api.clone()).await.unwrap()).await;
+        assert_eq!(blocks["pagination"], expected);
+        assert_eq!(blocks["blocks"].as_array().unwrap().len(), 2);
+        assert!(blocks.get("total_blocks").is_none());
+
+        let latest = body(get_latest_blocks(db.clone(), query(), api.clone()).await.unwrap()).await;
+        assert_eq!(latest["pagination"], expected);
+        assert_eq!((&latest["total_blocks"], &latest["limit"]), (&json!(3), &json!(2)));
+
+        let empty = json!({
+            "current_page": 1, "per_page": 2, "total_items": 0, "total_pages": 0, "has_next": false, "has_prev": false
+        });
+        let op_returns = body(get_op_returns(db.clone(), query(), api.clone()).await.unwrap()).await;
+        assert_eq!((&op_returns["op_returns"], &op_returns["pagination"]), (&json!([]), &empty));
+        let mempool = body(get_mempool(db, query(), api).await.unwrap()).await;
+        assert_eq!((&mempool["size"], &mempool["pagination"]), (&json!(0), &empty));
+    }
+
+    #[actix_web::test]
+    async fn tx_page_size_is_capped_at_max_page_size() {
+        use bitcoin::hashes::Hash;
+        let pool = test_pool();
+        let mut coinbase = coinbase_tx(0, 1_000);
+        coinbase.output = (0..30).map(|seed| bitcoin::TxOut {
+            value: bitcoin::Amount::from_sat(1_000),
+            script_pubkey: p2wpkh_script(seed),
+        }).collect();
+        let block = test_block(bitcoin::BlockHash::all_zeros(), 0, vec![coinbase.clone()]);
+        insert_block(&pool.get().unwrap(), &block, 0, &ChainConfig::default()).unwrap();
+
+        let txid = coinbase.compute_txid().to_string();
+        let req = actix_web::test::TestRequest::default().uri(&format!("/tx/{}?page_size=1000", txid)).to_http_request();
+        let query = web::Query(HashMap::from([("page_size".to_string(), "1000".to_string())]));
+        let response = get_tx(
+            req, web::Data::new(pool), web::Data::new(LookupCache::new(0)), web::Path::from(txid), query,
+            web::Data::new(ApiConfig::new(20)),
+        ).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let tx: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(tx["outputs"].as_array().unwrap().len(), 20);
+        assert_eq!(tx["outputs_pagination"]["per_page"], 20);
+        assert_eq!(tx["outputs_pagination"]["total_pages"], 2);
+        assert_eq!(tx["inputs_pagination"]["per_page"], 20);
+    }
+
+
+    #[actix_web::test]
+    async fn ws_blocks_answers_a_client_close_with_a_normal_close() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let server = actix_web::HttpServer::new(|| actix_web::App::new().route("/ws/blocks", web::get().to(ws_blocks)))
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(format!(
+            "GET /ws/blocks HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n", addr,
+        ).as_bytes()).await.unwrap();
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            response.push(stream.read_u8().await.unwrap());
+        }
+        assert!(response.starts_with(b"HTTP/1.1 101"), "{}", String::from_utf8_lossy(&response));
+
+        // A masked Close frame (with an all-zero mask) carrying code 1000
+        stream.write_all(&[0x88, 0x82, 0, 0, 0, 0, 0x03, 0xe8]).await.unwrap();
+        let mut frame = [0; 4];
+        stream.read_exact(&mut frame).await.unwrap();
+        handle.stop(false).await;
+        assert_eq!(frame, [0x88, 0x02, 0x03, 0xe8]);
     }
 }
@@ -0,0 +1,723 @@
+use bitcoin::{Block, BlockHash};
+use rusqlite::Connection;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use crate::db::{
+    delete_mempool_tx, extends_stored_chain, handle_reorg, insert_block, insert_block_with_fees, insert_mempool_tx,
+    query_block_hash_at_height, query_block_height, query_first_missing_height, query_latest_block, query_mempool_txids,
+};
+use crate::config::ChainConfig;
+use crate::progress::Progress;
+use crate::status;
+use crate::rpc::BitcoinRpcClient;
+
+// What an indexing run did
+#[derive(Default)]
+pub struct IndexSummary {
+    // With --dry-run, blocks that passed validation and would have been indexed
+    pub indexed: usize,
+    // Transactions in those blocks
+    pub transactions: usize,
+    // Blocks that failed dry-run validation
+    pub invalid: usize,
+    // Blocks that couldn't be fetched or decoded
+    pub skipped: usize,
+    // Blocks left as they were because they're already stored (--on-conflict skip)
+    pub already_stored: usize,
+    // Stopped early by Ctrl+C or SIGTERM
+    pub interrupted: bool,
+    // Why each invalid or skipped block wasn't indexed
+    pub errors: Vec<String>,
+}
+
+impl IndexSummary {
+    // Report a block that wasn't indexed, keeping the message for the summary
+    pub fn error(&mut self, progress: &Progress, message: String) {
+        progress.eprintln(&message);
+        self.errors.push(message);
+    }
+
+    // Fold in the totals of a run made on the way, e.g. catching up after a ZMQ gap
+    fn add(&mut self, run: IndexSummary) {
+        self.indexed += run.indexed;
+        self.transactions += run.transactions;
+        self.invalid += run.invalid;
+        self.skipped += run.skipped;
+        self.already_stored += run.already_stored;
+        self.interrupted = run.interrupted;
+        self.errors.extend(run.errors);
+    }
+}
+
+// How a pass over a height range ended
+enum RangeOutcome {
+    Done,
+    // The block at `height` doesn't build on the stored chain
+    Reorg { height: u32 },
+    Interrupted,
+}
+
+// The checks a dry run makes on each block instead of storing it: the header hashes to the
+// hash the source gave for it (if any), the transactions hash to the header's merkle root,
+// and the block builds on `previous`, the block before it (if known)
+pub fn validate_block(block: &Block, expected_hash: Option<&str>, previous: Option<BlockHash>) -> Result<(), String> {
+    let hash = block.block_hash();
+    if let Some(expected) = expected_hash
+        && hash.to_string() != expected
+    {
+        return Err(format!("header hashes to {}, expected {}", hash, expected));
+    }
+    if !block.check_merkle_root() {
+        return Err("transactions don't match the header's merkle root".to_string());
+    }
+    if let Some(previous) = previous
+        && block.header.prev_blockhash != previous
+    {
+        return Err(format!("builds on {}, not on the previous block {}", block.header.prev_blockhash, previous));
+    }
+    Ok(())
+}
+
+// Resolves on Ctrl+C, or on SIGTERM (what `docker stop` and systemd send) where there is one.
+// A signal that can't be listened for never resolves rather than stopping right away.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+// Flag set once Ctrl+C or SIGTERM is received. Indexing loops check it between blocks, so the
+// block being written is always finished before they stop, and the run ends like a finished one.
+pub fn shutdown_flag() -> Arc<AtomicBool> {
+    let flag = Arc::new(AtomicBool::new(false));
+    let handler_flag = Arc::clone(&flag);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        status!("Shutdown requested, finishing the current block...");
+        handler_flag.store(true, Ordering::SeqCst);
+    });
+    flag
+}
+
+// Index heights start_height..=tip from the node, stopping at `end_height` if it's set.
+// `jobs` workers fetch batches concurrently
+// while this task writes them to the DB strictly in height order. If a block doesn't extend
+// the stored chain, the stale blocks are rolled back to the fork point and indexing resumes
+// from there. Stops cleanly between blocks once `shutdown` is set.
+// With `dry_run`, blocks are fetched and checked with validate_block but nothing is written.
+#[allow(clippy::too_many_arguments)]
+pub async fn index_blocks_rpc(
+    conn: &Connection,
+    rpc: Arc<BitcoinRpcClient>,
+    mut start_height: u32,
+    tip: u32,
+    end_height: Option<u32>,
+    jobs: usize,
+    config: &ChainConfig,
+    shutdown: &AtomicBool,
+    dry_run: bool,
+    progress: &Progress,
+) -> anyhow::Result<IndexSummary> {
+    let last_height = |tip: u32| end_height.map_or(tip, |end| end.min(tip));
+    let mut tip = last_height(tip);
+    let mut summary = IndexSummary::default();
+    loop {
+        match index_range(conn, &rpc, start_height, tip, jobs, config, shutdown, dry_run, progress, &mut summary).await? {
+            RangeOutcome::Done => return Ok(summary),
+            RangeOutcome::Interrupted => {
+                summary.interrupted = true;
+                return Ok(summary);
+            }
+            RangeOutcome::Reorg { height } => {
+                let fork_height = find_fork_point(conn, &rpc, height).await?;
+                let removed = handle_reorg(conn, fork_height)?;
+                progress.println(format!(
+                    "Reorg detected at height {}: removed {} stale blocks, re-indexing from height {}",
+                    height, removed, fork_height
+                ));
+                start_height = fork_height;
+                tip = last_height(rpc.get_block_count().await?);
+                progress.set_remaining((tip + 1).saturating_sub(start_height) as u64);
+            }
+        }
+    }
+}
+
+// The height to resume indexing from: the one after the last stored block, or 0 for an empty
+// database. When an earlier run skipped a block it couldn't store, it's the start of that gap
+// instead, so the gap is filled in first. Warns when the node's `tip` is below the stored
+// chain, which usually means a reorg the stored chain hasn't followed.
+pub fn resume_height(conn: &Connection, tip: u32) -> anyhow::Result<u32> {
+    let Some((last_height, last_hash)) = query_latest_block(conn)? else {
+        return Ok(0);
+    };
+    if tip < last_height {
+        status!(
+            "Warning: node tip height {} is below stored height {} ({}); possible reorg, run with --reindex to rebuild",
+            tip, last_height, last_hash
+        );
+    }
+    let missing = query_first_missing_height(conn)?.unwrap_or(last_height + 1);
+    if missing <= last_height {
+        status!("Height {} is missing below stored height {}; resuming from there", missing, last_height);
+    }
+    Ok(missing)
+}
+
+// Walk back from `height` until the stored block below it matches the node's chain.
+// Returns the first height whose stored block has to be replaced.
+async fn find_fork_point(conn: &Connection, rpc: &BitcoinRpcClient, height: u32) -> anyhow::Result<u32> {
+    let mut height = height;
+    while height > 0 {
+        let parent = height - 1;
+        if let Some(stored) = query_block_hash_at_height(conn, parent)?
+            && stored == rpc.get_block_hash(parent).await?
+        {
+            break;
+        }
+        height = parent;
+    }
+    Ok(height)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn index_range(
+    conn: &Connection,
+    rpc: &Arc<BitcoinRpcClient>,
+    start_height: u32,
+    tip: u32,
+    jobs: usize,
+    config: &ChainConfig,
+    shutdown: &AtomicBool,
+    dry_run: bool,
+    progress: &Progress,
+    summary: &mut IndexSummary,
+) -> anyhow::Result<RangeOutcome> {
+    let heights: Vec<u32> = (start_height..=tip).collect();
+    let chunks: Arc<Vec<Vec<u32>>> = Arc::new(heights.chunks(rpc.batch_size()).map(|c| c.to_vec()).collect());
+    let jobs = jobs.max(1);
+
+    // Workers claim chunks in order, so at most `jobs` chunks are ever out of order
+    let next_chunk = Arc::new(AtomicUsize::new(0));
+    let (sender, mut receiver) = mpsc::channel(jobs * 2);
+
+    for _ in 0..jobs {
+        let rpc = Arc::clone(rpc);
+        let chunks = Arc::clone(&chunks);
+        let next_chunk = Arc::clone(&next_chunk);
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            loop {
+                let index = next_chunk.fetch_add(1, Ordering::SeqCst);
+                let Some(chunk) = chunks.get(index) else { break };
+                let results = rpc.get_blocks_batch(chunk).await;
+                if sender.send((index, results)).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(sender);
+
+    let mut pending = BTreeMap::new();
+    let mut next_write = 0;
+    // A dry run checks each block against the one before it, starting from the stored chain
+    let mut previous: Option<BlockHash> = match start_height.checked_sub(1) {
+        Some(parent) if dry_run => query_block_hash_at_height(conn, parent)?.and_then(|hash| hash.parse().ok()),
+        _ => None,
+    };
+
+    while let Some((index, results)) = receiver.recv().await {
+        pending.insert(index, results);
+
+        while let Some(results) = pending.remove(&next_write) {
+            let chunk = &chunks[next_write];
+            next_write += 1;
+
+            // Log and skip blocks the node couldn't serve rather than aborting the whole run
+            let results = match results {
+                Ok(results) => results,
+                Err(e) => {
+                    summary.error(progress, format!("Skipping heights {}..={}: {}", chunk[0], chunk[chunk.len() - 1], e));
+                    summary.skipped += chunk.len();
+                    chunk.iter().for_each(|&height| progress.advance(height));
+                    continue;
+                }
+            };
+
+            for (&height, result) in chunk.iter().zip(results) {
+                // Dropping the receiver on return stops the workers
+                if shutdown.load(Ordering::SeqCst) {
+                    return Ok(RangeOutcome::Interrupted);
+                }
+                match result {
+                    Ok(fetched) if dry_run => {
+                        match validate_block(&fetched.block, Some(&fetched.hash), previous) {
+                            Ok(()) => {
+                                summary.indexed += 1;
+                                summary.transactions += fetched.block.txdata.len();
+                            }
+                            Err(e) => {
+                                summary.error(progress, format!("Invalid block at height {} ({}): {}", height, fetched.hash, e));
+                                summary.invalid += 1;
+                            }
+                        }
+                        previous = Some(fetched.block.block_hash());
+                        progress.advance(height);
+                    }
+                    Ok(fetched) => {
+                        if !extends_stored_chain(conn, &fetched.block, height)? {
+                            return Ok(RangeOutcome::Reorg { height });
+                        }
+                        if insert_block_with_fees(conn, &fetched.block, height, fetched.node_txs.as_deref(), config)? {
+                            summary.indexed += 1;
+                            summary.transactions += fetched.block.txdata.len();
+                            progress.indexed(height, &fetched.hash);
+                        } else {
+                            summary.already_stored += 1;
+                            progress.advance(height);
+                        }
+                    }
+                    Err(e) => {
+                        summary.error(progress, format!("Skipping block at height {}: {}", height, e));
+                        summary.skipped += 1;
+                        previous = None;
+                        progress.advance(height);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(RangeOutcome::Done)
+}
+
+// Subscribe to the node's `zmqpubrawblock` feed. The socket is blocking, so it's read on its
+// own thread and each pushed block's bytes are forwarded over the returned channel. Messages
+// arrive as [topic, block, sequence]; only the block is kept.
+pub fn subscribe_raw_blocks(endpoint: &str) -> anyhow::Result<mpsc::Receiver<Vec<u8>>> {
+    let context = zmq::Context::new();
+    let socket = context.socket(zmq::SUB)?;
+    socket.connect(endpoint)?;
+    socket.set_subscribe(b"rawblock")?;
+    // Wake up every second so the thread notices when the receiver is dropped
+    socket.set_rcvtimeo(1000)?;
+
+    let (sender, receiver) = mpsc::channel(16);
+    std::thread::spawn(move || {
+        while !sender.is_closed() {
+            match socket.recv_multipart(0) {
+                Ok(mut parts) if parts.len() >= 2 => {
+                    if sender.blocking_send(parts.swap_remove(1)).is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => log::warn!("Ignoring malformed ZMQ message"),
+                Err(zmq::Error::EAGAIN) => {}
+                Err(e) => {
+                    log::warn!("ZMQ receive failed: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+    Ok(receiver)
+}
+
+// Index blocks pushed by the node until Ctrl+C. Each block's height comes from its stored
+// parent. A block whose parent isn't stored means blocks were missed (or a reorg went deeper
+// than the stored tip), so the RPC path catches up to the node's tip instead.
+pub async fn index_blocks_zmq(
+    conn: &Connection,
+    rpc: &Arc<BitcoinRpcClient>,
+    mut blocks: mpsc::Receiver<Vec<u8>>,
+    jobs: usize,
+    config: &ChainConfig,
+    shutdown: &AtomicBool,
+    summary: &mut IndexSummary,
+) -> anyhow::Result<()> {
+    loop {
+        if shutdown.load(Ordering::SeqCst) {
+            summary.interrupted = true;
+            return Ok(());
+        }
+        let bytes = match tokio::time::timeout(Duration::from_secs(1), blocks.recv()).await {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => anyhow::bail!("ZMQ subscription ended"),
+            Err(_) => continue,
+        };
+        let block: Block = match bitcoin::consensus::deserialize(&bytes) {
+            Ok(block) => block,
+            Err(e) => {
+                summary.error(&Progress::hidden(), format!("Skipping undecodable block from ZMQ: {}", e));
+                summary.skipped += 1;
+                continue;
+            }
+        };
+
+        let hash = block.block_hash().to_string();
+        // Blocks found during the backfill are queued too, but are already stored
+        if query_block_height(conn, &hash)?.is_some() {
+            continue;
+        }
+
+        match query_block_height(conn, &block.header.prev_blockhash.to_string())? {
+            Some(parent_height) => {
+                let height = parent_height + 1;
+                // A competing block was stored at this height; the node has switched chains
+                if let Some((tip, _)) = query_latest_block(conn)?
+                    && tip >= height
+                {
+                    let removed = handle_reorg(conn, height)?;
+                    status!("Reorg detected at height {}: removed {} stale blocks", height, removed);
+                }
+                match insert_block(conn, &block, height, config) {
+                    Ok(true) => {
+                        summary.indexed += 1;
+                        summary.transactions += block.txdata.len();
+                        status!("Indexed block at height {}: {}", height, hash);
+                    }
+                    Ok(false) => summary.already_stored += 1,
+                    Err(e) => {
+                        summary.error(&Progress::hidden(), format!("Error inserting block {} at height {}: {}", hash, height, e));
+                        summary.skipped += 1;
+                    }
+                }
+            }
+            None => {
+                let start_height = query_latest_block(conn)?.map_or(0, |(height, _)| height + 1);
+                let tip = rpc.get_block_count().await?;
+                status!("Block {} doesn't extend the stored chain; catching up via RPC from height {}", hash, start_height);
+                let caught_up = index_blocks_rpc(conn, Arc::clone(rpc), start_height, tip, None, jobs, config, shutdown, false, &Progress::hidden()).await?;
+                summary.add(caught_up);
+            }
+        }
+    }
+}
+
+// Poll the node's tip every `interval` until Ctrl+C, indexing new blocks as they appear.
+// A failed poll (e.g. the node restarting) is reported and retried on the next one.
+pub async fn index_blocks_watch(
+    conn: &Connection,
+    rpc: &Arc<BitcoinRpcClient>,
+    interval: Duration,
+    jobs: usize,
+    config: &ChainConfig,
+    shutdown: &AtomicBool,
+    summary: &mut IndexSummary,
+) -> anyhow::Result<()> {
+    loop {
+        if !sleep_unless_shutdown(interval, shutdown).await {
+            summary.interrupted = true;
+            return Ok(());
+        }
+
+        match poll_new_blocks(conn, rpc, jobs, config, shutdown).await {
+            Ok(polled) => summary.add(polled),
+            Err(e) => eprintln!("Polling the node failed: {}", e),
+        }
+    }
+}
+
+// Index whatever the node has beyond the stored tip. A stored tip the node no longer has at
+// that height (a reorg that didn't lengthen the chain, or one to a shorter chain) is rolled
+// back first; index_blocks_rpc handles forks below new blocks itself.
+async fn poll_new_blocks(
+    conn: &Connection,
+    rpc: &Arc<BitcoinRpcClient>,
+    jobs: usize,
+    config: &ChainConfig,
+    shutdown: &AtomicBool,
+) -> anyhow::Result<IndexSummary> {
+    let tip = rpc.get_block_count().await?;
+    let start_height = match query_latest_block(conn)? {
+        Some((stored_height, stored_hash)) => {
+            if stored_height > tip || rpc.get_block_hash(stored_height).await? != stored_hash {
+                let fork_height = find_fork_point(conn, rpc, stored_height.min(tip + 1)).await?;
+                let removed = handle_reorg(conn, fork_height)?;
+                status!("Reorg detected at height {}: removed {} stale blocks", fork_height, removed);
+                fork_height
+            } else {
+                stored_height + 1
+            }
+        }
+        None => 0,
+    };
+    if start_height > tip {
+        return Ok(IndexSummary::default());
+    }
+    index_blocks_rpc(conn, Arc::clone(rpc), start_height, tip, None, jobs, config, shutdown, false, &Progress::hidden()).await
+}
+
+// Sleep for `interval`, in short steps so Ctrl+C doesn't wait it out. False if `shutdown` was set.
+async fn sleep_unless_shutdown(interval: Duration, shutdown: &AtomicBool) -> bool {
+    let deadline = tokio::time::Instant::now() + interval;
+    while !shutdown.load(Ordering::SeqCst) && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_secs(1).min(deadline - tokio::time::Instant::now())).await;
+    }
+    !shutdown.load(Ordering::SeqCst)
+}
+
+// Poll the node's mempool every `interval` until `shutdown` is set, storing new pending transactions
+// and dropping ones that left the mempool (mined, replaced or evicted). A signal that arrives
+// during a poll stops the loop once that poll is done.
+pub async fn index_mempool(
+    conn: &Connection,
+    rpc: &BitcoinRpcClient,
+    interval: Duration,
+    shutdown: &AtomicBool,
+) -> anyhow::Result<()> {
+    loop {
+        match sync_mempool(conn, rpc).await {
+            Ok((added, removed)) => status!("Mempool synced: {} added, {} removed", added, removed),
+            Err(e) => eprintln!("Mempool poll failed: {}", e),
+        }
+
+        if !sleep_unless_shutdown(interval, shutdown).await {
+            break;
+        }
+    }
+    status!("Stopped mempool polling");
+    Ok(())
+}
+
+async fn sync_mempool(conn: &Connection, rpc: &BitcoinRpcClient) -> anyhow::Result<(usize, usize)> {
+    let entries = rpc.get_raw_mempool().await?;
+    let known: HashSet<String> = query_mempool_txids(conn)?.into_iter().collect();
+    let live: HashSet<&str> = entries.iter().map(|e| e.txid.as_str()).collect();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    let mut added = 0;
+    for entry in entries.iter().filter(|e| !known.contains(&e.txid)) {
+        // The tx may have been mined or evicted since getrawmempool
+        match rpc.get_raw_transaction_hex(&entry.txid).await {
+            Ok(hex) => {
+                let tx: bitcoin::Transaction = bitcoin::consensus::encode::deserialize_hex(&hex)?;
+                insert_mempool_tx(conn, &tx, now, entry.fee, entry.vsize)?;
+                added += 1;
+            }
+            Err(e) => eprintln!("Skipping mempool tx {}: {}", entry.txid, e),
+        }
+    }
+
+    let mut removed = 0;
+    for txid in known.iter().filter(|txid| !live.contains(txid.as_str())) {
+        delete_mempool_tx(conn, txid)?;
+        removed += 1;
+    }
+
+    Ok((added, removed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use actix_web::dev::ServerHandle;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use bitcoin::hashes::Hash;
+    use serde_json::json;
+
+    // A node serving `blocks` over JSON-RPC. Batches for lower heights answer slowest, so
+    // concurrent workers finish them out of height order.
+    struct MockNode {
+        blocks: Vec<Block>,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    async fn mock_rpc(node: web::Data<MockNode>, body: web::Json<serde_json::Value>) -> HttpResponse {
+        let (calls, batch) = match body.into_inner() {
+            serde_json::Value::Array(calls) => (calls, true),
+            call => (vec![call], false),
+        };
+        let in_flight = node.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        node.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        if let Some(lowest) = calls.iter().filter_map(|call| call["params"][0].as_u64()).min() {
+            let delay = (node.blocks.len() as u64).saturating_sub(lowest) * 5;
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+        }
+        node.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+        let replies: Vec<serde_json::Value> = calls.iter().map(|call| {
+            let params = &call["params"];
+            let result = match call["method"].as_str() {
+                Some("getblockcount") => json!(node.blocks.len() - 1),
+                Some("getblockhash") => json!(node.blocks[params[0].as_u64().unwrap() as usize].block_hash().to_string()),
+                Some("getblock") => {
+                    let block = node.blocks.iter()
+                        .find(|block| block.block_hash().to_string() == params[0].as_str().unwrap())
+                        .unwrap();
+                    json!(bitcoin::consensus::encode::serialize_hex(block))
+                }
+                Some("getrawmempool") => json!({}),
+                method => panic!("unexpected RPC call {:?}", method),
+            };
+            json!({"id": call["id"], "result": result, "error": null})
+        }).collect();
+        if batch {
+            HttpResponse::Ok().json(replies)
+        } else {
+            HttpResponse::Ok().json(&replies[0])
+        }
+    }
+
+    // Serve a chain of `len` coinbase-only blocks until the returned handle stops the server
+    fn start_mock_node(len: u32) -> (web::Data<MockNode>, String, ServerHandle) {
+        let mut blocks: Vec<Block> = Vec::new();
+        for height in 0..len {
+            let prev = blocks.last().map_or(BlockHash::all_zeros(), |block| block.block_hash());
+            blocks.push(test_block(prev, height, vec![coinbase_tx(height, 5_000)]));
+        }
+        let node = web::Data::new(MockNode { blocks, in_flight: AtomicUsize::new(0), max_in_flight: AtomicUsize::new(0) });
+        let server = HttpServer::new({
+            let node = node.clone();
+            move || App::new().app_data(node.clone()).default_service(web::post().to(mock_rpc))
+        })
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+        let url = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+        (node, url, handle)
+    }
+
+    // Database that logs the height of every block written, in write order
+    fn logged_db() -> Connection {
+        let conn = test_db();
+        conn.execute_batch(
+            "CREATE TABLE write_order (height INTEGER);
+             CREATE TRIGGER log_write AFTER INSERT ON blocks BEGIN INSERT INTO write_order VALUES (NEW.height); END;",
+        ).unwrap();
+        conn
+    }
+
+    fn write_order(conn: &Connection) -> Vec<u32> {
+        conn.prepare("SELECT height FROM write_order ORDER BY rowid").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<rusqlite::Result<_>>().unwrap()
+    }
+
+    #[actix_web::test]
+    async fn parallel_jobs_still_write_in_height_order() {
+        let (node, url, handle) = start_mock_node(12);
+        let conn = logged_db();
+        let rpc = Arc::new(BitcoinRpcClient::new(&url, "user", "password").with_batch_size(2));
+        let shutdown = AtomicBool::new(false);
+        let tip = rpc.get_block_count().await.unwrap();
+        let summary = index_blocks_rpc(
+            &conn, rpc, 0, tip, None, 4, &ChainConfig::default(), &shutdown, false, &Progress::hidden(),
+        ).await.unwrap();
+        handle.stop(false).await;
+
+        assert_eq!((summary.indexed, summary.skipped), (12, 0));
+        assert!(node.max_in_flight.load(Ordering::SeqCst) > 1, "batches were never fetched concurrently");
+        assert_eq!(write_order(&conn), (0..12).collect::<Vec<u32>>());
+    }
+
+    #[actix_web::test]
+    async fn indexing_resumes_after_the_last_stored_block() {
+        let (_node, url, handle) = start_mock_node(12);
+        let conn = logged_db();
+        let rpc = Arc::new(BitcoinRpcClient::new(&url, "user", "password").with_batch_size(4));
+        let shutdown = AtomicBool::new(false);
+        let config = ChainConfig::default();
+        let tip = rpc.get_block_count().await.unwrap();
+        assert_eq!(resume_height(&conn, tip).unwrap(), 0);
+
+        // A run stopped at height 5, then one picking up from the database
+        index_blocks_rpc(&conn, Arc::clone(&rpc), 0, tip, Some(5), 2, &config, &shutdown, false, &Progress::hidden()).await.unwrap();
+        let start = resume_height(&conn, tip).unwrap();
+        assert_eq!(start, 6);
+        let summary = index_blocks_rpc(&conn, rpc, start, tip, None, 2, &config, &shutdown, false, &Progress::hidden()).await.unwrap();
+        handle.stop(false).await;
+
+        assert_eq!((summary.indexed, summary.already_stored), (6, 0));
+        assert_eq!(write_order(&conn), (0..12).collect::<Vec<u32>>());
+        assert_eq!(resume_height(&conn, tip).unwrap(), 12);
+    }
+
+    #[actix_web::test]
+    async fn resume_fills_in_a_skipped_height_first() {
+        let (node, url, handle) = start_mock_node(6);
+        let conn = test_db();
+        let config = ChainConfig::default();
+        // Height 2 failed to store on an earlier run and was skipped
+        for (height, block) in node.blocks.iter().enumerate().filter(|(height, _)| *height != 2) {
+            insert_block(&conn, block, height as u32, &config).unwrap();
+        }
+        let rpc = Arc::new(BitcoinRpcClient::new(&url, "user", "password"));
+        let tip = rpc.get_block_count().await.unwrap();
+        let start = resume_height(&conn, tip).unwrap();
+        assert_eq!(start, 2);
+
+        let summary = index_blocks_rpc(
+            &conn, rpc, start, tip, None, 1, &config, &AtomicBool::new(false), false, &Progress::hidden(),
+        ).await.unwrap();
+        handle.stop(false).await;
+        assert_eq!((summary.indexed, summary.already_stored), (1, 3));
+        assert_eq!(resume_height(&conn, tip).unwrap(), 6);
+    }
+
+    #[actix_web::test]
+    async fn mempool_polling_stops_once_shutdown_is_set() {
+        let (_node, url, handle) = start_mock_node(1);
+        let conn = test_db();
+        let rpc = BitcoinRpcClient::new(&url, "user", "password");
+        // Set while a poll is running: the loop must not wait out the hour-long interval
+        let shutdown = AtomicBool::new(true);
+        let polled = tokio::time::timeout(
+            Duration::from_secs(5), index_mempool(&conn, &rpc, Duration::from_secs(3600), &shutdown),
+        ).await;
+        handle.stop(false).await;
+        assert!(matches!(polled, Ok(Ok(()))));
+    }
+
+    #[actix_web::test]
+    async fn zmq_block_that_fails_to_store_is_skipped() {
+        let conn = test_db();
+        let chain = store_chain(&conn, 3);
+        conn.execute_batch(
+            "CREATE TRIGGER fail_write BEFORE INSERT ON blocks WHEN NEW.height = 4 BEGIN SELECT RAISE(ABORT, 'disk full'); END;",
+        ).unwrap();
+        let third = test_block(chain[2].block_hash(), 3, vec![coinbase_tx(3, 5_000)]);
+        let fourth = test_block(third.block_hash(), 4, vec![coinbase_tx(4, 5_000)]);
+        let (sender, blocks) = mpsc::channel(4);
+        for block in [&third, &fourth] {
+            sender.send(bitcoin::consensus::serialize(block)).await.unwrap();
+        }
+        drop(sender);
+
+        let rpc = Arc::new(BitcoinRpcClient::new("http://127.0.0.1:1", "user", "password"));
+        let mut summary = IndexSummary::default();
+        let ended = index_blocks_zmq(
+            &conn, &rpc, blocks, 1, &ChainConfig::default(), &AtomicBool::new(false), &mut summary,
+        ).await;
+        // The failed block didn't end the session; the closed feed did
+        assert_eq!(ended.unwrap_err().to_string(), "ZMQ subscription ended");
+        assert_eq!((summary.indexed, summary.skipped), (1, 1));
+        assert!(summary.errors[0].contains("disk full"), "{:?}", summary.errors);
+    }
+}
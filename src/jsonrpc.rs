@@ -0,0 +1,243 @@
+use actix_web::web::Bytes;
+use actix_web::{web, HttpResponse};
+use rusqlite::Connection;
+use serde_json::{json, Value};
+use crate::cache::LookupCache;
+use crate::db::{query_block_hash_at_height, query_latest_block, query_tx_raw, DbPool};
+use crate::error::ApiError;
+use crate::handlers::{get_conn, is_valid_hash};
+
+// JSON-RPC 2.0 access to the index for tools written against bitcoind's RPC. Method names and
+// error codes follow Bitcoin Core where one exists; results are the same JSON the REST
+// endpoints return.
+
+// Codes from the JSON-RPC 2.0 spec
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+// Codes Bitcoin Core uses for the same failures
+const RPC_MISC_ERROR: i64 = -1;
+const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+const RPC_INVALID_PARAMETER: i64 = -8;
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+}
+
+impl From<rusqlite::Error> for RpcError {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::new(INTERNAL_ERROR, format!("Database error: {}", e))
+    }
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "result": result, "id": id}),
+        Err(e) => json!({
+            "jsonrpc": "2.0",
+            "error": {"code": e.code, "message": e.message},
+            "id": id
+        }),
+    }
+}
+
+// Parameter at `index` when params are positional, or `name` when they're named
+fn param<'a>(params: &'a Value, index: usize, name: &str) -> Option<&'a Value> {
+    match params {
+        Value::Array(params) => params.get(index),
+        Value::Object(params) => params.get(name),
+        _ => None,
+    }
+}
+
+fn hash_param(params: &Value, index: usize, name: &str) -> Result<String, RpcError> {
+    let value = param(params, index, name)
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("Missing parameter {}", name)))?;
+    let hash = value.as_str()
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("{} must be a string", name)))?;
+    if !is_valid_hash(hash) {
+        return Err(RpcError::new(
+            RPC_INVALID_PARAMETER,
+            format!("{} must be 64 lowercase hex characters", name),
+        ));
+    }
+    Ok(hash.to_string())
+}
+
+fn dispatch(conn: &Connection, cache: &LookupCache, method: &str, params: &Value) -> Result<Value, RpcError> {
+    match method {
+        "getblockcount" => match query_latest_block(conn)? {
+            Some((height, _)) => Ok(json!(height)),
+            None => Err(RpcError::new(RPC_MISC_ERROR, "No blocks indexed")),
+        },
+        "getbestblockhash" => match query_latest_block(conn)? {
+            Some((_, hash)) => Ok(json!(hash)),
+            None => Err(RpcError::new(RPC_MISC_ERROR, "No blocks indexed")),
+        },
+        "getblockhash" => {
+            let height = param(params, 0, "height")
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Missing parameter height"))?
+                .as_u64()
+                .and_then(|height| u32::try_from(height).ok())
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "height must be a non-negative integer"))?;
+            match query_block_hash_at_height(conn, height)? {
+                Some(hash) => Ok(json!(hash)),
+                None => Err(RpcError::new(RPC_INVALID_PARAMETER, "Block height out of range")),
+            }
+        }
+        "getblock" => {
+            let hash = hash_param(params, 0, "blockhash")?;
+            match cache.block(conn, &hash)? {
+                Some(block) => Ok(json!(block)),
+                None => Err(RpcError::new(RPC_INVALID_ADDRESS_OR_KEY, "Block not found")),
+            }
+        }
+        "gettransaction" => {
+            let txid = hash_param(params, 0, "txid")?;
+            match cache.tx(conn, &txid)? {
+                Some(tx) => Ok(json!(tx)),
+                None => Err(RpcError::new(RPC_INVALID_ADDRESS_OR_KEY, "Transaction not found")),
+            }
+        }
+        "getrawtransaction" => {
+            let txid = hash_param(params, 0, "txid")?;
+            match query_tx_raw(conn, &txid)? {
+                Some(Some(raw_data)) => Ok(json!(hex::encode(raw_data))),
+                Some(None) => Err(RpcError::new(RPC_MISC_ERROR, "Raw data not stored (indexed with --no-raw)")),
+                None => Err(RpcError::new(RPC_INVALID_ADDRESS_OR_KEY, "Transaction not found")),
+            }
+        }
+        _ => Err(RpcError::new(METHOD_NOT_FOUND, "Method not found")),
+    }
+}
+
+// Response to one call, or None for a notification (a call without an id), which gets no
+// response even when it fails
+fn handle_call(conn: &Connection, cache: &LookupCache, call: &Value) -> Option<Value> {
+    let Some(call) = call.as_object() else {
+        return Some(response(Value::Null, Err(RpcError::new(INVALID_REQUEST, "Invalid Request"))));
+    };
+    let id = call.get("id").cloned();
+    // Ids must be a string, number or null
+    if matches!(id, Some(Value::Bool(_) | Value::Array(_) | Value::Object(_))) {
+        return Some(response(Value::Null, Err(RpcError::new(INVALID_REQUEST, "Invalid Request"))));
+    }
+
+    let method = call.get("method").and_then(Value::as_str);
+    let params = call.get("params").cloned().unwrap_or(Value::Array(Vec::new()));
+    let result = match method {
+        Some(method) if call.get("jsonrpc") == Some(&json!("2.0")) && (params.is_array() || params.is_object()) => {
+            dispatch(conn, cache, method, &params)
+        }
+        _ => Err(RpcError::new(INVALID_REQUEST, "Invalid Request")),
+    };
+    id.map(|id| response(id, result))
+}
+
+fn handle_body(conn: &Connection, cache: &LookupCache, body: &[u8]) -> Option<Value> {
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(_) => return Some(response(Value::Null, Err(RpcError::new(PARSE_ERROR, "Parse error")))),
+    };
+    match request {
+        Value::Array(calls) if calls.is_empty() => {
+            Some(response(Value::Null, Err(RpcError::new(INVALID_REQUEST, "Invalid Request"))))
+        }
+        // A batch gets an array of responses in any order; Some(empty) would be invalid, so a
+        // batch of notifications gets no body at all
+        Value::Array(calls) => {
+            let responses: Vec<Value> = calls.iter().filter_map(|call| handle_call(conn, cache, call)).collect();
+            (!responses.is_empty()).then_some(Value::Array(responses))
+        }
+        call => handle_call(conn, cache, &call),
+    }
+}
+
+// POST /rpc - JSON-RPC 2.0, single calls or batches. Errors are reported in the envelope with
+// a 200, as JSON-RPC over HTTP expects; only an unreachable database is an HTTP error.
+#[utoipa::path(
+    post,
+    path = "/rpc",
+    request_body = JsonRpcRequest,
+    responses(
+        (status = 200, description = "JSON-RPC response, or an array of them for a batch", body = JsonRpcResponse),
+        (status = 204, description = "Every call was a notification"),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn post_rpc(
+    db: web::Data<DbPool>,
+    cache: web::Data<LookupCache>,
+    body: Bytes,
+) -> Result<HttpResponse, ApiError> {
+    let conn = get_conn(&db)?;
+
+    Ok(match handle_body(&conn, &cache, &body) {
+        Some(response) => HttpResponse::Ok().json(response),
+        None => HttpResponse::NoContent().finish(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ChainConfig;
+    use crate::db::insert_block;
+    use crate::test_utils::*;
+    use bitcoin::hashes::Hash;
+
+    fn call(conn: &Connection, body: &str) -> Option<Value> {
+        handle_body(conn, &LookupCache::new(0), body.as_bytes())
+    }
+
+    #[test]
+    fn dispatches_single_and_batched_calls() {
+        let conn = test_db();
+        let coinbase = coinbase_tx(0, 5_000);
+        let block = test_block(bitcoin::BlockHash::all_zeros(), 0, vec![coinbase.clone()]);
+        insert_block(&conn, &block, 0, &ChainConfig::default()).unwrap();
+        let hash = block.block_hash().to_string();
+
+        let single = call(&conn, r#"{"jsonrpc":"2.0","method":"getblockcount","id":1}"#).unwrap();
+        assert_eq!(single, json!({"jsonrpc": "2.0", "result": 0, "id": 1}));
+
+        let batch = call(&conn, &format!(
+            r#"[{{"jsonrpc":"2.0","method":"getblockhash","params":[0],"id":"a"}},
+                {{"jsonrpc":"2.0","method":"getblock","params":{{"blockhash":"{}"}},"id":"b"}},
+                {{"jsonrpc":"2.0","method":"getblockcount"}},
+                {{"jsonrpc":"2.0","method":"gettransaction","params":["{}"],"id":"c"}}]"#,
+            hash,
+            "00".repeat(32),
+        )).unwrap();
+        let batch = batch.as_array().unwrap();
+        assert_eq!(batch.len(), 3);
+        assert_eq!(batch[0]["result"], json!(hash));
+        assert_eq!(batch[1]["result"]["hash"], json!(hash));
+        assert_eq!(batch[2]["error"]["code"], json!(RPC_INVALID_ADDRESS_OR_KEY));
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        let conn = test_db();
+        assert_eq!(call(&conn, "{")
+            .unwrap()["error"]["code"], json!(PARSE_ERROR));
+        assert_eq!(call(&conn, "[]")
+            .unwrap()["error"]["code"], json!(INVALID_REQUEST));
+        assert_eq!(call(&conn, r#"{"method":"getblockcount","id":1}"#)
+            .unwrap()["error"]["code"], json!(INVALID_REQUEST));
+        assert_eq!(call(&conn, r#"{"jsonrpc":"2.0","method":"stop","id":1}"#)
+            .unwrap()["error"]["code"], json!(METHOD_NOT_FOUND));
+        assert_eq!(call(&conn, r#"{"jsonrpc":"2.0","method":"getblock","params":["xyz"],"id":1}"#)
+            .unwrap()["error"]["code"], json!(RPC_INVALID_PARAMETER));
+        assert_eq!(call(&conn, r#"[{"jsonrpc":"2.0","method":"getblockcount"}]"#), None);
+    }
+}
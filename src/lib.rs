@@ -1,3 +1,23 @@
+pub mod cache;
+pub mod config;
 pub mod db;
+pub mod decode;
+pub mod error;
+pub mod events;
+pub mod handlers;
+pub mod indexer;
+pub mod jsonrpc;
+pub mod logging;
+pub mod merkle;
+pub mod metrics;
+pub mod migrations;
 pub mod models;
+pub mod openapi;
 pub mod parser;
+pub mod progress;
+pub mod ratelimit;
+pub mod rpc;
+pub mod script;
+#[cfg(test)]
+mod test_utils;
+pub mod tls;
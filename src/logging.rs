@@ -0,0 +1,99 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::HttpMessage;
+use log::kv::{Key, Value, VisitSource};
+use std::io::Write;
+use std::time::Instant;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+// ID of the request being handled, stored in the request extensions
+#[derive(Clone)]
+pub struct RequestId(pub String);
+
+tokio::task_local! {
+    // Set while a request is being handled so every log line it produces carries the ID
+    static REQUEST_ID: String;
+}
+
+// Install the global logger. Levels still come from RUST_LOG.
+pub fn init(format: LogFormat) {
+    let mut builder = env_logger::Builder::from_default_env();
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let mut line = serde_json::Map::new();
+            line.insert("timestamp".into(), buf.timestamp_millis().to_string().into());
+            line.insert("level".into(), record.level().as_str().into());
+            line.insert("target".into(), record.target().into());
+            line.insert("message".into(), record.args().to_string().into());
+            if let Ok(request_id) = REQUEST_ID.try_with(|id| id.clone()) {
+                line.insert("request_id".into(), request_id.into());
+            }
+            let _ = record.key_values().visit(&mut JsonFields(&mut line));
+            writeln!(buf, "{}", serde_json::Value::Object(line))
+        });
+    }
+    builder.init();
+}
+
+// Copies a record's key-value pairs into the JSON line, keeping numbers as numbers
+struct JsonFields<'a>(&'a mut serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        let value = if let Some(n) = value.to_u64() {
+            n.into()
+        } else if let Some(n) = value.to_f64() {
+            n.into()
+        } else {
+            value.to_string().into()
+        };
+        self.0.insert(key.as_str().to_string(), value);
+        Ok(())
+    }
+}
+
+// Give every request a UUID, returned in the x-request-id header and attached to its log lines
+pub async fn assign_request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let request_id = uuid::Uuid::new_v4().to_string();
+    req.extensions_mut().insert(RequestId(request_id.clone()));
+
+    let mut res = REQUEST_ID.scope(request_id.clone(), next.call(req)).await?;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        res.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    Ok(res)
+}
+
+// Structured access log, used instead of middleware::Logger with --log-format json
+pub async fn log_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+    let started = Instant::now();
+
+    let res = next.call(req).await?;
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    log::info!(
+        target: "access",
+        method = method.as_str(),
+        path = path.as_str(),
+        status = res.status().as_u16(),
+        latency_ms = latency_ms;
+        "{} {} {}", method, path, res.status().as_u16()
+    );
+    Ok(res)
+}
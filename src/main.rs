@@ -1,26 +1,59 @@
-use clap::{Parser, Subcommand};
+use clap::builder::{BoolishValueParser, RangedU64ValueParser};
+use clap::{ArgAction, Parser, Subcommand};
 // use rusqlite::Connection;  // REMOVE THIS LINE
-use std::path::Path;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use actix_cors::Cors;
 use actix_web::{web, App, HttpServer, middleware};
-use env_logger;
-use reqwest;
-use serde_json::json;
-use bitcoin::consensus;
+use actix_web::http::header;
+use serde::Serialize;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
 
-mod models;
-mod db;
-mod parser;
-mod handlers;
-
-use db::*;
-use handlers::*;
+use regtest_block_explorer::cache::{self, LookupCache, RichListCache};
+use regtest_block_explorer::config::{self, ApiConfig, ChainConfig, JournalMode, OnConflict, SqliteConfig, Synchronous};
+use regtest_block_explorer::db::*;
+use regtest_block_explorer::events;
+use regtest_block_explorer::handlers::*;
+use regtest_block_explorer::indexer::{self, IndexSummary};
+use regtest_block_explorer::jsonrpc::post_rpc;
+use regtest_block_explorer::logging::{self, LogFormat};
+use regtest_block_explorer::metrics;
+use regtest_block_explorer::openapi::ApiDoc;
+use regtest_block_explorer::parser;
+use regtest_block_explorer::progress::{self, OutputFormat, Progress};
+use regtest_block_explorer::status;
+use regtest_block_explorer::ratelimit::{self, RateLimiter};
+use regtest_block_explorer::rpc::{self, BitcoinRpcClient};
+use regtest_block_explorer::tls;
 
 #[derive(Parser)]
 #[command(name = "bitcoin-explore")]
 #[command(about = "A CLI tool for Bitcoin regtest block indexing and exploration")]
 struct Cli {
+    /// Chain the data belongs to: regtest, testnet, signet or mainnet
+    #[arg(long, global = true, default_value = "regtest", value_parser = config::parse_network)]
+    network: bitcoin::Network,
+    /// Log output format; json emits one object per line with request IDs
+    #[arg(long, global = true, value_enum, default_value = "text")]
+    log_format: LogFormat,
+    /// SQLite database file; use a separate one per network to run explorers side by side
+    #[arg(long, global = true, env = "BLOCK_EXPLORER_DB", default_value = "blocks.db")]
+    db_path: PathBuf,
+    /// SQLite journal mode; wal lets the API read while the indexer writes
+    #[arg(long, global = true, value_enum, default_value = "wal")]
+    sqlite_journal_mode: JournalMode,
+    /// SQLite synchronous setting; normal may lose the last commits on power loss, full syncs every commit
+    #[arg(long, global = true, value_enum, default_value = "normal")]
+    sqlite_synchronous: Synchronous,
+    /// SQLite page cache per connection, in KiB
+    #[arg(long, global = true, default_value_t = config::DEFAULT_SQLITE_CACHE_KIB)]
+    sqlite_cache_size: u64,
+    /// Bytes of the database file SQLite may memory-map per connection (0 disables it)
+    #[arg(long, global = true, default_value_t = config::DEFAULT_SQLITE_MMAP_SIZE)]
+    sqlite_mmap_size: u64,
     #[command(subcommand)]
     command: Commands,
 }
@@ -32,113 +65,577 @@ enum Commands {
         /// Path to blocks directory for file-based indexing (optional; if not provided, uses RPC)
         #[arg(long)]
         from_file: Option<String>,
+        /// Read blocks from stdin, framed as in .blk files (magic bytes and length) unless --raw is set
+        #[arg(long, conflicts_with_all = ["from_file", "mempool", "zmq_endpoint"])]
+        from_stdin: bool,
+        /// With --from-stdin, read bare consensus-serialized blocks back to back, without magic bytes or lengths
+        #[arg(long, requires = "from_stdin")]
+        raw: bool,
+        /// Re-scan the chain from height 0 instead of resuming from the last stored block
+        #[arg(long)]
+        reindex: bool,
+        /// First height to index, instead of resuming from the last stored block
+        #[arg(long, conflicts_with_all = ["reindex", "mempool"])]
+        start_height: Option<u32>,
+        /// Last height to index, instead of the node's tip
+        #[arg(long, conflicts_with_all = ["mempool", "zmq_endpoint"])]
+        end_height: Option<u32>,
+        /// URL of the node's JSON-RPC endpoint
+        #[arg(long, env = "BITCOIN_RPC_URL", default_value = "http://127.0.0.1:18443", value_parser = rpc::parse_rpc_url)]
+        rpc_url: String,
+        /// RPC username
+        #[arg(long, env = "BITCOIN_RPC_USER", default_value = "user")]
+        rpc_user: String,
+        /// RPC password
+        #[arg(long, env = "BITCOIN_RPC_PASSWORD", default_value = "pass", hide_env_values = true)]
+        rpc_password: String,
+        /// Path to the node's .cookie file (overrides --rpc-user and --rpc-password)
+        #[arg(long)]
+        rpc_cookie: Option<String>,
+        /// Proxy to reach the node through (http, https, socks5 or socks5h URL); HTTP_PROXY/ALL_PROXY are used when not set
+        #[arg(long)]
+        rpc_proxy: Option<String>,
+        /// Number of blocks requested per JSON-RPC batch
+        #[arg(long, default_value_t = rpc::DEFAULT_BATCH_SIZE)]
+        batch_size: usize,
+        /// Times a request is retried when the node can't be reached or returns a 5xx error
+        #[arg(long, default_value_t = rpc::DEFAULT_MAX_RETRIES)]
+        rpc_retries: u32,
+        /// Delay before the first retry in milliseconds; it doubles with each further retry
+        #[arg(long, default_value_t = rpc::DEFAULT_RETRY_DELAY_MS)]
+        rpc_retry_delay_ms: u64,
+        /// getblock verbosity: 0 fetches raw hex, 2 fetches the node's decoded JSON along with fees
+        #[arg(long, default_value = "0", value_parser = rpc::parse_rpc_verbosity)]
+        rpc_verbosity: u8,
+        /// Number of concurrent RPC fetch workers, or of workers parsing .blk files with --from-file
+        #[arg(long, default_value = "1")]
+        jobs: usize,
+        /// Poll the node's mempool instead of indexing blocks (runs until Ctrl+C)
+        #[arg(long)]
+        mempool: bool,
+        /// Seconds between polls with --mempool or --watch
+        #[arg(long, default_value = "10")]
+        interval: u64,
+        /// Node's zmqpubrawblock endpoint; after catching up via RPC, index blocks as they are pushed (runs until Ctrl+C)
+        #[arg(long)]
+        zmq_endpoint: Option<String>,
+        /// After catching up, poll the node's block count every --interval seconds and index new blocks (runs until Ctrl+C)
+        #[arg(long, conflicts_with_all = ["from_file", "from_stdin", "mempool", "zmq_endpoint", "end_height", "dry_run"])]
+        watch: bool,
+        /// Don't store raw blocks and transactions, roughly halving the database; /block/{hash}/raw, /tx/{txid}/raw and /tx/{txid}/decode then answer 409
+        #[arg(long, conflicts_with = "mempool")]
+        no_raw: bool,
+        /// What to do with a block that's already stored: skip leaves it as it is; update rewrites it and its transactions
+        #[arg(long, value_enum, default_value = "skip", conflicts_with = "mempool")]
+        on_conflict: OnConflict,
+        /// Parse or fetch and validate every block without writing anything, then report what would be indexed
+        #[arg(long, conflicts_with_all = ["mempool", "zmq_endpoint"])]
+        dry_run: bool,
+        /// Show a progress bar with rate and ETA instead of a line per block (on or off; lines are used when stderr isn't a terminal)
+        #[arg(long, default_value = "on", action = ArgAction::Set, value_parser = BoolishValueParser::new())]
+        progress: bool,
+        /// Output: text prints status lines and a summary; json prints only a JSON summary, with status lines on stderr
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+    /// Check the database for inconsistencies, including against each block's raw data
+    Verify {
+        /// Output: text prints each problem and a summary; json prints only a JSON summary, with status lines on stderr
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+    /// Compact the database with VACUUM and refresh query planner statistics with ANALYZE
+    Maintain {
+        /// Also run PRAGMA optimize
+        #[arg(long)]
+        optimize: bool,
     },
     /// Start the web server for block exploration API
     Serve {
+        /// Address to listen on (IPv4 or IPv6); use 0.0.0.0 or :: to accept connections from other hosts
+        #[arg(long, default_value = "127.0.0.1", value_parser = parse_host)]
+        host: IpAddr,
         /// Port to run the server on
         #[arg(short, long, default_value = "8080")]
         port: u16,
+        /// Origins allowed to call the API from a browser (comma-separated, or * for any)
+        #[arg(long, default_value = "*", value_delimiter = ',')]
+        cors_origin: Vec<String>,
+        /// Requests per minute allowed from each client IP (unlimited if not set)
+        #[arg(long)]
+        rate_limit: Option<u32>,
+        /// Compress responses with gzip, deflate, brotli or zstd when the client's Accept-Encoding allows it (on or off)
+        #[arg(long, default_value = "on", action = ArgAction::Set, value_parser = BoolishValueParser::new())]
+        compression: bool,
+        /// Most items any paginated endpoint returns per page; larger ?limit= values are capped to it
+        #[arg(long, default_value_t = config::DEFAULT_MAX_PAGE_SIZE, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+        max_page_size: usize,
+        /// Blocks and transactions each kept in the in-memory lookup cache (0 disables it)
+        #[arg(long, default_value_t = cache::DEFAULT_CACHE_SIZE)]
+        cache_size: usize,
+        /// PEM certificate (chain) to serve HTTPS with; requires --tls-key
+        #[arg(long, requires = "tls_key")]
+        tls_cert: Option<PathBuf>,
+        /// PEM private key for --tls-cert
+        #[arg(long, requires = "tls_cert")]
+        tls_key: Option<PathBuf>,
+        /// URL of a node's JSON-RPC endpoint to broadcast POST /tx transactions through (POST /tx answers 501 if not set)
+        #[arg(long, value_parser = rpc::parse_rpc_url)]
+        rpc_url: Option<String>,
+        /// RPC username for --rpc-url
+        #[arg(long, env = "BITCOIN_RPC_USER", default_value = "user")]
+        rpc_user: String,
+        /// RPC password for --rpc-url
+        #[arg(long, env = "BITCOIN_RPC_PASSWORD", default_value = "pass", hide_env_values = true)]
+        rpc_password: String,
+        /// Path to the node's .cookie file (overrides --rpc-user and --rpc-password)
+        #[arg(long, requires = "rpc_url")]
+        rpc_cookie: Option<String>,
     },
 }
 
+// What an index or verify run prints with --format json: for verify, the totals are what the
+// database holds and the errors are the problems found
+#[derive(Serialize)]
+struct RunSummary {
+    blocks_indexed: u64,
+    transactions_indexed: u64,
+    duration_secs: f64,
+    errors: Vec<String>,
+}
+
+fn print_summary(summary: &RunSummary) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string(summary)?);
+    Ok(())
+}
+
+// Bytes on disk, counting the WAL file next to the database
+fn database_size(db_path: &Path) -> u64 {
+    let mut wal = db_path.as_os_str().to_owned();
+    wal.push("-wal");
+    [db_path.as_os_str(), &wal].iter()
+        .filter_map(|path| std::fs::metadata(path).ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+fn parse_host(s: &str) -> Result<IpAddr, String> {
+    s.parse().map_err(|_| format!("'{}' is not an IP address; expected IPv4 like 0.0.0.0 or IPv6 like ::", s))
+}
+
+// For responses that change with every new block: caches may store them but must revalidate
+fn no_cache() -> middleware::DefaultHeaders {
+    middleware::DefaultHeaders::new().add((header::CACHE_CONTROL, "no-cache"))
+}
+
+// CORS policy for the API. Preflight OPTIONS requests are answered by the middleware.
+fn build_cors(origins: &[String]) -> Cors {
+    let cors = Cors::default()
+        .allowed_methods(vec!["GET", "POST", "OPTIONS"])
+        .allow_any_header()
+        .max_age(3600);
+
+    if origins.iter().any(|origin| origin == "*") {
+        cors.allow_any_origin().send_wildcard()
+    } else {
+        origins.iter().fold(cors, |cors, origin| cors.allowed_origin(origin))
+    }
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    env_logger::init();
-
     let cli = Cli::parse();
-    let db_path = Path::new("blocks.db");
-    let conn = Arc::new(Mutex::new(init_db(db_path)?));
+    logging::init(cli.log_format);
+    let log_format = cli.log_format;
+    let chain = ChainConfig::new(cli.network);
+    let db_path = cli.db_path.as_path();
+    let sqlite = SqliteConfig {
+        journal_mode: cli.sqlite_journal_mode,
+        synchronous: cli.sqlite_synchronous,
+        cache_size_kib: cli.sqlite_cache_size,
+        mmap_size: cli.sqlite_mmap_size,
+    };
+    let conn = init_db(db_path, &chain, &sqlite)?;
 
     match cli.command {
-        Commands::Index { from_file } => {
-            if let Some(path) = from_file {
-                println!("Indexing from files in: {}", path);
-                parser::index_blocks(&conn.lock().unwrap(), Path::new(&path)).await?;
-                println!("File-based indexing complete!");
-            } else {
-                println!("Block Explorer Indexer");
-                println!("Fetching blocks from regtest node at http://127.0.0.1:18443");
-
-                let client = reqwest::Client::new();
-
-                // Get block count
-                let response = client
-                    .post("http://127.0.0.1:18443")
-                    .basic_auth("user", Some("pass"))
-                    .json(&json!({"jsonrpc": "1.0", "id": "1", "method": "getblockcount", "params": []}))
-                    .send()
-                    .await?;
-                let result: serde_json::Value = response.json().await?;
-                let count = result["result"].as_u64().unwrap_or(0) as u32;
-
-                println!("Starting block indexing... Total blocks: {}", count);
-
-                for height in 0..count {
-                    // Get block hash
-                    let response = client
-                        .post("http://127.0.0.1:18443")
-                        .basic_auth("user", Some("pass"))
-                        .json(&json!({"jsonrpc": "1.0", "id": "1", "method": "getblockhash", "params": [height]}))
-                        .send()
-                        .await?;
-                    let result: serde_json::Value = response.json().await?;
-                    let hash = result["result"].as_str().unwrap();
-
-                    // Get block hex
-                    let response = client
-                        .post("http://127.0.0.1:18443")
-                        .basic_auth("user", Some("pass"))
-                        .json(&json!({"jsonrpc": "1.0", "id": "1", "method": "getblock", "params": [hash, 0]}))
-                        .send()
-                        .await?;
-                    let result: serde_json::Value = response.json().await?;
-                    let hex = result["result"].as_str().unwrap();
-
-                    let block_bytes = hex::decode(hex).unwrap();
-                    let block: bitcoin::Block = consensus::deserialize(&block_bytes).unwrap();
-
-                    insert_block(&conn.lock().unwrap(), &block, height)?;
-                    println!("Indexed block at height {}: {}", height, hash);
+        Commands::Index {
+            from_file,
+            from_stdin,
+            raw,
+            reindex,
+            start_height,
+            end_height,
+            rpc_url,
+            rpc_user,
+            rpc_password,
+            rpc_cookie,
+            rpc_proxy,
+            batch_size,
+            rpc_retries,
+            rpc_retry_delay_ms,
+            rpc_verbosity,
+            jobs,
+            mempool,
+            interval,
+            zmq_endpoint,
+            watch,
+            no_raw,
+            on_conflict,
+            dry_run,
+            progress,
+            format,
+        } => {
+            let started = Instant::now();
+            if format == OutputFormat::Json {
+                progress::status_to_stderr();
+            }
+            let chain = ChainConfig { store_raw: !no_raw, on_conflict, ..chain };
+            let outcome: anyhow::Result<IndexSummary> = async {
+                if let (Some(start), Some(end)) = (start_height, end_height)
+                    && start > end
+                {
+                    anyhow::bail!("--start-height {} is above --end-height {}", start, end);
+                }
+                if dry_run {
+                    status!("Dry run: blocks are validated but not written to {}", db_path.display());
                 }
+                if from_stdin {
+                    status!("Indexing blocks from stdin");
+                    let heights = start_height.unwrap_or(0)..=end_height.unwrap_or(u32::MAX);
+                    let summary = parser::index_stream(
+                        &conn, std::io::stdin().lock(), raw, &chain, &indexer::shutdown_flag(), heights, dry_run,
+                    )?;
+                    Ok(summary)
+                } else if let Some(path) = from_file {
+                    status!("Indexing from files in: {}", path);
+                    let heights = start_height.unwrap_or(0)..=end_height.unwrap_or(u32::MAX);
+                    let summary = parser::index_blocks(&conn, Path::new(&path), &chain, &indexer::shutdown_flag(), heights, jobs, dry_run, progress).await?;
+                    if !dry_run {
+                        status!("File-based indexing complete!");
+                    }
+                    Ok(summary)
+                } else {
+                    status!("Block Explorer Indexer");
+                    let mut rpc = match rpc_cookie {
+                        Some(cookie_path) => BitcoinRpcClient::from_cookie(&rpc_url, Path::new(&cookie_path))?,
+                        None => BitcoinRpcClient::new(&rpc_url, &rpc_user, &rpc_password),
+                    }
+                        .with_batch_size(batch_size)
+                        .with_verbosity(rpc_verbosity)
+                        .with_retries(rpc_retries, Duration::from_millis(rpc_retry_delay_ms));
+                    if let Some(proxy) = &rpc_proxy {
+                        rpc = rpc.with_proxy(proxy)?;
+                    }
+                    if mempool {
+                        status!("Polling mempool at {} every {}s (Ctrl+C to stop)", rpc.url(), interval);
+                        indexer::index_mempool(&conn, &rpc, Duration::from_secs(interval), &indexer::shutdown_flag()).await?;
+                        Ok(IndexSummary::default())
+                    } else {
+                        status!("Fetching blocks from node at {}", rpc.url());
 
-                let block_count: u32 = conn.lock().unwrap().query_row(
-                    "SELECT COUNT(*) FROM blocks", [], |row| row.get(0)
-                )?;
-                let tx_count: u64 = conn.lock().unwrap().query_row(
-                    "SELECT COUNT(*) FROM transactions", [], |row| row.get(0)
-                )?;
+                        // Subscribe before backfilling so blocks found in the meantime are queued, not missed
+                        let pushed_blocks = zmq_endpoint.as_deref().map(indexer::subscribe_raw_blocks).transpose()?;
 
-                println!("Indexing complete!");
-                println!("Blocks: {}", block_count);
-                println!("Transactions: {}", tx_count);
+                        let tip = rpc.get_block_count().await?;
+                        if let Some(end) = end_height
+                            && end > tip
+                        {
+                            anyhow::bail!("--end-height {} is above the node's tip {}", end, tip);
+                        }
+
+                        // Resume from the last stored block unless a full re-scan or a start height was requested
+                        let start_height = if let Some(start) = start_height {
+                            start
+                        } else if reindex {
+                            0
+                        } else {
+                            indexer::resume_height(&conn, tip)?
+                        };
+
+                        let last_height = end_height.unwrap_or(tip);
+                        status!("Starting block indexing from height {} to {}... Node tip: {}", start_height, last_height, tip);
+
+                        let shutdown = indexer::shutdown_flag();
+                        let rpc = Arc::new(rpc);
+                        let bar = Progress::blocks(progress, (last_height + 1).saturating_sub(start_height) as u64);
+                        let mut summary = indexer::index_blocks_rpc(
+                            &conn, Arc::clone(&rpc), start_height, tip, end_height, jobs, &chain, &shutdown, dry_run, &bar,
+                        ).await?;
+                        bar.finish();
+
+                        if dry_run {
+                            status!(
+                                "Dry run{}: {} blocks with {} transactions would be indexed, {} failed validation, {} couldn't be fetched",
+                                if summary.interrupted { " (stopped early)" } else { "" },
+                                summary.indexed, summary.transactions, summary.invalid, summary.skipped
+                            );
+                            return Ok(summary);
+                        }
+
+                        if let Some(blocks) = pushed_blocks
+                            && let Some(endpoint) = &zmq_endpoint
+                            && !summary.interrupted
+                        {
+                            status!("Caught up; indexing blocks pushed from {} (Ctrl+C to stop)", endpoint);
+                            indexer::index_blocks_zmq(&conn, &rpc, blocks, jobs, &chain, &shutdown, &mut summary).await?;
+                        }
+
+                        if watch && !summary.interrupted {
+                            status!("Caught up; polling {} for new blocks every {}s (Ctrl+C to stop)", rpc.url(), interval);
+                            indexer::index_blocks_watch(&conn, &rpc, Duration::from_secs(interval), jobs, &chain, &shutdown, &mut summary).await?;
+                        }
+
+                        let counts = query_counts_fast(&conn)?;
+
+                        if summary.interrupted {
+                            status!("Indexing stopped cleanly; run index again to resume");
+                        } else {
+                            status!("Indexing complete!");
+                        }
+                        status!("Indexed this run: {}", summary.indexed);
+                        if summary.already_stored > 0 {
+                            status!("Already stored: {} (left as they were; --on-conflict update rewrites them)", summary.already_stored);
+                        }
+                        status!("Blocks: {}", counts.blocks);
+                        status!("Transactions: {}", counts.transactions);
+                        if summary.skipped > 0 {
+                            status!("Skipped: {} (run with --reindex to retry)", summary.skipped);
+                        }
+                        Ok(summary)
+                    }
+                }
+            }.await;
+
+            match format {
+                OutputFormat::Text => {
+                    outcome?;
+                }
+                OutputFormat::Json => {
+                    let (summary, failed) = match outcome {
+                        Ok(summary) => (summary, false),
+                        Err(e) => (IndexSummary { errors: vec![e.to_string()], ..Default::default() }, true),
+                    };
+                    print_summary(&RunSummary {
+                        blocks_indexed: summary.indexed as u64,
+                        transactions_indexed: summary.transactions as u64,
+                        duration_secs: started.elapsed().as_secs_f64(),
+                        errors: summary.errors,
+                    })?;
+                    if failed {
+                        std::process::exit(1);
+                    }
+                }
             }
         }
-        Commands::Serve { port } => {
-            println!("Starting web server on http://127.0.0.1:{}", port);
+        Commands::Verify { format } => {
+            let started = Instant::now();
+            if format == OutputFormat::Json {
+                progress::status_to_stderr();
+            }
+            // One line per problem, printed as found and kept for the JSON summary
+            let mut errors = Vec::new();
+            let mut report = |line: String| {
+                progress::status(&line);
+                errors.push(line);
+            };
+
+            status!("Verifying database...");
+            let mismatches = verify_tx_counts(&conn)?;
+            for (hash, height, stored, actual) in &mismatches {
+                report(format!(
+                    "Block {} at height {}: stored tx_count {}, found {} transactions",
+                    hash, height, stored, actual
+                ));
+            }
+
+            let raw_problems = verify_raw_blocks(&conn)?;
+            for (hash, height, problem) in &raw_problems {
+                report(format!("Block {} at height {}: {}", hash, height, problem));
+            }
+
+            let orphaned = query_orphaned_txs(&conn)?;
+            for (txid, block_hash) in &orphaned {
+                report(format!("Transaction {}: block {} is not stored", txid, block_hash));
+            }
+
+            // The running totals behind /stats are compared with a full recount and reset if they've drifted
+            let exact = query_counts_exact(&conn)?;
+            let drifted: Vec<_> = counter_entries(&query_counts_fast(&conn)?).into_iter()
+                .zip(counter_entries(&exact))
+                .filter(|((_, running), (_, counted))| running != counted)
+                .collect();
+            for ((key, running), (_, counted)) in &drifted {
+                report(format!("Counter {}: running total {}, recounted {}", key, running, counted));
+            }
+            if !drifted.is_empty() {
+                reset_counts(&conn, &exact)?;
+                status!("Counters reset to the recounted totals");
+            }
+
+            let problems = mismatches.len() + raw_problems.len() + orphaned.len() + drifted.len();
+            if format == OutputFormat::Json {
+                print_summary(&RunSummary {
+                    blocks_indexed: exact.blocks as u64,
+                    transactions_indexed: exact.transactions as u64,
+                    duration_secs: started.elapsed().as_secs_f64(),
+                    errors,
+                })?;
+            }
+            if problems == 0 {
+                status!("No problems found");
+            } else {
+                status!(
+                    "Found {} problems: {} tx_count mismatches, {} raw_data mismatches, {} orphaned transactions, {} drifted counters",
+                    problems, mismatches.len(), raw_problems.len(), orphaned.len(), drifted.len()
+                );
+                std::process::exit(1);
+            }
+        }
+        Commands::Maintain { optimize } => {
+            let before = database_size(db_path);
+            println!("Running VACUUM and ANALYZE{}...", if optimize { " and PRAGMA optimize" } else { "" });
+            vacuum_and_analyze(&conn, optimize)?;
+            let after = database_size(db_path);
+            println!(
+                "Database size: {} bytes before, {} bytes after ({} bytes freed)",
+                before, after, before.saturating_sub(after)
+            );
+        }
+        Commands::Serve {
+            host, port, cors_origin, rate_limit, compression, max_page_size, cache_size, tls_cert, tls_key,
+            rpc_url, rpc_user, rpc_password, rpc_cookie,
+        } => {
+            let addr = SocketAddr::new(host, port);
+            // Both or neither are set; clap enforces the pairing
+            let tls_config = match (&tls_cert, &tls_key) {
+                (Some(cert), Some(key)) => Some(tls::load_server_config(cert, key)?),
+                _ => None,
+            };
+            let scheme = if tls_config.is_some() { "https" } else { "http" };
+            println!("Starting web server on {}://{}", scheme, addr);
             println!("Available endpoints:");
             println!("  GET /block/{{hash}} - Get block by hash");
             println!("  GET /block/height/{{height}} - Get block by height");
+            println!("  GET /block/{{hash}}/next, /block/{{hash}}/prev - Get the adjacent block's summary");
+            println!("  GET /block/{{hash}}/txids?page=1&limit=20 - Get a block's txids (also /block/height/{{height}}/txids)");
+            println!("  GET /block/{{hash}}/transactions?page=1&limit=20 - Get full details of a block's transactions");
+            println!("  GET /block/height/{{height}}/tx/{{index}} - Get the transaction at a position in a block (0 is the coinbase)");
+            println!("  GET /block/{{hash}}/raw?format=hex|bin - Get raw block (also /block/height/{{height}}/raw)");
+            println!("  GET /block/{{hash}}/filter - Get the block's BIP158 basic filter as hex");
             println!("  GET /tx/{{txid}} - Get transaction by ID");
+            println!("  GET /tx/{{txid}}/raw - Get raw transaction hex (also /tx/{{txid}}/hex)");
+            println!("  GET /tx/{{txid}}/decode - Get a decoded transaction with script asm, like decoderawtransaction");
+            println!("  GET /tx/{{txid}}/merkle-proof - Get a Merkle inclusion proof for a confirmed transaction");
+            println!("  GET /tip - Get the best block's height, hash and timestamp");
             println!("  GET /blocks/latest?limit=10 - Get latest blocks");
             println!("  GET /stats - Get blockchain statistics");
-            println!("  GET /health - Health check");
-            println!("  GET /blocks?page=1&limit=20 - Get all blocks with pagination");
-            let conn_clone = Arc::clone(&conn);
-            HttpServer::new(move || {
-                App::new()
-                    .wrap(middleware::Logger::default())
-                    .app_data(web::Data::new(conn_clone.clone()))
+            println!("  GET /stats/daily?days=30 - Get per-day block, transaction and volume totals");
+            println!("  GET /fee-estimates?blocks=6 - Get min/median/max fee rates paid in recent blocks");
+            println!("  GET /health/live - Liveness check (also /health)");
+            println!("  GET /health/ready - Readiness check (database reachable)");
+            println!("  GET /metrics - Prometheus metrics");
+            println!("  GET /openapi.json - OpenAPI 3 document (Swagger UI at /docs/)");
+            println!("  GET /blocks?page=1&limit=20&sort=height&order=desc&after=...&before=... - Get all blocks with pagination, optionally within a time range");
+            println!("  GET /blocks/range?from=0&to=100 - Get blocks in a height range");
+            println!("  GET /export/blocks.csv?from=0&to=100 - Download blocks in a height range as CSV");
+            println!("  GET /ws/blocks - WebSocket stream of new blocks");
+            println!("  GET /address/{{address}}?page=1&limit=20 - Get address balance and history");
+            println!("  GET /address/{{address}}/history?limit=20&cursor=... - Get address history by cursor");
+            println!("  GET /utxos/{{address}}?min_confirmations=0 - Get unspent outputs for an address");
+            println!("  GET /rich-list?page=1&limit=20 - Get addresses ranked by balance");
+            println!("  GET /mempool?page=1&limit=20 - Get pending transactions");
+            println!("  GET /op_returns?page=1&limit=20 - Get OP_RETURN payloads");
+            println!("  GET /search/{{query}} - Find a block (hash or height), transaction or address");
+            println!("  POST /tx - Broadcast a raw transaction (hex body) through the node at --rpc-url");
+            println!("  POST /rpc - JSON-RPC 2.0 (getblockcount, getblockhash, getblock, gettransaction, ...), batches allowed");
+            drop(conn);
+            let pool = init_pool(db_path, sqlite)?;
+            tokio::spawn(events::watch_new_blocks(pool.clone(), Duration::from_secs(1)));
+            // Created once so every worker shares the same buckets and cache entries
+            let rate_limiter = rate_limit.map(|per_minute| web::Data::new(RateLimiter::new(per_minute)));
+            let lookup_cache = web::Data::new(LookupCache::new(cache_size));
+            let rich_list = web::Data::new(RichListCache::new(cache::RICH_LIST_TTL));
+            // Not retried: a client whose broadcast failed can simply post it again
+            let broadcast_rpc = match (rpc_url, rpc_cookie) {
+                (Some(url), Some(cookie_path)) => Some(BitcoinRpcClient::from_cookie(&url, Path::new(&cookie_path))?),
+                (Some(url), None) => Some(BitcoinRpcClient::new(&url, &rpc_user, &rpc_password)),
+                (None, _) => None,
+            }
+                .map(|rpc| web::Data::new(rpc.with_retries(0, Duration::ZERO)));
+            let api = ApiConfig::new(max_page_size);
+            let openapi = ApiDoc::openapi();
+            let server = HttpServer::new(move || {
+                let app = App::new();
+                let app = match &rate_limiter {
+                    Some(rate_limiter) => app.app_data(rate_limiter.clone()),
+                    None => app,
+                };
+                let app = match &broadcast_rpc {
+                    Some(rpc) => app.app_data(rpc.clone()),
+                    None => app,
+                };
+                app
+                    .wrap(middleware::from_fn(ratelimit::limit_requests))
+                    .wrap(compress_responses(compression))
+                    .wrap(build_cors(&cors_origin))
+                    .wrap(middleware::from_fn(metrics::track_requests))
+                    .wrap(middleware::Condition::new(log_format == LogFormat::Text, middleware::Logger::default()))
+                    .wrap(middleware::Condition::new(log_format == LogFormat::Json, middleware::from_fn(logging::log_requests)))
+                    .wrap(middleware::from_fn(logging::assign_request_id))
+                    .app_data(web::Data::new(pool.clone()))
+                    .app_data(web::Data::new(chain))
+                    .app_data(web::Data::new(api))
+                    .app_data(lookup_cache.clone())
+                    .app_data(rich_list.clone())
+                    .app_data(web::PathConfig::default().error_handler(path_error))
                     .route("/block/{hash}", web::get().to(get_block))
                     .route("/block/height/{height}", web::get().to(get_block_by_height))
+                    .route("/block/{hash}/next", web::get().to(get_next_block))
+                    .route("/block/{hash}/prev", web::get().to(get_prev_block))
+                    .route("/block/{hash}/txids", web::get().to(get_block_txids))
+                    .route("/block/height/{height}/txids", web::get().to(get_block_txids_by_height))
+                    .route("/block/{hash}/transactions", web::get().to(get_block_transactions))
+                    .route("/block/height/{height}/tx/{index}", web::get().to(get_block_tx_at_index))
+                    .route("/block/{hash}/raw", web::get().to(get_block_raw))
+                    .route("/block/{hash}/filter", web::get().to(get_block_filter))
+                    .route("/block/height/{height}/raw", web::get().to(get_block_raw_by_height))
                     .route("/tx/{txid}", web::get().to(get_tx))
-                    .route("/blocks/latest", web::get().to(get_latest_blocks))
-                    .route("/stats", web::get().to(get_stats))
+                    .route("/tx", web::post().to(post_tx))
+                    .route("/tx/{txid}/raw", web::get().to(get_tx_raw))
+                    .route("/tx/{txid}/hex", web::get().to(get_tx_hex))
+                    .route("/tx/{txid}/decode", web::get().to(get_tx_decode))
+                    .route("/tx/{txid}/merkle-proof", web::get().to(get_tx_merkle_proof))
+                    .service(web::resource("/tip").wrap(no_cache()).route(web::get().to(get_tip)))
+                    .service(web::resource("/blocks/latest").wrap(no_cache()).route(web::get().to(get_latest_blocks)))
+                    .route("/blocks/range", web::get().to(get_blocks_range))
+                    .route("/export/blocks.csv", web::get().to(export_blocks_csv))
+                    .service(web::resource("/stats").wrap(no_cache()).route(web::get().to(get_stats)))
+                    .route("/stats/daily", web::get().to(get_daily_stats))
+                    .route("/fee-estimates", web::get().to(get_fee_estimates))
                     .route("/health", web::get().to(health_check))
+                    .route("/health/live", web::get().to(health_check))
+                    .route("/health/ready", web::get().to(readiness_check))
+                    .route("/metrics", web::get().to(get_metrics))
                     .route("/blocks", web::get().to(get_all_blocks))
-            })
-            .bind(("127.0.0.1", port))?
-            .run()
-            .await?;
+                    .route("/address/{address}", web::get().to(get_address))
+                    .route("/address/{address}/history", web::get().to(get_address_history))
+                    .route("/ws/blocks", web::get().to(ws_blocks))
+                    .route("/utxos/{address}", web::get().to(get_address_utxos))
+                    .route("/rich-list", web::get().to(get_rich_list))
+                    .service(web::resource("/mempool").wrap(no_cache()).route(web::get().to(get_mempool)))
+                    .route("/op_returns", web::get().to(get_op_returns))
+                    .route("/search/{query}", web::get().to(get_search))
+                    .route("/rpc", web::post().to(post_rpc))
+                    .service(web::redirect("/docs", "/docs/"))
+                    .service(SwaggerUi::new("/docs/{_:.*}").url("/openapi.json", openapi.clone()))
+            });
+            let server = match tls_config {
+                Some(tls_config) => server.bind_rustls_0_23(addr, tls_config)?,
+                None => server.bind(addr)?,
+            };
+            server.run().await?;
+            // run() returns once actix has drained in-flight requests after Ctrl+C/SIGTERM
+            println!("Server shut down cleanly");
         }
     }
 
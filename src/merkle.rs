@@ -0,0 +1,71 @@
+use bitcoin::hashes::{sha256d, Hash};
+use bitcoin::{TxMerkleNode, Txid};
+
+fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut data = [0u8; 64];
+    data[..32].copy_from_slice(left);
+    data[32..].copy_from_slice(right);
+    sha256d::Hash::hash(&data).to_byte_array()
+}
+
+// Sibling hashes from the transaction at `index` up to the root, lowest level first.
+// As in the block header, a level with an odd number of nodes pairs its last node with itself.
+pub fn merkle_branch(txids: &[Txid], index: usize) -> Vec<TxMerkleNode> {
+    let mut level: Vec<[u8; 32]> = txids.iter().map(|txid| txid.to_byte_array()).collect();
+    let mut index = index;
+    let mut branch = Vec::new();
+
+    while level.len() > 1 {
+        let sibling = (index ^ 1).min(level.len() - 1);
+        branch.push(TxMerkleNode::from_byte_array(level[sibling]));
+        level = level.chunks(2)
+            .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+        index /= 2;
+    }
+    branch
+}
+
+// Fold a branch back up from the transaction to the root it proves
+pub fn merkle_root_from_branch(txid: Txid, index: usize, branch: &[TxMerkleNode]) -> TxMerkleNode {
+    let mut index = index;
+    let mut node = txid.to_byte_array();
+    for sibling in branch {
+        let sibling = sibling.to_byte_array();
+        node = if index.is_multiple_of(2) {
+            hash_pair(&node, &sibling)
+        } else {
+            hash_pair(&sibling, &node)
+        };
+        index /= 2;
+    }
+    TxMerkleNode::from_byte_array(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn branches_fold_back_to_the_header_root_with_an_odd_tx_count() {
+        let coinbase = coinbase_tx(1, 5_000);
+        let mut txdata = vec![coinbase.clone()];
+        txdata.extend((0..4).map(|i| spend_tx(&coinbase, 0, 1_000 + i)));
+        let block = test_block(bitcoin::BlockHash::all_zeros(), 1, txdata);
+        let txids: Vec<Txid> = block.txdata.iter().map(|tx| tx.compute_txid()).collect();
+
+        for index in 1..txids.len() {
+            let branch = merkle_branch(&txids, index);
+            assert_eq!(branch.len(), 3);
+            assert_eq!(merkle_root_from_branch(txids[index], index, &branch), block.header.merkle_root, "tx {}", index);
+        }
+
+        // The fifth txid has no sibling on the bottom level, so it's paired with itself
+        let branch = merkle_branch(&txids, 4);
+        assert_eq!(branch[0], TxMerkleNode::from_byte_array(txids[4].to_byte_array()));
+        // A branch only proves the position it was built for
+        let branch = merkle_branch(&txids, 3);
+        assert_ne!(merkle_root_from_branch(txids[3], 2, &branch), block.header.merkle_root);
+    }
+}
@@ -0,0 +1,60 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use prometheus::{Histogram, IntCounter, IntCounterVec};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+pub static BLOCKS_INDEXED: LazyLock<IntCounter> = LazyLock::new(|| {
+    prometheus::register_int_counter!("blocks_indexed_total", "Blocks inserted into the database").unwrap()
+});
+
+pub static TRANSACTIONS_INDEXED: LazyLock<IntCounter> = LazyLock::new(|| {
+    prometheus::register_int_counter!("transactions_indexed_total", "Transactions inserted into the database").unwrap()
+});
+
+pub static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    prometheus::register_int_counter_vec!("http_requests_total", "HTTP requests by route", &["endpoint"]).unwrap()
+});
+
+pub static CACHE_LOOKUPS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    prometheus::register_int_counter_vec!(
+        "cache_lookups_total",
+        "Block and transaction cache lookups by outcome",
+        &["cache", "result"]
+    ).unwrap()
+});
+
+pub static DB_QUERY_DURATION: LazyLock<Histogram> = LazyLock::new(|| {
+    prometheus::register_histogram!(
+        "db_query_duration_seconds",
+        "Time spent executing SQLite statements",
+        vec![0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0]
+    ).unwrap()
+});
+
+// SQLite profile callback, installed on pooled connections, so every statement is timed
+pub fn record_db_query(_sql: &str, duration: Duration) {
+    DB_QUERY_DURATION.observe(duration.as_secs_f64());
+}
+
+// Middleware counting requests by route pattern (e.g. /block/{hash}) to keep label cardinality low
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let endpoint = req.match_pattern().unwrap_or_else(|| "unmatched".to_string());
+    HTTP_REQUESTS.with_label_values(&[endpoint.as_str()]).inc();
+    next.call(req).await
+}
+
+// Current values of all registered metrics in the Prometheus text format
+pub fn render() -> Result<String, prometheus::Error> {
+    // Metrics register on first use; force them so unused ones still show up as zero
+    LazyLock::force(&BLOCKS_INDEXED);
+    LazyLock::force(&TRANSACTIONS_INDEXED);
+    LazyLock::force(&HTTP_REQUESTS);
+    LazyLock::force(&CACHE_LOOKUPS);
+    LazyLock::force(&DB_QUERY_DURATION);
+    prometheus::TextEncoder::new().encode_to_string(&prometheus::gather())
+}
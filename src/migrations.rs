@@ -0,0 +1,497 @@
+use bitcoin::hashes::Hash;
+use rusqlite::{Connection, Result};
+use crate::config::ChainConfig;
+use crate::db::{
+    block_weight_and_stripped_size, chainwork_after, compute_block_filter, insert_mempool_spends, insert_tx,
+    query_counts_exact, reset_counts,
+};
+
+// A schema change applied on top of the base tables created by init_db.
+// `rebuild` marks changes that add data derived from transactions, which existing rows
+// lack until they're re-derived from the stored raw blocks.
+struct Migration {
+    description: &'static str,
+    apply: fn(&Connection) -> Result<()>,
+    rebuild: bool,
+}
+
+// Applied in order; a migration's version is its position in this list plus one.
+// Only ever append to this list.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        description: "Add transaction version, lock_time, size, vsize and weight",
+        apply: add_transaction_metadata,
+        rebuild: true,
+    },
+    Migration {
+        description: "Add address index",
+        apply: add_address_index,
+        rebuild: true,
+    },
+    Migration {
+        description: "Add mempool table",
+        apply: add_mempool,
+        rebuild: false,
+    },
+    Migration {
+        description: "Index blocks by height and timestamp, transactions by block",
+        apply: add_lookup_indexes,
+        rebuild: false,
+    },
+    Migration {
+        description: "Store per-block transaction count",
+        apply: add_block_tx_count,
+        rebuild: false,
+    },
+    Migration {
+        description: "Flag coinbase transactions",
+        apply: add_is_coinbase,
+        rebuild: false,
+    },
+    Migration {
+        description: "Store each transaction's position in its block",
+        apply: add_index_in_block,
+        rebuild: true,
+    },
+    Migration {
+        description: "Store transaction fees",
+        apply: add_transaction_fee,
+        rebuild: true,
+    },
+    Migration {
+        description: "Add OP_RETURN payload table",
+        apply: add_op_returns,
+        rebuild: true,
+    },
+    Migration {
+        description: "Add spending index",
+        apply: add_spends,
+        rebuild: true,
+    },
+    Migration {
+        description: "Store SegWit and Taproot flags",
+        apply: add_tx_flags,
+        rebuild: true,
+    },
+    Migration {
+        description: "Classify transaction inputs",
+        apply: classify_inputs,
+        rebuild: true,
+    },
+    Migration {
+        description: "Keep running block and transaction totals",
+        apply: add_metadata,
+        rebuild: false,
+    },
+    Migration {
+        description: "Store block weight and stripped size",
+        apply: add_block_weight,
+        rebuild: false,
+    },
+    Migration {
+        description: "Store transaction base and witness size",
+        apply: add_tx_witness_size,
+        rebuild: false,
+    },
+    Migration {
+        description: "Store cumulative chainwork per block",
+        apply: add_chainwork,
+        rebuild: false,
+    },
+    Migration {
+        description: "Store block output value and reward",
+        apply: add_block_values,
+        rebuild: false,
+    },
+    Migration {
+        description: "Add BIP158 block filter table",
+        apply: add_block_filters,
+        rebuild: false,
+    },
+    Migration {
+        description: "Flag RBF mempool transactions and track the outputs they spend",
+        apply: add_mempool_conflicts,
+        rebuild: false,
+    },
+];
+
+// Bring the schema up to the latest version. Each migration runs in its own transaction
+// and records its version, so an interrupted upgrade resumes where it stopped.
+pub fn run_migrations(conn: &Connection, config: &ChainConfig) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            description TEXT,
+            applied_at INTEGER DEFAULT (strftime('%s', 'now'))
+        )",
+        [],
+    )?;
+
+    let current = schema_version(conn)?;
+    let mut rebuild = false;
+
+    for (version, migration) in (1..).zip(MIGRATIONS).skip(current as usize) {
+        log::info!("Applying schema migration {}: {}", version, migration.description);
+        let tx = conn.unchecked_transaction()?;
+        (migration.apply)(&tx)?;
+        tx.execute(
+            "INSERT INTO schema_version (version, description) VALUES (?1, ?2)",
+            rusqlite::params![&version, migration.description],
+        )?;
+        tx.commit()?;
+        rebuild |= migration.rebuild;
+    }
+
+    if rebuild {
+        rebuild_transactions(conn, config)?;
+    }
+    Ok(())
+}
+
+pub fn schema_version(conn: &Connection) -> Result<u32> {
+    conn.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", [], |row| row.get(0))
+}
+
+// Re-derive every transaction row from the stored raw blocks with the current insert_tx,
+// walking blocks in height order so spends are resolved after the outputs they spend
+fn rebuild_transactions(conn: &Connection, config: &ChainConfig) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    {
+        let mut stmt = tx.prepare("SELECT hash, raw_data FROM blocks WHERE raw_data IS NOT NULL ORDER BY height")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let hash: String = row.get(0)?;
+            let raw_data: Vec<u8> = row.get(1)?;
+            let Ok(block) = bitcoin::consensus::deserialize::<bitcoin::Block>(&raw_data) else {
+                log::warn!("Skipping block {}: stored raw data doesn't decode", hash);
+                continue;
+            };
+            for (index, transaction) in block.txdata.iter().enumerate() {
+                insert_tx(&tx, transaction, &hash, index, None, config)?;
+            }
+        }
+    }
+    tx.commit()
+}
+
+// SQLite has no ADD COLUMN IF NOT EXISTS
+fn add_column(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+    let exists: bool = conn.query_row(
+        &format!("SELECT COUNT(*) > 0 FROM pragma_table_info('{}') WHERE name = ?1", table),
+        [column],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+    Ok(())
+}
+
+fn add_transaction_metadata(conn: &Connection) -> Result<()> {
+    add_column(conn, "transactions", "version", "INTEGER")?;
+    add_column(conn, "transactions", "lock_time", "INTEGER")?;
+    add_column(conn, "transactions", "size", "INTEGER")?;
+    add_column(conn, "transactions", "vsize", "INTEGER")?;
+    add_column(conn, "transactions", "weight", "INTEGER")
+}
+
+fn add_address_index(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS address_index (
+            address TEXT,
+            txid TEXT,
+            vout INTEGER,
+            value INTEGER,
+            spent INTEGER DEFAULT 0,
+            spent_by TEXT,
+            PRIMARY KEY (txid, vout)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_address_index_address ON address_index(address)",
+        [],
+    )?;
+    Ok(())
+}
+
+fn add_mempool(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS mempool (
+            txid TEXT PRIMARY KEY,
+            first_seen INTEGER,
+            fee INTEGER,
+            vsize INTEGER,
+            raw_data BLOB
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn add_lookup_indexes(conn: &Connection) -> Result<()> {
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_blocks_height ON blocks(height)", [])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_blocks_timestamp ON blocks(timestamp)", [])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_transactions_block_hash ON transactions(block_hash)", [])?;
+    Ok(())
+}
+
+fn add_block_tx_count(conn: &Connection) -> Result<()> {
+    add_column(conn, "blocks", "tx_count", "INTEGER")?;
+    conn.execute(
+        "UPDATE blocks SET tx_count = (SELECT COUNT(*) FROM transactions WHERE block_hash = blocks.hash)
+         WHERE tx_count IS NULL",
+        [],
+    )?;
+    Ok(())
+}
+
+// A coinbase has a single input spending the null outpoint
+fn add_is_coinbase(conn: &Connection) -> Result<()> {
+    add_column(conn, "transactions", "is_coinbase", "INTEGER NOT NULL DEFAULT 0")?;
+    conn.execute(
+        "UPDATE transactions SET is_coinbase = 1
+         WHERE json_array_length(inputs) = 1
+           AND json_extract(inputs, '$[0].prev_txid') = ?1
+           AND json_extract(inputs, '$[0].vout') = ?2",
+        rusqlite::params![bitcoin::Txid::all_zeros().to_string(), u32::MAX],
+    )?;
+    Ok(())
+}
+
+fn add_index_in_block(conn: &Connection) -> Result<()> {
+    add_column(conn, "transactions", "index_in_block", "INTEGER")?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_transactions_block_position ON transactions(block_hash, index_in_block)",
+        [],
+    )?;
+    Ok(())
+}
+
+// NULL for coinbase transactions and ones spending outputs that aren't indexed
+fn add_transaction_fee(conn: &Connection) -> Result<()> {
+    add_column(conn, "transactions", "fee", "INTEGER")
+}
+
+fn add_op_returns(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS op_returns (
+            txid TEXT,
+            vout INTEGER,
+            data TEXT,
+            ascii TEXT,
+            PRIMARY KEY (txid, vout)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+// Which transaction spends each output, keyed by the output spent
+fn add_spends(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS spends (
+            prev_txid TEXT,
+            vout INTEGER,
+            spending_txid TEXT,
+            PRIMARY KEY (prev_txid, vout)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_spends_spending_txid ON spends(spending_txid)",
+        [],
+    )?;
+    Ok(())
+}
+
+// Bitmask of the TX_FLAG_* constants in script.rs
+fn add_tx_flags(conn: &Connection) -> Result<()> {
+    add_column(conn, "transactions", "tx_flags", "INTEGER NOT NULL DEFAULT 0")
+}
+
+// input_type lives in the inputs JSON, so there's no schema change; the rebuild fills it in
+fn classify_inputs(_conn: &Connection) -> Result<()> {
+    Ok(())
+}
+
+// Key/value totals that insert_block, insert_tx and handle_reorg keep up to date, so /stats
+// doesn't have to scan the blocks and transactions tables. Seeded with one full count here.
+fn add_metadata(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS metadata (
+            key TEXT PRIMARY KEY,
+            value INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    reset_counts(conn, &query_counts_exact(conn)?)
+}
+
+// Backfilled from each block's raw data; blocks stored without it keep NULLs
+fn add_block_weight(conn: &Connection) -> Result<()> {
+    add_column(conn, "blocks", "weight", "INTEGER")?;
+    add_column(conn, "blocks", "stripped_size", "INTEGER")?;
+
+    let mut stmt = conn.prepare("SELECT hash, raw_data FROM blocks WHERE raw_data IS NOT NULL AND weight IS NULL")?;
+    let mut update = conn.prepare("UPDATE blocks SET weight = ?1, stripped_size = ?2 WHERE hash = ?3")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let hash: String = row.get(0)?;
+        let raw_data: Vec<u8> = row.get(1)?;
+        let Ok(block) = bitcoin::consensus::deserialize::<bitcoin::Block>(&raw_data) else {
+            log::warn!("Skipping block {}: stored raw data doesn't decode", hash);
+            continue;
+        };
+        let (weight, stripped_size) = block_weight_and_stripped_size(&block);
+        update.execute(rusqlite::params![weight, stripped_size, hash])?;
+    }
+    Ok(())
+}
+
+// Weight is 3 * base size + total size, so both follow from the stored size and weight
+fn add_tx_witness_size(conn: &Connection) -> Result<()> {
+    add_column(conn, "transactions", "base_size", "INTEGER")?;
+    add_column(conn, "transactions", "witness_size", "INTEGER")?;
+    conn.execute(
+        "UPDATE transactions SET base_size = (weight - size) / 3, witness_size = size - (weight - size) / 3
+         WHERE base_size IS NULL",
+        [],
+    )?;
+    Ok(())
+}
+
+// Parents come before their children in height order, so each block's parent already has its
+// chainwork when the block is reached
+fn add_chainwork(conn: &Connection) -> Result<()> {
+    add_column(conn, "blocks", "chainwork", "TEXT")?;
+
+    let mut stmt = conn.prepare("SELECT hash, prev_block, bits FROM blocks ORDER BY height")?;
+    let mut update = conn.prepare("UPDATE blocks SET chainwork = ?1 WHERE hash = ?2")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let hash: String = row.get(0)?;
+        let chainwork = chainwork_after(conn, &row.get::<_, String>(1)?, row.get(2)?)?;
+        update.execute(rusqlite::params![chainwork, hash])?;
+    }
+    Ok(())
+}
+
+// Summed from the indexed outputs, which blocks keep even when stored without raw data
+fn add_block_values(conn: &Connection) -> Result<()> {
+    add_column(conn, "blocks", "total_output_value", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(conn, "blocks", "subsidy_plus_fees", "INTEGER NOT NULL DEFAULT 0")?;
+    conn.execute(
+        "UPDATE blocks SET
+             total_output_value = COALESCE((
+                 SELECT SUM(json_extract(o.value, '$.value')) FROM transactions t, json_each(t.outputs) o
+                 WHERE t.block_hash = blocks.hash
+             ), 0),
+             subsidy_plus_fees = COALESCE((
+                 SELECT SUM(json_extract(o.value, '$.value')) FROM transactions t, json_each(t.outputs) o
+                 WHERE t.block_hash = blocks.hash AND t.is_coinbase = 1
+             ), 0)",
+        [],
+    )?;
+    Ok(())
+}
+
+// Backfilled from each block's raw data; blocks stored without it, or spending outputs that
+// aren't indexed, get no filter
+fn add_block_filters(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS block_filters (
+            block_hash TEXT PRIMARY KEY,
+            filter BLOB NOT NULL
+        )",
+        [],
+    )?;
+
+    let mut stmt = conn.prepare("SELECT hash, raw_data FROM blocks WHERE raw_data IS NOT NULL")?;
+    let mut insert = conn.prepare("INSERT OR REPLACE INTO block_filters (block_hash, filter) VALUES (?1, ?2)")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let hash: String = row.get(0)?;
+        let raw_data: Vec<u8> = row.get(1)?;
+        let Ok(block) = bitcoin::consensus::deserialize::<bitcoin::Block>(&raw_data) else {
+            log::warn!("Skipping block {}: stored raw data doesn't decode", hash);
+            continue;
+        };
+        if let Some(filter) = compute_block_filter(conn, &block)? {
+            insert.execute(rusqlite::params![hash, filter])?;
+        }
+    }
+    Ok(())
+}
+
+// Backfilled from the pending transactions' raw data
+fn add_mempool_conflicts(conn: &Connection) -> Result<()> {
+    add_column(conn, "mempool", "rbf", "INTEGER NOT NULL DEFAULT 0")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS mempool_spends (
+            txid TEXT NOT NULL,
+            prev_txid TEXT NOT NULL,
+            vout INTEGER NOT NULL,
+            PRIMARY KEY (txid, prev_txid, vout)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_mempool_spends_outpoint ON mempool_spends(prev_txid, vout)",
+        [],
+    )?;
+
+    let mut stmt = conn.prepare("SELECT txid, raw_data FROM mempool WHERE raw_data IS NOT NULL")?;
+    let mut update = conn.prepare("UPDATE mempool SET rbf = ?1 WHERE txid = ?2")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let txid: String = row.get(0)?;
+        let raw_data: Vec<u8> = row.get(1)?;
+        let Ok(tx) = bitcoin::consensus::deserialize::<bitcoin::Transaction>(&raw_data) else {
+            log::warn!("Skipping mempool transaction {}: stored raw data doesn't decode", txid);
+            continue;
+        };
+        update.execute(rusqlite::params![tx.is_explicitly_rbf(), txid])?;
+        insert_mempool_spends(conn, &txid, &tx)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn versions(conn: &Connection) -> Vec<u32> {
+        conn.prepare("SELECT version FROM schema_version ORDER BY version").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn migrations_are_recorded_once_and_resume_where_they_stopped() {
+        let conn = test_db();
+        let config = ChainConfig::default();
+        let latest = MIGRATIONS.len() as u32;
+        assert_eq!(versions(&conn), (1..=latest).collect::<Vec<_>>());
+        run_migrations(&conn, &config).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), latest);
+        assert_eq!(versions(&conn).len(), MIGRATIONS.len());
+
+        // A database upgraded only as far as the version before block filters
+        let chain = store_chain(&conn, 3);
+        conn.execute_batch(
+            "DELETE FROM schema_version WHERE version >= 18;
+             DROP TABLE block_filters;
+             DROP TABLE mempool_spends;
+             ALTER TABLE mempool DROP COLUMN rbf;",
+        ).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), 17);
+
+        run_migrations(&conn, &config).unwrap();
+        assert_eq!(versions(&conn), (1..=latest).collect::<Vec<_>>());
+        let filters: usize = conn.query_row("SELECT COUNT(*) FROM block_filters", [], |row| row.get(0)).unwrap();
+        assert_eq!(filters, chain.len());
+        conn.execute("INSERT INTO mempool (txid, rbf) VALUES ('ab', 1)", []).unwrap();
+    }
+}
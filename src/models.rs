@@ -1,7 +1,8 @@
 use serde::Serialize;
+use utoipa::ToSchema;
 
 // JSON responses for the API
-#[derive(Serialize)]
+#[derive(Serialize, Clone, ToSchema)]
 pub struct BlockResponse {
     pub hash: String,
     pub height: u32,
@@ -10,15 +11,28 @@ pub struct BlockResponse {
     pub merkle_root: String,
     pub timestamp: u32,
     pub bits: u32,
+    // Relative to the mainnet minimum difficulty, as bitcoind reports it
+    pub difficulty: f64,
     pub nonce: u32,
     pub tx_count: usize,
     pub size: usize,
+    // BIP141 weight units. Null for blocks stored without raw data before these were recorded.
+    pub weight: Option<u64>,
+    // Size in bytes without witness data
+    pub stripped_size: Option<usize>,
+    // Expected hashes to produce the chain up to this block, as 64 hex characters. Null when
+    // the chain below it isn't fully indexed.
+    pub chainwork: Option<String>,
+    // Sum of every output of the block's transactions, coinbase included, in sats
+    pub total_output_value: u64,
+    // The coinbase's outputs in sats: the block reward, i.e. the subsidy plus the fees it claimed
+    pub subsidy_plus_fees: u64,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone, ToSchema)]
 pub struct TxResponse {
     pub txid: String,
-    pub version: u32,
+    pub version: i32,
     pub lock_time: u32,
     pub block_hash: Option<String>,
     pub block_height: Option<u32>,
@@ -26,42 +40,609 @@ pub struct TxResponse {
     pub inputs: Vec<TxInSimplified>,
     pub outputs: Vec<TxOutSimplified>,
     pub size: usize,
+    // Serialized size without the segwit marker, flag and witnesses
+    pub base_size: usize,
+    // Bytes of segwit marker, flag and witnesses; weight is base_size * 4 + witness_size
+    pub witness_size: usize,
     pub vsize: usize,
     pub weight: usize,
+    pub is_coinbase: bool,
+    // In sats; None for coinbase transactions or when an input value couldn't be resolved
+    pub fee: Option<u64>,
+    // In sat/vB
+    pub fee_rate: Option<f64>,
+    // An input carries witness data
+    pub has_witness: bool,
+    // Has witness data or pays to a witness program
+    pub is_segwit: bool,
+    // Pays to a p2tr output
+    pub is_taproot: bool,
+    // Only present when the request paginated inputs or outputs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inputs_pagination: Option<Pagination>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outputs_pagination: Option<Pagination>,
+}
+
+// Which page of a list was returned: blocks, a block's or address's transactions, the
+// mempool, OP_RETURNs, the rich list, or a transaction's inputs or outputs
+#[derive(Serialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct Pagination {
+    pub current_page: usize,
+    pub per_page: usize,
+    pub total_items: usize,
+    pub total_pages: usize,
+    pub has_next: bool,
+    pub has_prev: bool,
 }
 
-#[derive(Serialize, serde::Deserialize, Default)]
+impl Pagination {
+    pub fn new(page: usize, per_page: usize, total_items: usize) -> Self {
+        let total_pages = total_items.div_ceil(per_page.max(1));
+        Self {
+            current_page: page,
+            per_page,
+            total_items,
+            total_pages,
+            has_next: page < total_pages,
+            has_prev: page > 1,
+        }
+    }
+}
+
+// One page of a paginated endpoint: its own fields (the page's items among them) with a
+// `pagination` object next to them
+#[derive(Serialize)]
+pub struct PaginatedResponse<T> {
+    #[serde(flatten)]
+    pub data: T,
+    pub pagination: Pagination,
+}
+
+impl<T> PaginatedResponse<T> {
+    pub fn new(data: T, page: usize, per_page: usize, total_items: usize) -> Self {
+        Self { data, pagination: Pagination::new(page, per_page, total_items) }
+    }
+}
+
+#[derive(Serialize, serde::Deserialize, Clone, Default, ToSchema)]
 pub struct TxInSimplified {
     pub prev_txid: String,
     pub vout: u32,
     pub script_sig: String,
+    // Disassembly of script_sig, added at query time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script_sig_asm: Option<String>,
     pub sequence: u32,
     pub witness: Vec<String>,
+    // Coinbase inputs don't spend a previous output
+    #[serde(default)]
+    pub coinbase: bool,
+    // Value of the spent output, resolved at query time; None if it isn't indexed
+    #[serde(default)]
+    pub value: Option<u64>,
+    // p2pkh, p2sh, p2wpkh, p2wsh, p2tr-keypath, p2tr-scriptpath, coinbase or nonstandard
+    #[serde(default)]
+    pub input_type: String,
+    // Coinbase inputs only, added at query time: the BIP34 height in script_sig (absent for
+    // blocks from before BIP34) and the miner's printable ASCII tag
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coinbase_height: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coinbase_tag: Option<String>,
+    // Script type and address of the spent output, resolved with value. Only the v2 shape
+    // shows them, so they're neither stored nor in v1 responses.
+    #[serde(skip)]
+    pub prev_script_type: Option<String>,
+    #[serde(skip)]
+    pub prev_address: Option<String>,
+    // The spent output, with /tx/{txid}?prevout=true; absent for coinbase inputs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prevout: Option<TxPrevout>,
+}
+
+// The output an input spends. Every field is null when the previous transaction isn't indexed
+// yet; address is also null for scripts that don't have one.
+#[derive(Serialize, serde::Deserialize, Clone, ToSchema)]
+pub struct TxPrevout {
+    pub value: Option<u64>,
+    pub script_type: Option<String>,
+    pub address: Option<String>,
 }
 
-#[derive(Serialize, serde::Deserialize, Default)]
+#[derive(Serialize, serde::Deserialize, Clone, Default, ToSchema)]
 pub struct TxOutSimplified {
     pub value: u64,
     pub script_pubkey: String,
+    // Disassembly of script_pubkey, added at query time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script_pubkey_asm: Option<String>,
+    // p2pkh, p2sh, p2wpkh, p2wsh, p2tr, op_return or nonstandard
+    #[serde(default)]
+    pub script_type: String,
+    pub address: Option<String>,
+    // Resolved at query time from the spending index; false until a spending tx is indexed
+    #[serde(default)]
+    pub spent: bool,
+    #[serde(default)]
+    pub spent_by_txid: Option<String>,
+}
+
+// The v2 shape of a block (?v=2 or Accept: application/vnd.explorer.v2+json): BlockResponse
+// with the header fields, sizes and values grouped
+#[derive(Serialize, ToSchema)]
+pub struct BlockResponseV2 {
+    pub hash: String,
+    pub height: u32,
+    pub header: BlockHeaderV2,
+    pub chainwork: Option<String>,
+    pub tx_count: usize,
+    pub size: BlockSizeV2,
+    pub value: BlockValueV2,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BlockHeaderV2 {
+    pub version: u32,
+    pub prev_block: String,
+    pub merkle_root: String,
+    pub timestamp: u32,
+    pub bits: u32,
+    pub difficulty: f64,
+    pub nonce: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BlockSizeV2 {
+    pub total: usize,
+    pub stripped: Option<usize>,
+    pub weight: Option<u64>,
+}
+
+// In sats
+#[derive(Serialize, ToSchema)]
+pub struct BlockValueV2 {
+    pub total_output: u64,
+    pub subsidy_plus_fees: u64,
+}
+
+impl From<BlockResponse> for BlockResponseV2 {
+    fn from(block: BlockResponse) -> Self {
+        Self {
+            hash: block.hash,
+            height: block.height,
+            header: BlockHeaderV2 {
+                version: block.version,
+                prev_block: block.prev_block,
+                merkle_root: block.merkle_root,
+                timestamp: block.timestamp,
+                bits: block.bits,
+                difficulty: block.difficulty,
+                nonce: block.nonce,
+            },
+            chainwork: block.chainwork,
+            tx_count: block.tx_count,
+            size: BlockSizeV2 {
+                total: block.size,
+                stripped: block.stripped_size,
+                weight: block.weight,
+            },
+            value: BlockValueV2 {
+                total_output: block.total_output_value,
+                subsidy_plus_fees: block.subsidy_plus_fees,
+            },
+        }
+    }
+}
+
+// The v2 shape of a transaction: TxResponse with the confirmation status, sizes and fee
+// grouped, and each input showing the output it spends
+#[derive(Serialize, ToSchema)]
+pub struct TxResponseV2 {
+    pub txid: String,
+    pub version: i32,
+    pub lock_time: u32,
+    pub status: TxStatusV2,
+    pub size: TxSizeV2,
+    // None for coinbase transactions or when an input value couldn't be resolved
+    pub fee: Option<TxFeeV2>,
+    pub is_coinbase: bool,
+    // Signals BIP125 replaceability: an input's sequence is below 0xfffffffe
+    pub rbf: bool,
+    pub has_witness: bool,
+    pub is_segwit: bool,
+    pub is_taproot: bool,
+    pub inputs: Vec<TxInV2>,
+    pub outputs: Vec<TxOutV2>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inputs_pagination: Option<Pagination>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outputs_pagination: Option<Pagination>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TxStatusV2 {
+    pub confirmed: bool,
+    pub block_hash: Option<String>,
+    pub block_height: Option<u32>,
+    pub confirmations: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TxSizeV2 {
+    pub total: usize,
+    pub base: usize,
+    pub witness: usize,
+    pub vsize: usize,
+    pub weight: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TxFeeV2 {
+    // In sats
+    pub total: u64,
+    // In sat/vB
+    pub rate: f64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TxInV2 {
+    // The spent output; null for a coinbase input
+    pub prevout: Option<PrevoutV2>,
+    pub script_sig: ScriptV2,
+    pub witness: Vec<String>,
+    pub sequence: u32,
+    // p2pkh, p2sh, p2wpkh, p2wsh, p2tr-keypath, p2tr-scriptpath, coinbase or nonstandard
+    #[serde(rename = "type")]
+    pub input_type: String,
+    // Coinbase inputs only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coinbase: Option<CoinbaseV2>,
+}
+
+// Value, script type and address are null when the spent output isn't indexed
+#[derive(Serialize, ToSchema)]
+pub struct PrevoutV2 {
+    pub txid: String,
+    pub vout: u32,
+    pub value: Option<u64>,
+    pub script_type: Option<String>,
+    pub address: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CoinbaseV2 {
+    pub height: Option<u32>,
+    pub tag: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TxOutV2 {
+    pub value: u64,
+    pub script_pubkey: ScriptV2,
+    // Txid of the spending transaction; null while unspent
+    pub spent_by: Option<String>,
+}
+
+// A script as hex and asm. Output scripts also carry their type and address.
+#[derive(Serialize, ToSchema)]
+pub struct ScriptV2 {
+    pub hex: String,
+    pub asm: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub script_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+}
+
+impl From<TxResponse> for TxResponseV2 {
+    fn from(tx: TxResponse) -> Self {
+        let rbf = !tx.is_coinbase && tx.inputs.iter().any(|input| input.sequence < 0xfffffffe);
+        Self {
+            txid: tx.txid,
+            version: tx.version,
+            lock_time: tx.lock_time,
+            status: TxStatusV2 {
+                confirmed: tx.block_hash.is_some(),
+                block_hash: tx.block_hash,
+                block_height: tx.block_height,
+                confirmations: tx.confirmations,
+            },
+            size: TxSizeV2 {
+                total: tx.size,
+                base: tx.base_size,
+                witness: tx.witness_size,
+                vsize: tx.vsize,
+                weight: tx.weight,
+            },
+            fee: tx.fee.zip(tx.fee_rate).map(|(total, rate)| TxFeeV2 { total, rate }),
+            is_coinbase: tx.is_coinbase,
+            rbf,
+            has_witness: tx.has_witness,
+            is_segwit: tx.is_segwit,
+            is_taproot: tx.is_taproot,
+            inputs: tx.inputs.into_iter().map(|input| TxInV2 {
+                prevout: (!input.coinbase).then_some(PrevoutV2 {
+                    txid: input.prev_txid,
+                    vout: input.vout,
+                    value: input.value,
+                    script_type: input.prev_script_type,
+                    address: input.prev_address,
+                }),
+                script_sig: ScriptV2 {
+                    hex: input.script_sig,
+                    asm: input.script_sig_asm,
+                    script_type: None,
+                    address: None,
+                },
+                witness: input.witness,
+                sequence: input.sequence,
+                input_type: input.input_type,
+                coinbase: input.coinbase.then_some(CoinbaseV2 {
+                    height: input.coinbase_height,
+                    tag: input.coinbase_tag,
+                }),
+            }).collect(),
+            outputs: tx.outputs.into_iter().map(|output| TxOutV2 {
+                value: output.value,
+                script_pubkey: ScriptV2 {
+                    hex: output.script_pubkey,
+                    asm: output.script_pubkey_asm,
+                    script_type: Some(output.script_type),
+                    address: output.address,
+                },
+                spent_by: output.spent_by_txid,
+            }).collect(),
+            inputs_pagination: tx.inputs_pagination,
+            outputs_pagination: tx.outputs_pagination,
+        }
+    }
+}
+
+// Same shape as Bitcoin Core's decoderawtransaction
+#[derive(Serialize, ToSchema)]
+pub struct DecodedTx {
+    pub txid: String,
+    // wtxid; equals txid when there's no witness data
+    pub hash: String,
+    pub version: i32,
+    pub size: usize,
+    pub vsize: usize,
+    pub weight: usize,
+    pub locktime: u32,
+    pub vin: Vec<DecodedInput>,
+    pub vout: Vec<DecodedOutput>,
+}
+
+// A coinbase input has `coinbase` in place of txid, vout and scriptSig
+#[derive(Serialize, ToSchema)]
+pub struct DecodedInput {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coinbase: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub txid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vout: Option<u32>,
+    #[serde(rename = "scriptSig", skip_serializing_if = "Option::is_none")]
+    pub script_sig: Option<DecodedScriptSig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub txinwitness: Vec<String>,
+    pub sequence: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DecodedScriptSig {
+    pub asm: String,
+    pub hex: String,
 }
+
+#[derive(Serialize, ToSchema)]
+pub struct DecodedOutput {
+    // In BTC
+    pub value: f64,
+    pub n: u32,
+    #[serde(rename = "scriptPubKey")]
+    pub script_pubkey: DecodedScriptPubKey,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DecodedScriptPubKey {
+    pub asm: String,
+    pub hex: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    // Core's names: pubkeyhash, scripthash, witness_v0_keyhash, witness_v0_scripthash,
+    // witness_v1_taproot, witness_unknown, pubkey, nulldata or nonstandard
+    #[serde(rename = "type")]
+    pub script_type: String,
+}
+
+// A page of blocks, as /blocks and /blocks/latest return it
 #[derive(Serialize)]
-pub struct LatestBlocksResponse {
+pub struct BlockList {
     pub blocks: Vec<BlockSummary>,
-    pub total_count: u32,
+    // The shape /blocks/latest had before it gained `pagination`; same as
+    // pagination.total_items and pagination.per_page. To be removed in the next release.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_blocks: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
 }
 
-#[derive(Serialize)]
+// A transaction relayed by POST /tx
+#[derive(Serialize, ToSchema)]
+pub struct BroadcastResult {
+    pub txid: String,
+}
+
+// The best stored block
+#[derive(Serialize, ToSchema)]
+pub struct ChainTip {
+    pub height: u32,
+    pub hash: String,
+    pub timestamp: u32,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
 pub struct BlockSummary {
     pub hash: String,
     pub height: u32,
     pub timestamp: u32,
     pub tx_count: usize,
+    // In bytes
+    pub size: usize,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct StatsResponse {
     pub total_blocks: u32,
     pub total_transactions: u64,
     pub latest_block_height: u32,
     pub latest_block_hash: String,
-}
\ No newline at end of file
+    // In sats
+    pub total_fees: u64,
+    // In bytes
+    pub avg_block_size: f64,
+    pub avg_tx_per_block: f64,
+    pub chain_size_bytes: u64,
+    // Share of non-coinbase transactions flagged segwit / taproot, in percent
+    pub segwit_tx_percent: f64,
+    pub taproot_tx_percent: f64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DailyStats {
+    // UTC day, YYYY-MM-DD
+    pub date: String,
+    pub blocks: u32,
+    pub transactions: u64,
+    // Sum of all output values in sats, coinbase included
+    pub output_volume: u64,
+    // Sum of the blocks' rewards (subsidy plus fees) in sats
+    pub subsidy_plus_fees: u64,
+    // Sums over the day's blocks, for block-space utilization
+    pub weight: u64,
+    pub stripped_size: u64,
+}
+
+// Fee rates (sat/vB) paid in recent blocks. The rates are null when those blocks hold no
+// transactions with a known fee.
+#[derive(Serialize, ToSchema)]
+pub struct FeeEstimates {
+    // Number of blocks the rates were taken from
+    pub blocks: u32,
+    // Number of transactions the rates were taken from
+    pub transactions: usize,
+    pub min: Option<f64>,
+    pub median: Option<f64>,
+    pub max: Option<f64>,
+}
+
+#[derive(Clone)]
+pub struct ChainStats {
+    pub total_blocks: u32,
+    pub total_transactions: u64,
+    pub total_fees: u64,
+    pub avg_block_size: f64,
+    pub avg_tx_per_block: f64,
+    pub chain_size_bytes: u64,
+    pub segwit_tx_percent: f64,
+    pub taproot_tx_percent: f64,
+}
+
+// Running totals kept in the metadata table. Signed so the same struct can hold a change to them.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct ChainCounts {
+    pub blocks: i64,
+    pub block_bytes: i64,
+    pub transactions: i64,
+    pub fees: i64,
+    pub non_coinbase_transactions: i64,
+    pub segwit_transactions: i64,
+    pub taproot_transactions: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AddressSummary {
+    pub address: String,
+    pub balance: u64,
+    pub total_received: u64,
+    pub total_sent: u64,
+    pub tx_count: usize,
+    pub transactions: Vec<String>,
+}
+
+// Where a page of /address/{address}/history ended: its last entry. Sent to clients as base64
+// JSON, which they hand back unchanged.
+#[derive(Serialize, serde::Deserialize, PartialEq, Debug)]
+pub struct HistoryCursor {
+    pub height: u32,
+    pub txid: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AddressHistoryEntry {
+    pub txid: String,
+    pub block_height: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AddressHistory {
+    pub address: String,
+    // Newest first; ties within a block are broken by txid, descending
+    pub transactions: Vec<AddressHistoryEntry>,
+    // Pass as ?cursor= for the next page; null on the last page
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct RichListEntry {
+    // From 1, richest first
+    pub rank: usize,
+    pub address: String,
+    // Sum of the address's unspent outputs, in sats
+    pub balance: u64,
+    pub utxo_count: u64,
+}
+
+#[derive(Serialize)]
+pub struct RichList {
+    pub addresses: Vec<RichListEntry>,
+    // Unix time the ranking was computed; it's recomputed at most once a minute
+    pub computed_at: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Utxo {
+    pub txid: String,
+    pub vout: u32,
+    pub value: u64,
+    pub block_height: Option<u32>,
+    pub confirmations: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MempoolEntry {
+    pub txid: String,
+    pub first_seen: u64,
+    pub fee: u64,
+    pub vsize: usize,
+    pub fee_rate: f64,
+    // Signals BIP125 replaceability: an input's sequence is below 0xfffffffe
+    pub rbf: bool,
+    // Another pending transaction spends one of the same outputs; at most one of them can confirm
+    pub double_spend: bool,
+    // Txids of those transactions
+    pub conflicts: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OpReturnEntry {
+    pub txid: String,
+    pub vout: u32,
+    pub block_height: Option<u32>,
+    // Hex of the pushed bytes
+    pub data: String,
+    // Set when the payload is printable ASCII
+    pub ascii: Option<String>,
+}
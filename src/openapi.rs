@@ -0,0 +1,226 @@
+use serde::Serialize;
+use utoipa::{OpenApi, ToSchema};
+use crate::handlers;
+use crate::jsonrpc;
+use crate::models::*;
+
+// Shapes of the responses the handlers build with json!. They only describe the API for the
+// OpenAPI document, so keep them in step with the handlers when a response changes.
+
+// Body of every 4xx/5xx JSON response
+#[derive(Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: ErrorBody,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    // Stable snake_case identifier, e.g. "block_not_found", "invalid_parameter" or "database_error"
+    pub code: String,
+    pub message: String,
+    // The values the error is about, e.g. {"hash": ...} or {"limit": ...}; null when there are none
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct HealthResponse {
+    // "healthy" for liveness, "ready" for readiness
+    pub status: String,
+    pub service: String,
+}
+
+// PaginatedResponse<BlockList>
+#[derive(Serialize, ToSchema)]
+pub struct BlocksPage {
+    pub blocks: Vec<BlockSummary>,
+    pub pagination: Pagination,
+    // Only on /blocks/latest, the shape it had before it gained `pagination`
+    #[schema(deprecated)]
+    pub total_blocks: Option<u32>,
+    #[schema(deprecated)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BlocksRange {
+    pub from: u32,
+    pub to: u32,
+    pub blocks: Vec<BlockSummary>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BlockTxidsPage {
+    pub hash: String,
+    pub txids: Vec<String>,
+    pub pagination: Pagination,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BlockTransactionsPage {
+    pub hash: String,
+    pub transactions: Vec<TxResponse>,
+    pub pagination: Pagination,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BlockTransactionsPageV2 {
+    pub hash: String,
+    pub transactions: Vec<TxResponseV2>,
+    pub pagination: Pagination,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MerkleProof {
+    pub txid: String,
+    pub block_hash: String,
+    pub block_height: u32,
+    pub merkle_root: String,
+    // Index of the transaction in its block
+    pub pos: usize,
+    // Sibling hashes from the transaction up to the root
+    pub merkle: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DailyStatsSeries {
+    pub days: Vec<DailyStats>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AddressPage {
+    pub address: String,
+    pub balance: u64,
+    pub total_received: u64,
+    pub total_sent: u64,
+    pub transactions: Vec<String>,
+    pub pagination: Pagination,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AddressUtxos {
+    pub address: String,
+    pub balance: u64,
+    pub utxos: Vec<Utxo>,
+}
+
+// PaginatedResponse<RichList>
+#[derive(Serialize, ToSchema)]
+pub struct RichListPage {
+    pub addresses: Vec<RichListEntry>,
+    pub pagination: Pagination,
+    // Unix time the ranking was computed; it's recomputed at most once a minute
+    pub computed_at: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MempoolPage {
+    pub size: usize,
+    pub total_fees: u64,
+    pub transactions: Vec<MempoolEntry>,
+    pub pagination: Pagination,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OpReturnsPage {
+    pub op_returns: Vec<OpReturnEntry>,
+    pub pagination: Pagination,
+}
+
+// `type` says which of the other fields is set
+#[derive(Serialize, ToSchema)]
+pub struct SearchResult {
+    // block, tx, address or not_found
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub block: Option<BlockResponse>,
+    pub tx: Option<TxResponse>,
+    pub address: Option<AddressSummary>,
+    pub query: Option<String>,
+}
+
+// One JSON-RPC 2.0 call. POST /rpc also takes an array of these as a batch.
+#[derive(Serialize, ToSchema)]
+pub struct JsonRpcRequest {
+    // Always "2.0"
+    pub jsonrpc: String,
+    // getblockcount, getbestblockhash, getblockhash, getblock, gettransaction or getrawtransaction
+    pub method: String,
+    // Positional array or named object, e.g. ["<blockhash>"] or {"blockhash": "<blockhash>"}
+    #[schema(value_type = Option<Object>)]
+    pub params: Option<serde_json::Value>,
+    // Omitted for a notification, which gets no response
+    #[schema(value_type = Option<Object>)]
+    pub id: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+// Exactly one of `result` and `error` is set
+#[derive(Serialize, ToSchema)]
+pub struct JsonRpcResponse {
+    pub jsonrpc: String,
+    #[schema(value_type = Option<Object>)]
+    pub result: Option<serde_json::Value>,
+    pub error: Option<JsonRpcError>,
+    #[schema(value_type = Object)]
+    pub id: serde_json::Value,
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Regtest Block Explorer API", description = "Blocks, transactions and addresses indexed from a Bitcoin node"),
+    paths(
+        handlers::get_block,
+        handlers::get_block_by_height,
+        handlers::get_next_block,
+        handlers::get_prev_block,
+        handlers::get_block_txids,
+        handlers::get_block_txids_by_height,
+        handlers::get_block_transactions,
+        handlers::get_block_tx_at_index,
+        handlers::get_block_raw,
+        handlers::get_block_filter,
+        handlers::get_block_raw_by_height,
+        handlers::get_tx,
+        handlers::get_tx_raw,
+        handlers::get_tx_hex,
+        handlers::post_tx,
+        handlers::get_tx_decode,
+        handlers::get_tx_merkle_proof,
+        handlers::get_tip,
+        handlers::get_latest_blocks,
+        handlers::get_all_blocks,
+        handlers::get_blocks_range,
+        handlers::export_blocks_csv,
+        handlers::get_stats,
+        handlers::get_daily_stats,
+        handlers::get_fee_estimates,
+        handlers::health_check,
+        handlers::readiness_check,
+        handlers::get_metrics,
+        handlers::get_address,
+        handlers::get_address_history,
+        handlers::get_address_utxos,
+        handlers::get_rich_list,
+        handlers::get_mempool,
+        handlers::get_op_returns,
+        handlers::get_search,
+        jsonrpc::post_rpc,
+    ),
+    components(schemas(
+        BlockResponse, BlockSummary, ChainTip, BroadcastResult, TxResponse, TxInSimplified, TxPrevout, TxOutSimplified,
+        Pagination, DecodedTx, DecodedInput, DecodedScriptSig, DecodedOutput, DecodedScriptPubKey,
+        StatsResponse, DailyStats, FeeEstimates, AddressSummary, Utxo, MempoolEntry, OpReturnEntry,
+        ErrorResponse, ErrorBody, HealthResponse, BlocksPage, BlocksRange, BlockTxidsPage, BlockTransactionsPage, MerkleProof,
+        DailyStatsSeries, AddressPage, AddressHistory, AddressHistoryEntry, AddressUtxos, RichListEntry, RichListPage, MempoolPage, OpReturnsPage, SearchResult,
+        JsonRpcRequest, JsonRpcError, JsonRpcResponse,
+        BlockResponseV2, BlockHeaderV2, BlockSizeV2, BlockValueV2, TxResponseV2, TxStatusV2, TxSizeV2, TxFeeV2,
+        TxInV2, PrevoutV2, CoinbaseV2, TxOutV2, ScriptV2, BlockTransactionsPageV2,
+    ))
+)]
+pub struct ApiDoc;
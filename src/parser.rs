@@ -1,70 +1,647 @@
-use bitcoin::Block;
+use bitcoin::block::Header;
+use bitcoin::consensus::{encode, Decodable};
+use bitcoin::hashes::Hash;
+use bitcoin::{Block, BlockHash};
+use futures_util::{stream, StreamExt};
 use rusqlite::Connection;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fs::File;
-use std::io::{self, BufReader, Read};
-use std::path::Path;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
 use tokio::fs::read_dir;
-use crate::db::insert_block;
+use tokio::sync::mpsc;
+use crate::config::ChainConfig;
+use crate::db::{insert_block, query_block_height};
+use crate::indexer::{validate_block, IndexSummary};
+use crate::progress::Progress;
+use crate::status;
 
-// Magic bytes from the regtest blk file
-const REGTEST_MAGIC: [u8; 4] = [0x83, 0x9d, 0xe4, 0x11];
+// Skip the zero padding the node leaves between and after blocks.
+// Returns the first non-zero byte, or None if the file ends first.
+fn skip_padding(reader: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0u8; 1];
+    loop {
+        match reader.read_exact(&mut byte) {
+            Ok(()) if byte[0] == 0 => continue,
+            Ok(()) => return Ok(Some(byte[0])),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+    }
+}
 
-// Parse a single block from reader
-fn parse_block(reader: &mut impl Read) -> io::Result<Block> {
-    let mut magic = [0u8; 4];
-    reader.read_exact(&mut magic)?;
+// Read the magic bytes and size that precede each block. Returns the block's size, or
+// Ok(None) once only padding is left.
+fn read_block_frame(reader: &mut impl Read, config: &ChainConfig) -> io::Result<Option<usize>> {
+    let Some(first) = skip_padding(reader)? else {
+        return Ok(None);
+    };
+    let mut magic = [first, 0, 0, 0];
+    reader.read_exact(&mut magic[1..])?;
 
-    if magic != REGTEST_MAGIC {
-        eprintln!("Invalid magic: {:02x?}, expected regtest: {:02x?}", magic, REGTEST_MAGIC);
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid magic"));
+    if magic != config.magic() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "magic bytes {:02x?} don't match {} ({:02x?}); is --network set correctly?",
+                magic, config.network, config.magic()
+            ),
+        ));
     }
 
     let mut size = [0u8; 4];
     reader.read_exact(&mut size)?;
-    let size = u32::from_le_bytes(size) as usize;
-    println!("Block size: {}", size);
+    Ok(Some(u32::from_le_bytes(size) as usize))
+}
+
+// Parse a single block from reader. Returns Ok(None) once only padding is left. A block cut
+// off by the end of the file fails with UnexpectedEof; any other error means the data is corrupt.
+fn parse_block(reader: &mut impl Read, config: &ChainConfig) -> io::Result<Option<Block>> {
+    let Some(size) = read_block_frame(reader, config)? else {
+        return Ok(None);
+    };
 
     let mut block_data = vec![0u8; size];
     reader.read_exact(&mut block_data)?;
 
     let block = bitcoin::consensus::deserialize(&block_data)
-        .map_err(|e| {
-            eprintln!("Consensus decode failed: {}", e);
-            io::Error::new(io::ErrorKind::InvalidData, e)
-        })?;
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    Ok(Some(block))
+}
 
-    Ok(block)
+// Parse one bare consensus-serialized block, without the .blk framing. Returns Ok(None) at
+// the end of the stream.
+fn parse_raw_block(reader: &mut impl BufRead) -> io::Result<Option<Block>> {
+    if reader.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    Block::consensus_decode(bitcoin::io::from_std_mut(reader))
+        .map(Some)
+        .map_err(|e| match e {
+            encode::Error::Io(e) if e.kind() == bitcoin::io::ErrorKind::UnexpectedEof => io::ErrorKind::UnexpectedEof.into(),
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        })
 }
 
-// Index all blocks from a directory of .blk files
-pub async fn index_blocks(db_conn: &Connection, blocks_dir: &Path) -> anyhow::Result<()> {
+// blk*.dat files in `blocks_dir`, ordered by file number (blk00000.dat, blk00001.dat, ...)
+async fn blk_files(blocks_dir: &Path) -> io::Result<Vec<PathBuf>> {
     let mut entries = read_dir(blocks_dir).await?;
-    let mut height = 0;
+    let mut files = Vec::new();
 
     while let Some(entry) = entries.next_entry().await? {
         let filename = entry.file_name();
-        let filename_str = filename.to_string_lossy();
-
-        if filename_str.starts_with("blk") && filename_str.ends_with(".dat") {
-            println!("Processing file: {}", filename_str);
-
-            let file = File::open(entry.path())?;
-            let mut reader = BufReader::new(file);
-
-            while let Ok(block) = parse_block(&mut reader) {
-                match insert_block(db_conn, &block, height) {
-                    Ok(_) => {
-                        println!("Indexed block at height {}: {}", height, block.block_hash());
-                        height += 1;
-                    }
-                    Err(e) => {
-                        eprintln!("Error inserting block at height {}: {}", height, e);
-                    }
+        let number = filename.to_string_lossy()
+            .strip_prefix("blk")
+            .and_then(|name| name.strip_suffix(".dat"))
+            .and_then(|number| number.parse::<u32>().ok());
+        if let Some(number) = number {
+            files.push((number, entry.path()));
+        }
+    }
+
+    files.sort();
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
+
+// What ChainTracker places on the chain: a parsed block, or where one is stored
+trait ChainLink {
+    fn hash(&self) -> BlockHash;
+    fn prev_hash(&self) -> BlockHash;
+}
+
+impl ChainLink for Block {
+    fn hash(&self) -> BlockHash {
+        self.block_hash()
+    }
+
+    fn prev_hash(&self) -> BlockHash {
+        self.header.prev_blockhash
+    }
+}
+
+// Assigns heights by following prev_blockhash links. The node writes blocks to disk in the
+// order it received them, so a block can appear before its parent; such orphans are held
+// until the parent shows up. Competing blocks at the same height both get that height.
+struct ChainTracker<T> {
+    heights: HashMap<BlockHash, u32>,
+    // Orphans keyed by the parent they are waiting for
+    orphans: HashMap<BlockHash, Vec<T>>,
+}
+
+impl<T> Default for ChainTracker<T> {
+    fn default() -> Self {
+        Self { heights: HashMap::new(), orphans: HashMap::new() }
+    }
+}
+
+impl<T: ChainLink> ChainTracker<T> {
+    // Returns the blocks that can now be placed on the chain, parents before children
+    fn connect(&mut self, block: T) -> Vec<(u32, T)> {
+        let parent = block.prev_hash();
+        let height = if parent == BlockHash::all_zeros() {
+            Some(0)
+        } else {
+            self.heights.get(&parent).map(|height| height + 1)
+        };
+        let Some(height) = height else {
+            self.orphans.entry(parent).or_default().push(block);
+            return Vec::new();
+        };
+
+        let mut connected = Vec::new();
+        let mut queue = VecDeque::from([(height, block)]);
+        while let Some((height, block)) = queue.pop_front() {
+            let hash = block.hash();
+            self.heights.insert(hash, height);
+            if let Some(children) = self.orphans.remove(&hash) {
+                queue.extend(children.into_iter().map(|child| (height + 1, child)));
+            }
+            connected.push((height, block));
+        }
+        connected
+    }
+
+    fn orphan_count(&self) -> usize {
+        self.orphans.values().map(Vec::len).sum()
+    }
+
+    // Place a block that isn't passed to connect, e.g. one already stored
+    fn insert_known(&mut self, hash: BlockHash, height: u32) {
+        self.heights.insert(hash, height);
+    }
+}
+
+// Where a block is stored in the .blk files, found by the first pass of a parallel import
+struct BlockLocation {
+    // Index into the list of files
+    file: usize,
+    // Offset of the serialized block, after its magic bytes and size
+    offset: u64,
+    size: usize,
+    hash: BlockHash,
+    prev_hash: BlockHash,
+}
+
+impl ChainLink for BlockLocation {
+    fn hash(&self) -> BlockHash {
+        self.hash
+    }
+
+    fn prev_hash(&self) -> BlockHash {
+        self.prev_hash
+    }
+}
+
+// Blocks stored per chunk handed to a pass-two worker
+const LOCATION_CHUNK_SIZE: usize = 64;
+
+// Print the totals of a finished index_blocks run, counting orphans as skipped
+fn report(totals: &mut IndexSummary, orphans: usize, dry_run: bool) {
+    if orphans > 0 {
+        totals.skipped += orphans;
+        totals.error(&Progress::hidden(), format!("Skipped {} orphan blocks whose parent wasn't found", orphans));
+    }
+    if dry_run {
+        status!(
+            "Dry run: {} blocks with {} transactions would be indexed, {} failed validation",
+            totals.indexed, totals.transactions, totals.invalid
+        );
+    } else {
+        status!("Finished indexing {} blocks with {} transactions", totals.indexed, totals.transactions);
+        if totals.already_stored > 0 {
+            status!("Already stored: {} (left as they were; --on-conflict update rewrites them)", totals.already_stored);
+        }
+    }
+}
+
+// Store a block placed at `height`, or with `dry_run` only validate it
+fn store_block(
+    db_conn: &Connection,
+    block: &Block,
+    height: u32,
+    config: &ChainConfig,
+    dry_run: bool,
+    progress: &Progress,
+    totals: &mut IndexSummary,
+) {
+    if dry_run {
+        // The tracker already placed the block on its parent
+        match validate_block(block, None, None) {
+            Ok(()) => {
+                totals.indexed += 1;
+                totals.transactions += block.txdata.len();
+            }
+            Err(e) => {
+                totals.error(progress, format!("Invalid block at height {} ({}): {}", height, block.block_hash(), e));
+                totals.invalid += 1;
+            }
+        }
+        progress.advance(height);
+        return;
+    }
+    match insert_block(db_conn, block, height, config) {
+        Ok(true) => {
+            progress.indexed(height, block.block_hash());
+            totals.indexed += 1;
+            totals.transactions += block.txdata.len();
+        }
+        Ok(false) => {
+            totals.already_stored += 1;
+            progress.advance(height);
+        }
+        Err(e) => {
+            totals.error(progress, format!("Error inserting block at height {}: {}", height, e));
+            totals.skipped += 1;
+        }
+    }
+}
+
+// Index all blocks from a directory of .blk files, stopping between blocks once `shutdown` is set.
+// Heights come from the prev_blockhash chain rather than file position; blocks whose parent
+// never appears in the files are reported as orphans and not indexed.
+// Only blocks whose derived height is in `heights` are indexed.
+// With `jobs` above 1 the files are parsed in parallel; see index_blocks_parallel.
+// With `dry_run`, blocks are parsed and checked with validate_block but nothing is written.
+#[allow(clippy::too_many_arguments)]
+pub async fn index_blocks(
+    db_conn: &Connection,
+    blocks_dir: &Path,
+    config: &ChainConfig,
+    shutdown: &AtomicBool,
+    heights: RangeInclusive<u32>,
+    jobs: usize,
+    dry_run: bool,
+    show_progress: bool,
+) -> anyhow::Result<IndexSummary> {
+    let files = blk_files(blocks_dir).await?;
+    if jobs > 1 {
+        return index_blocks_parallel(db_conn, files, config, shutdown, heights, jobs, dry_run, show_progress).await;
+    }
+
+    let mut chain = ChainTracker::default();
+    let mut totals = IndexSummary::default();
+
+    let mut total_bytes = 0;
+    for path in &files {
+        total_bytes += std::fs::metadata(path)?.len();
+    }
+    let progress = Progress::bytes(show_progress, total_bytes);
+    // Bytes in the files already read
+    let mut done_bytes = 0;
+
+    for path in files {
+        let filename = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        progress.println(format!("Processing file: {}", filename));
+
+        let file = File::open(&path)?;
+        let file_bytes = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        loop {
+            let block = match parse_block(&mut reader, config) {
+                Ok(Some(block)) => block,
+                Ok(None) => break,
+                // The node may still be appending to its newest file
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    progress.eprintln(format!("{}: ignoring partial block at end of file", filename));
+                    break;
+                }
+                Err(e) => {
+                    progress.finish();
+                    anyhow::bail!("{}: {}", filename, e)
+                }
+            };
+            progress.set_position(done_bytes + reader.stream_position()?);
+            for (height, block) in chain.connect(block) {
+                if shutdown.load(Ordering::SeqCst) {
+                    progress.finish();
+                    status!("Stopped after indexing {} blocks", totals.indexed);
+                    totals.interrupted = true;
+                    return Ok(totals);
+                }
+                if heights.contains(&height) {
+                    store_block(db_conn, &block, height, config, dry_run, &progress, &mut totals);
+                }
+            }
+        }
+        done_bytes += file_bytes;
+    }
+    progress.finish();
+
+    report(&mut totals, chain.orphan_count(), dry_run);
+    Ok(totals)
+}
+
+// Index blocks streamed through `reader` (stdin) in the .blk framing, or with `raw` as bare
+// consensus-serialized blocks back to back. Heights come from the prev_blockhash chain as in
+// index_blocks, which here may also start on a block that's already stored.
+pub fn index_stream(
+    db_conn: &Connection,
+    mut reader: impl BufRead,
+    raw: bool,
+    config: &ChainConfig,
+    shutdown: &AtomicBool,
+    heights: RangeInclusive<u32>,
+    dry_run: bool,
+) -> anyhow::Result<IndexSummary> {
+    let mut chain = ChainTracker::default();
+    let mut totals = IndexSummary::default();
+    let progress = Progress::hidden();
+
+    loop {
+        let block = match if raw { parse_raw_block(&mut reader) } else { parse_block(&mut reader, config) } {
+            Ok(Some(block)) => block,
+            Ok(None) => break,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => anyhow::bail!("Input ended partway through a block"),
+            Err(e) => anyhow::bail!("Invalid block in input: {}", e),
+        };
+        let parent = block.header.prev_blockhash;
+        if let Some(height) = query_block_height(db_conn, &parent.to_string())? {
+            chain.insert_known(parent, height);
+        }
+        for (height, block) in chain.connect(block) {
+            if shutdown.load(Ordering::SeqCst) {
+                status!("Stopped after indexing {} blocks", totals.indexed);
+                totals.interrupted = true;
+                return Ok(totals);
+            }
+            if heights.contains(&height) {
+                store_block(db_conn, &block, height, config, dry_run, &progress, &mut totals);
+            }
+        }
+    }
+
+    report(&mut totals, chain.orphan_count(), dry_run);
+    Ok(totals)
+}
+
+// The blocks found in one .blk file
+struct FileScan {
+    locations: Vec<BlockLocation>,
+    // Whether a partial block at the end of the file was left out
+    partial_tail: bool,
+}
+
+// Locate every block in a .blk file by reading only the frames and headers, skipping over
+// the transactions. A partial block at the end of the file is left out, as in index_blocks.
+fn scan_file(path: &Path, file: usize, config: &ChainConfig) -> io::Result<FileScan> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let file_bytes = reader.get_ref().metadata()?.len();
+    let mut locations = Vec::new();
+    loop {
+        let size = match read_block_frame(&mut reader, config) {
+            Ok(Some(size)) => size,
+            Ok(None) => break,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        };
+        let offset = reader.stream_position()?;
+        if offset + size as u64 > file_bytes {
+            return Ok(FileScan { locations, partial_tail: true });
+        }
+        let header: Header = bitcoin::consensus::Decodable::consensus_decode(&mut reader)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        reader.seek(SeekFrom::Start(offset + size as u64))?;
+        locations.push(BlockLocation {
+            file,
+            offset,
+            size,
+            hash: header.block_hash(),
+            prev_hash: header.prev_blockhash,
+        });
+    }
+    Ok(FileScan { locations, partial_tail: false })
+}
+
+// Read and decode the blocks at `locations`, which are in the same order as they'll be stored
+fn read_blocks(files: &[PathBuf], locations: &[(u32, BlockLocation)]) -> io::Result<Vec<(u32, Block)>> {
+    let mut open: Option<(usize, File)> = None;
+    let mut blocks = Vec::with_capacity(locations.len());
+    for (height, location) in locations {
+        let file = match &mut open {
+            Some((index, file)) if *index == location.file => file,
+            _ => &mut open.insert((location.file, File::open(&files[location.file])?)).1,
+        };
+        file.seek(SeekFrom::Start(location.offset))?;
+        let mut block_data = vec![0u8; location.size];
+        file.read_exact(&mut block_data)?;
+        let block = bitcoin::consensus::deserialize(&block_data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        blocks.push((*height, block));
+    }
+    Ok(blocks)
+}
+
+// index_blocks with `jobs` parallel workers, for bulk imports where decoding is the bottleneck.
+// Heights need the whole chain, so this takes two passes: the files are scanned in parallel
+// for block headers, which are linked into height order, then workers decode the blocks in
+// chunks while this task stores them in that order.
+#[allow(clippy::too_many_arguments)]
+async fn index_blocks_parallel(
+    db_conn: &Connection,
+    files: Vec<PathBuf>,
+    config: &ChainConfig,
+    shutdown: &AtomicBool,
+    heights: RangeInclusive<u32>,
+    jobs: usize,
+    dry_run: bool,
+    show_progress: bool,
+) -> anyhow::Result<IndexSummary> {
+    status!("Scanning {} block files with {} workers...", files.len(), jobs);
+    let files = Arc::new(files);
+    let config = *config;
+    let mut scans = stream::iter(0..files.len())
+        .map(|file| {
+            let files = Arc::clone(&files);
+            tokio::task::spawn_blocking(move || scan_file(&files[file], file, &config))
+        })
+        .buffered(jobs)
+        .enumerate();
+
+    // Files are linked in order so competing blocks and orphans come out as in index_blocks
+    let mut chain = ChainTracker::default();
+    let mut ordered = Vec::new();
+    let mut partial_tails = Vec::new();
+    while let Some((file, scan)) = scans.next().await {
+        let scan = scan?.map_err(|e| anyhow::anyhow!("{}: {}", files[file].display(), e))?;
+        if scan.partial_tail {
+            partial_tails.push(file);
+        }
+        for location in scan.locations {
+            ordered.extend(chain.connect(location).into_iter().filter(|(height, _)| heights.contains(height)));
+        }
+    }
+    status!("Found {} blocks to index", ordered.len());
+
+    let mut chunks: Vec<Vec<(u32, BlockLocation)>> = Vec::new();
+    let mut ordered = ordered.into_iter().peekable();
+    while ordered.peek().is_some() {
+        chunks.push(ordered.by_ref().take(LOCATION_CHUNK_SIZE).collect());
+    }
+    let chunks = Arc::new(chunks);
+    let total_blocks = chunks.iter().map(Vec::len).sum::<usize>();
+
+    // Workers claim chunks in order, so at most `jobs` chunks are ever out of order
+    let next_chunk = Arc::new(AtomicUsize::new(0));
+    let (sender, mut receiver) = mpsc::channel(jobs * 2);
+    for _ in 0..jobs {
+        let files = Arc::clone(&files);
+        let chunks = Arc::clone(&chunks);
+        let next_chunk = Arc::clone(&next_chunk);
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            loop {
+                let index = next_chunk.fetch_add(1, Ordering::SeqCst);
+                if index >= chunks.len() {
+                    break;
+                }
+                let files = Arc::clone(&files);
+                let chunks = Arc::clone(&chunks);
+                let blocks = tokio::task::spawn_blocking(move || read_blocks(&files, &chunks[index]))
+                    .await
+                    .unwrap_or_else(|e| Err(io::Error::other(e)));
+                if sender.send((index, blocks)).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(sender);
+
+    let progress = Progress::blocks(show_progress, total_blocks as u64);
+    for file in partial_tails {
+        // The node may still be appending to its newest file
+        progress.eprintln(format!("{}: ignoring partial block at end of file", files[file].display()));
+    }
+    let mut totals = IndexSummary::default();
+    let mut pending = BTreeMap::new();
+    let mut next_write = 0;
+    while let Some((index, blocks)) = receiver.recv().await {
+        pending.insert(index, blocks);
+        while let Some(blocks) = pending.remove(&next_write) {
+            next_write += 1;
+            let blocks = match blocks {
+                Ok(blocks) => blocks,
+                Err(e) => {
+                    progress.finish();
+                    anyhow::bail!("Failed to read a block: {}", e)
                 }
+            };
+            for (height, block) in blocks {
+                if shutdown.load(Ordering::SeqCst) {
+                    progress.finish();
+                    status!("Stopped after indexing {} blocks", totals.indexed);
+                    totals.interrupted = true;
+                    return Ok(totals);
+                }
+                store_block(db_conn, &block, height, &config, dry_run, &progress, &mut totals);
             }
         }
     }
+    progress.finish();
+
+    report(&mut totals, chain.orphan_count(), dry_run);
+    Ok(totals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
 
-    println!("Finished indexing {} blocks", height);
-    Ok(())
-}
\ No newline at end of file
+    #[test]
+    fn orphans_connect_once_their_parent_arrives() {
+        let block0 = test_block(BlockHash::all_zeros(), 0, vec![coinbase_tx(0, 5_000)]);
+        let block1 = test_block(block0.block_hash(), 1, vec![coinbase_tx(1, 5_000)]);
+        let block2 = test_block(block1.block_hash(), 2, vec![coinbase_tx(2, 5_000)]);
+
+        let mut chain = ChainTracker::default();
+        assert!(chain.connect(block2.clone()).is_empty());
+        assert_eq!(chain.orphan_count(), 1);
+        let connected: Vec<_> = chain.connect(block0.clone()).into_iter()
+            .map(|(height, block)| (height, block.block_hash()))
+            .collect();
+        assert_eq!(connected, vec![(0, block0.block_hash())]);
+        let connected: Vec<_> = chain.connect(block1.clone()).into_iter()
+            .map(|(height, block)| (height, block.block_hash()))
+            .collect();
+        assert_eq!(connected, vec![(1, block1.block_hash()), (2, block2.block_hash())]);
+        assert_eq!(chain.orphan_count(), 0);
+    }
+
+    #[test]
+    fn stream_continues_from_stored_blocks() {
+        let conn = test_db();
+        let config = ChainConfig::default();
+        let shutdown = AtomicBool::new(false);
+        let block0 = test_block(BlockHash::all_zeros(), 0, vec![coinbase_tx(0, 5_000)]);
+        let block1 = test_block(block0.block_hash(), 1, vec![coinbase_tx(1, 5_000)]);
+        let block2 = test_block(block1.block_hash(), 2, vec![coinbase_tx(2, 5_000)]);
+
+        // Framed as in .blk files, with the padding the node leaves between blocks
+        let mut framed = Vec::new();
+        for block in [&block0, &block1] {
+            let bytes = bitcoin::consensus::serialize(block);
+            framed.extend(config.magic());
+            framed.extend((bytes.len() as u32).to_le_bytes());
+            framed.extend(bytes);
+            framed.extend([0; 8]);
+        }
+        let summary = index_stream(&conn, framed.as_slice(), false, &config, &shutdown, 0..=u32::MAX, false).unwrap();
+        assert_eq!((summary.indexed, summary.skipped), (2, 0));
+
+        // A bare block whose parent is only in the database
+        let raw = bitcoin::consensus::serialize(&block2);
+        let summary = index_stream(&conn, raw.as_slice(), true, &config, &shutdown, 0..=u32::MAX, false).unwrap();
+        assert_eq!((summary.indexed, summary.skipped), (1, 0));
+        assert_eq!(query_block_height(&conn, &block2.block_hash().to_string()).unwrap(), Some(2));
+
+        assert!(index_stream(&conn, &raw[..raw.len() - 1], true, &config, &shutdown, 0..=u32::MAX, false).is_err());
+    }
+
+    #[tokio::test]
+    async fn partial_block_at_the_end_of_a_blk_file_is_eof() {
+        let config = ChainConfig::default();
+        let shutdown = AtomicBool::new(false);
+        let block0 = test_block(BlockHash::all_zeros(), 0, vec![coinbase_tx(0, 5_000)]);
+        let block1 = test_block(block0.block_hash(), 1, vec![coinbase_tx(1, 5_000)]);
+        let block2 = test_block(block1.block_hash(), 2, vec![coinbase_tx(2, 5_000)]);
+        let frame = |block: &Block| {
+            let bytes = bitcoin::consensus::serialize(block);
+            let mut framed = config.magic().to_vec();
+            framed.extend((bytes.len() as u32).to_le_bytes());
+            framed.extend(bytes);
+            framed
+        };
+
+        // A block, a run of padding, a block, then one the node was still writing
+        let mut data = frame(&block0);
+        data.extend([0; 4096]);
+        data.extend(frame(&block1));
+        let partial = frame(&block2);
+        data.extend(&partial[..partial.len() / 2]);
+
+        let mut reader = data.as_slice();
+        assert_eq!(parse_block(&mut reader, &config).unwrap().map(|b| b.block_hash()), Some(block0.block_hash()));
+        assert_eq!(parse_block(&mut reader, &config).unwrap().map(|b| b.block_hash()), Some(block1.block_hash()));
+        assert_eq!(parse_block(&mut reader, &config).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        let dir = std::env::temp_dir().join(format!("blk-partial-tail-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("blk00000.dat"), &data).unwrap();
+        let scan = scan_file(&dir.join("blk00000.dat"), 0, &config).unwrap();
+        assert_eq!((scan.locations.len(), scan.partial_tail), (2, true));
+        for jobs in [1, 2] {
+            let conn = test_db();
+            let summary = index_blocks(&conn, &dir, &config, &shutdown, 0..=u32::MAX, jobs, false, false).await;
+            let summary = summary.unwrap_or_else(|e| panic!("jobs {}: {}", jobs, e));
+            assert_eq!((summary.indexed, summary.skipped), (2, 0), "jobs {}", jobs);
+            assert_eq!(query_block_height(&conn, &block1.block_hash().to_string()).unwrap(), Some(1));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::fmt::Display;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// What index and verify print to stdout
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    // Status lines as they happen, then a summary in prose
+    Text,
+    // Only a JSON summary at the end; status lines go to stderr
+    Json,
+}
+
+static STATUS_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+// Keep stdout for a machine-readable summary by sending status lines to stderr from now on
+pub fn status_to_stderr() {
+    STATUS_TO_STDERR.store(true, Ordering::Relaxed);
+}
+
+// A status line for the person running the command, on stdout unless status_to_stderr was called
+pub fn status(line: impl Display) {
+    if STATUS_TO_STDERR.load(Ordering::Relaxed) {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+// println! for status lines; see status
+#[macro_export]
+macro_rules! status {
+    ($($arg:tt)*) => {
+        $crate::progress::status(format_args!($($arg)*))
+    };
+}
+
+// Reports indexing progress as a bar with rate and ETA, or as one line per indexed block when
+// bars are turned off or stderr isn't a terminal (e.g. output redirected to a log file)
+pub struct Progress {
+    bar: Option<ProgressBar>,
+    // Whether the bar counts blocks (RPC) or bytes of block files
+    counts_blocks: bool,
+}
+
+impl Progress {
+    // A bar over `count` blocks
+    pub fn blocks(show: bool, count: u64) -> Progress {
+        let style = ProgressStyle::with_template(
+            "{elapsed_precise} [{bar:40}] {human_pos}/{human_len} blocks ({per_sec}, ETA {eta}) {msg}",
+        ).expect("valid progress template");
+        Progress { bar: new_bar(show, count, style), counts_blocks: true }
+    }
+
+    // A bar over `total` bytes of .blk files
+    pub fn bytes(show: bool, total: u64) -> Progress {
+        let style = ProgressStyle::with_template(
+            "{elapsed_precise} [{bar:40}] {bytes}/{total_bytes} ({bytes_per_sec}, ETA {eta}) {msg}",
+        ).expect("valid progress template");
+        Progress { bar: new_bar(show, total, style), counts_blocks: false }
+    }
+
+    // Per-block lines only
+    pub fn hidden() -> Progress {
+        Progress { bar: None, counts_blocks: true }
+    }
+
+    // Blocks still to come after the ones handled so far, e.g. after a reorg moves the range
+    pub fn set_remaining(&self, remaining: u64) {
+        if let Some(bar) = &self.bar {
+            bar.set_length(bar.position() + remaining);
+        }
+    }
+
+    pub fn set_position(&self, position: u64) {
+        if let Some(bar) = &self.bar {
+            bar.set_position(position);
+        }
+    }
+
+    // A block at `height` was handled; the bar shows the latest height
+    pub fn advance(&self, height: u32) {
+        if let Some(bar) = &self.bar {
+            if self.counts_blocks {
+                bar.inc(1);
+            }
+            bar.set_message(format!("height {}", height));
+        }
+    }
+
+    // A block was stored: advances the bar, or prints a line when there's no bar
+    pub fn indexed(&self, height: u32, hash: impl Display) {
+        match &self.bar {
+            Some(_) => self.advance(height),
+            None => crate::status!("Indexed block at height {}: {}", height, hash),
+        }
+    }
+
+    // Print above the bar without garbling it
+    pub fn println(&self, line: impl Display) {
+        match &self.bar {
+            Some(bar) => bar.suspend(|| status(line)),
+            None => status(line),
+        }
+    }
+
+    pub fn eprintln(&self, line: impl Display) {
+        match &self.bar {
+            Some(bar) => bar.suspend(|| eprintln!("{}", line)),
+            None => eprintln!("{}", line),
+        }
+    }
+
+    // Clear the bar; the caller prints a summary in its place
+    pub fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+fn new_bar(show: bool, length: u64, style: ProgressStyle) -> Option<ProgressBar> {
+    if !show || !std::io::stderr().is_terminal() {
+        return None;
+    }
+    Some(ProgressBar::new(length).with_style(style.progress_chars("=> ")))
+}
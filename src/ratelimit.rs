@@ -0,0 +1,128 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, ResponseError};
+use dashmap::DashMap;
+use serde_json::json;
+use std::net::IpAddr;
+use std::time::Instant;
+use crate::error::ApiError;
+
+// Past this many tracked clients, buckets that have refilled completely are dropped
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+// Per-IP token buckets: each client may burst up to `per_minute` requests, refilled
+// continuously at `per_minute` tokens per minute
+pub struct RateLimiter {
+    per_minute: u32,
+    buckets: DashMap<IpAddr, Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute: per_minute.max(1),
+            buckets: DashMap::new(),
+        }
+    }
+
+    fn refill_per_sec(&self) -> f64 {
+        self.per_minute as f64 / 60.0
+    }
+
+    // Take a token for `ip`. On refusal returns the seconds until one is available.
+    fn check(&self, ip: IpAddr) -> Result<(), u64> {
+        let now = Instant::now();
+        let capacity = self.per_minute as f64;
+        let rate = self.refill_per_sec();
+
+        if self.buckets.len() > MAX_TRACKED_CLIENTS {
+            self.buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate < capacity
+            });
+        }
+
+        let mut bucket = self.buckets.entry(ip).or_insert(Bucket { tokens: capacity, updated: now });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / rate).ceil() as u64)
+        }
+    }
+}
+
+// Reject clients over their rate with 429. Does nothing unless a RateLimiter is registered
+// as app data; health checks are never limited so probes keep working.
+pub async fn limit_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let limiter = req.app_data::<web::Data<RateLimiter>>().cloned();
+    let ip = req.peer_addr().map(|addr| addr.ip());
+
+    if let (Some(limiter), Some(ip)) = (limiter, ip)
+        && !req.path().starts_with("/health")
+        && let Err(retry_after) = limiter.check(ip)
+    {
+        let mut response = ApiError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "rate_limited",
+            format!("Rate limit of {} requests per minute exceeded", limiter.per_minute),
+        ).with_details(json!({ "retry_after": retry_after })).error_response();
+        response.headers_mut().insert(header::RETRY_AFTER, retry_after.into());
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+    use actix_web::{middleware, App, HttpResponse};
+
+    #[test]
+    fn each_ip_gets_its_own_bucket() {
+        let limiter = RateLimiter::new(3);
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        let other: IpAddr = "192.0.2.2".parse().unwrap();
+        for _ in 0..3 {
+            assert_eq!(limiter.check(client), Ok(()));
+        }
+        // 3 a minute refills one token every 20 seconds
+        assert_eq!(limiter.check(client), Err(20));
+        assert_eq!(limiter.check(other), Ok(()));
+    }
+
+    #[actix_web::test]
+    async fn over_the_limit_is_429_except_for_health_checks() {
+        let app = init_service(
+            App::new()
+                .wrap(middleware::from_fn(limit_requests))
+                .app_data(web::Data::new(RateLimiter::new(1)))
+                .route("/tip", web::get().to(HttpResponse::Ok))
+                .route("/health", web::get().to(HttpResponse::Ok)),
+        ).await;
+        let get = |path: &str| TestRequest::get().uri(path).peer_addr("192.0.2.1:5000".parse().unwrap()).to_request();
+
+        assert_eq!(call_service(&app, get("/tip")).await.status(), StatusCode::OK);
+        let response = call_service(&app, get("/tip")).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "60");
+        let body: serde_json::Value = read_body_json(response).await;
+        assert_eq!((&body["error"]["code"], &body["error"]["details"]), (&json!("rate_limited"), &json!({"retry_after": 60})));
+        assert_eq!(call_service(&app, get("/health")).await.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,498 @@
+use bitcoin::absolute::LockTime;
+use bitcoin::block::{Header, Version};
+use bitcoin::consensus;
+use bitcoin::hashes::Hash;
+use bitcoin::transaction;
+use bitcoin::{Amount, BlockHash, CompactTarget, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxMerkleNode, TxOut, Txid, Witness};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use crate::error::IndexError;
+
+// Number of calls sent per JSON-RPC batch unless configured otherwise
+pub const DEFAULT_BATCH_SIZE: usize = 100;
+
+// Retries after a failed request unless configured otherwise, and the delay before the first;
+// each retry waits twice as long as the one before, up to MAX_RETRY_DELAY
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+pub const DEFAULT_RETRY_DELAY_MS: u64 = 500;
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+// A block fetched from the node. `node_txs` holds what the node reported about each transaction
+// (getblock verbosity 2), or None when fees and input values are left to be resolved from
+// indexed transactions.
+pub struct FetchedBlock {
+    pub hash: String,
+    pub block: bitcoin::Block,
+    pub node_txs: Option<Vec<NodeTxInfo>>,
+}
+
+// A transaction's fee and the values of the outputs it spends, in sats, as the node reports
+// them. The node omits the fee for the coinbase and when it has no undo data for the block;
+// input values come from each input's prevout, which the coinbase doesn't have.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NodeTxInfo {
+    pub fee: Option<u64>,
+    pub input_values: Vec<Option<u64>>,
+}
+
+// Parse an --rpc-verbosity value. 0 fetches raw hex; 2 fetches the node's decoded JSON.
+pub fn parse_rpc_verbosity(value: &str) -> Result<u8, String> {
+    match value {
+        "0" => Ok(0),
+        "2" => Ok(2),
+        other => Err(format!("unsupported verbosity '{}' (expected 0 or 2)", other)),
+    }
+}
+
+// Parse an --rpc-url value. reqwest only speaks HTTP(S) over TCP, so a node listening on a
+// Unix socket has to be forwarded to a TCP port first (or reached through --rpc-proxy).
+pub fn parse_rpc_url(value: &str) -> Result<String, String> {
+    let url = reqwest::Url::parse(value).map_err(|e| format!("'{}' is not a URL: {}", value, e))?;
+    match url.scheme() {
+        "http" | "https" => Ok(value.to_string()),
+        other => Err(format!(
+            "unsupported scheme '{}' (expected http or https); forward a Unix socket to a TCP port, e.g. with socat or ssh -L",
+            other
+        )),
+    }
+}
+
+// A transaction in the node's mempool, as reported by getrawmempool verbose
+pub struct RawMempoolEntry {
+    pub txid: String,
+    pub fee: u64,
+    pub vsize: usize,
+}
+
+// Minimal JSON-RPC client for a Bitcoin Core node
+pub struct BitcoinRpcClient {
+    client: reqwest::Client,
+    url: String,
+    user: String,
+    password: String,
+    batch_size: usize,
+    verbosity: u8,
+    max_retries: u32,
+    retry_delay: Duration,
+}
+
+impl BitcoinRpcClient {
+    pub fn new(url: &str, user: &str, password: &str) -> Self {
+        BitcoinRpcClient {
+            client: reqwest::Client::new(),
+            url: url.to_string(),
+            user: user.to_string(),
+            password: password.to_string(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            verbosity: 0,
+            max_retries: DEFAULT_MAX_RETRIES,
+            retry_delay: Duration::from_millis(DEFAULT_RETRY_DELAY_MS),
+        }
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    // getblock verbosity used when fetching blocks: 0 (raw hex) or 2 (decoded JSON)
+    pub fn with_verbosity(mut self, verbosity: u8) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    // How often a request that failed to reach the node is retried, and the delay before the first retry
+    pub fn with_retries(mut self, max_retries: u32, retry_delay: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    // Send every request through this proxy: http://, https://, socks5:// or socks5h:// (which
+    // resolves the node's hostname on the proxy's side). Without it the HTTP_PROXY, HTTPS_PROXY,
+    // ALL_PROXY and NO_PROXY environment variables are honored.
+    pub fn with_proxy(mut self, proxy: &str) -> anyhow::Result<Self> {
+        let url = reqwest::Url::parse(proxy)
+            .map_err(|e| anyhow::anyhow!("Invalid RPC proxy '{}': {}", proxy, e))?;
+        if !matches!(url.scheme(), "http" | "https" | "socks5" | "socks5h") {
+            anyhow::bail!("Invalid RPC proxy '{}': expected an http, https, socks5 or socks5h URL", proxy);
+        }
+        self.client = reqwest::Client::builder()
+            .proxy(reqwest::Proxy::all(url)?)
+            .build()?;
+        Ok(self)
+    }
+
+    // Read credentials from the node's .cookie file, which holds "user:password"
+    pub fn from_cookie(url: &str, cookie_path: &Path) -> anyhow::Result<Self> {
+        let cookie = std::fs::read_to_string(cookie_path)?;
+        let (user, password) = cookie.trim().split_once(':')
+            .ok_or_else(|| anyhow::anyhow!("Malformed cookie file: {}", cookie_path.display()))?;
+        Ok(Self::new(url, user, password))
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    // POST a JSON-RPC request and decode the reply. Connection failures and 5xx responses that
+    // don't carry a JSON-RPC reply (e.g. the node's 503 while its work queue is full, or a proxy
+    // error while it restarts) are retried with exponential backoff. JSON-RPC errors come back as
+    // a normal reply, even with a 500 status, so they're never retried; the caller sees them.
+    async fn post<T: DeserializeOwned>(&self, body: &serde_json::Value) -> Result<T, IndexError> {
+        let mut attempt = 0;
+        loop {
+            let error = match self.send(body).await {
+                Ok(reply) => return Ok(reply),
+                Err(e) if attempt < self.max_retries && is_retryable(&e) => e,
+                Err(e) => return Err(e),
+            };
+            let delay = self.retry_delay.saturating_mul(2u32.saturating_pow(attempt)).min(MAX_RETRY_DELAY);
+            attempt += 1;
+            log::warn!(
+                attempt, max_retries = self.max_retries, delay_ms = delay.as_millis() as u64;
+                "RPC request failed ({}), retrying in {:?} ({}/{})", error, delay, attempt, self.max_retries
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn send<T: DeserializeOwned>(&self, body: &serde_json::Value) -> Result<T, IndexError> {
+        let response = self.client
+            .post(&self.url)
+            .basic_auth(&self.user, Some(&self.password))
+            .json(body)
+            .send()
+            .await?;
+        let status = response.status();
+        let text = response.text().await?;
+        match serde_json::from_str(&text) {
+            Ok(reply) => Ok(reply),
+            Err(_) if !status.is_success() => Err(IndexError::HttpStatus(status.as_u16(), text)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Send a single JSON-RPC call and return its `result` field
+    async fn call(&self, method: &str, params: serde_json::Value) -> Result<serde_json::Value, IndexError> {
+        let mut result: serde_json::Value = self
+            .post(&json!({"jsonrpc": "1.0", "id": "1", "method": method, "params": params}))
+            .await?;
+        if !result["error"].is_null() {
+            return Err(IndexError::RpcError(result["error"].to_string()));
+        }
+        Ok(result["result"].take())
+    }
+
+    // Send several calls as one JSON-RPC batch. The outer error means the whole request failed;
+    // each entry carries its own result since the node reports errors per call.
+    async fn call_batch(&self, calls: &[(&str, serde_json::Value)]) -> Result<Vec<Result<serde_json::Value, IndexError>>, IndexError> {
+        let requests: Vec<serde_json::Value> = calls.iter().enumerate().map(|(id, (method, params))| {
+            json!({"jsonrpc": "1.0", "id": id, "method": method, "params": params})
+        }).collect();
+
+        let responses: Vec<serde_json::Value> = self.post(&serde_json::Value::Array(requests)).await?;
+
+        // Responses may come back in any order, so match them up by id
+        let mut by_id: HashMap<u64, serde_json::Value> = responses.into_iter()
+            .filter_map(|r| r["id"].as_u64().map(|id| (id, r)))
+            .collect();
+
+        Ok((0..calls.len() as u64).map(|id| {
+            let mut entry = by_id.remove(&id).ok_or(IndexError::MissingField("id"))?;
+            if !entry["error"].is_null() {
+                return Err(IndexError::RpcError(entry["error"].to_string()));
+            }
+            Ok(entry["result"].take())
+        }).collect())
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    // Height of the node's best block (getblockcount returns the tip height, not a count)
+    pub async fn get_block_count(&self) -> Result<u32, IndexError> {
+        let result = self.call("getblockcount", json!([])).await?;
+        Ok(result.as_u64().ok_or(IndexError::MissingField("result"))? as u32)
+    }
+
+    pub async fn get_block_hash(&self, height: u32) -> Result<String, IndexError> {
+        let result = self.call("getblockhash", json!([height])).await?;
+        Ok(result.as_str().ok_or(IndexError::MissingField("result"))?.to_string())
+    }
+
+    // Raw consensus-serialized block as hex (getblock verbosity 0)
+    pub async fn get_block_hex(&self, hash: &str) -> Result<String, IndexError> {
+        let result = self.call("getblock", json!([hash, 0])).await?;
+        Ok(result.as_str().ok_or(IndexError::MissingField("result"))?.to_string())
+    }
+
+    // All transactions currently in the node's mempool, with their fee in sats and vsize
+    pub async fn get_raw_mempool(&self) -> Result<Vec<RawMempoolEntry>, IndexError> {
+        let result = self.call("getrawmempool", json!([true])).await?;
+        let entries = result.as_object().ok_or(IndexError::MissingField("result"))?;
+
+        entries.iter().map(|(txid, entry)| {
+            let fee_btc = entry["fees"]["base"].as_f64().ok_or(IndexError::MissingField("fees.base"))?;
+            let fee = bitcoin::Amount::from_btc(fee_btc)
+                .map_err(|e| IndexError::RpcError(e.to_string()))?
+                .to_sat();
+            let vsize = entry["vsize"].as_u64().ok_or(IndexError::MissingField("vsize"))? as usize;
+            Ok(RawMempoolEntry { txid: txid.clone(), fee, vsize })
+        }).collect()
+    }
+
+    // Raw consensus-serialized transaction as hex (getrawtransaction non-verbose)
+    pub async fn get_raw_transaction_hex(&self, txid: &str) -> Result<String, IndexError> {
+        let result = self.call("getrawtransaction", json!([txid, false])).await?;
+        Ok(result.as_str().ok_or(IndexError::MissingField("result"))?.to_string())
+    }
+
+    // Submit a raw transaction to the node's mempool and relay it; returns the txid. The node's
+    // rejection reasons (e.g. a missing input or too low a fee) come back as RpcError.
+    pub async fn send_raw_transaction(&self, hex: &str) -> Result<String, IndexError> {
+        let result = self.call("sendrawtransaction", json!([hex])).await?;
+        Ok(result.as_str().ok_or(IndexError::MissingField("result"))?.to_string())
+    }
+
+    // Turn a getblock result into a block, from raw hex (verbosity 0) or decoded JSON (verbosity 2)
+    fn decode_block(&self, hash: String, result: serde_json::Value) -> Result<FetchedBlock, IndexError> {
+        if self.verbosity == 2 {
+            let (block, node_txs) = block_from_json(&result)?;
+            // The JSON is mapped field by field, so make sure nothing was lost on the way
+            if block.block_hash().to_string() != hash {
+                return Err(IndexError::RpcError(format!("decoded block doesn't hash to {}", hash)));
+            }
+            return Ok(FetchedBlock { hash, block, node_txs: Some(node_txs) });
+        }
+        let hex = result.as_str().ok_or(IndexError::MissingField("result"))?;
+        let block: bitcoin::Block = consensus::deserialize(&hex::decode(hex)?)?;
+        Ok(FetchedBlock { hash, block, node_txs: None })
+    }
+
+    // Fetch and decode the block at the given height
+    pub async fn get_block(&self, height: u32) -> Result<FetchedBlock, IndexError> {
+        let hash = self.get_block_hash(height).await?;
+        let result = self.call("getblock", json!([hash, self.verbosity])).await?;
+        self.decode_block(hash, result)
+    }
+
+    // Fetch and decode many blocks using batched getblockhash/getblock calls, `batch_size` heights
+    // at a time. Results line up with `heights`; a failed entry doesn't affect the others.
+    pub async fn get_blocks_batch(&self, heights: &[u32]) -> Result<Vec<Result<FetchedBlock, IndexError>>, IndexError> {
+        let mut blocks = Vec::with_capacity(heights.len());
+
+        for chunk in heights.chunks(self.batch_size) {
+            let hash_calls: Vec<(&str, serde_json::Value)> = chunk.iter()
+                .map(|height| ("getblockhash", json!([height])))
+                .collect();
+            let hashes: Vec<Result<String, IndexError>> = self.call_batch(&hash_calls).await?
+                .into_iter()
+                .map(|r| r.and_then(|v| v.as_str().map(str::to_string).ok_or(IndexError::MissingField("result"))))
+                .collect();
+
+            // Only ask for blocks whose hash lookup succeeded
+            let block_calls: Vec<(&str, serde_json::Value)> = hashes.iter()
+                .filter_map(|h| h.as_ref().ok())
+                .map(|hash| ("getblock", json!([hash, self.verbosity])))
+                .collect();
+            let mut results = self.call_batch(&block_calls).await?.into_iter();
+
+            for hash in hashes {
+                blocks.push(hash.and_then(|hash| {
+                    let result = results.next().ok_or(IndexError::MissingField("result"))??;
+                    self.decode_block(hash, result)
+                }));
+            }
+        }
+
+        Ok(blocks)
+    }
+}
+
+// Whether a failed request might succeed if sent again: the node couldn't be reached or
+// answered with a server error instead of a JSON-RPC reply
+fn is_retryable(error: &IndexError) -> bool {
+    match error {
+        IndexError::Transport(e) => !e.is_builder(),
+        IndexError::HttpStatus(status, _) => *status >= 500,
+        _ => false,
+    }
+}
+
+fn json_str<'a>(value: &'a serde_json::Value, field: &'static str) -> Result<&'a str, IndexError> {
+    value[field].as_str().ok_or(IndexError::MissingField(field))
+}
+
+fn json_u64(value: &serde_json::Value, field: &'static str) -> Result<u64, IndexError> {
+    value[field].as_u64().ok_or(IndexError::MissingField(field))
+}
+
+fn json_hash<T: std::str::FromStr>(value: &serde_json::Value, field: &'static str) -> Result<T, IndexError> {
+    json_str(value, field)?.parse().map_err(|_| IndexError::RpcError(format!("invalid {}", field)))
+}
+
+fn btc_to_sat(value: &serde_json::Value, field: &'static str) -> Result<u64, IndexError> {
+    let btc = value[field].as_f64().ok_or(IndexError::MissingField(field))?;
+    Amount::from_btc(btc)
+        .map(Amount::to_sat)
+        .map_err(|e| IndexError::RpcError(e.to_string()))
+}
+
+// Build a block from getblock verbosity 2 JSON, along with the fee and input values the node
+// reports for each transaction
+fn block_from_json(json: &serde_json::Value) -> Result<(bitcoin::Block, Vec<NodeTxInfo>), IndexError> {
+    let header = Header {
+        version: Version::from_consensus(json["version"].as_i64().ok_or(IndexError::MissingField("version"))? as i32),
+        // The genesis block has no previousblockhash
+        prev_blockhash: match json.get("previousblockhash") {
+            Some(_) => json_hash(json, "previousblockhash")?,
+            None => BlockHash::all_zeros(),
+        },
+        merkle_root: json_hash::<TxMerkleNode>(json, "merkleroot")?,
+        time: json_u64(json, "time")? as u32,
+        bits: CompactTarget::from_unprefixed_hex(json_str(json, "bits")?)
+            .map_err(|e| IndexError::RpcError(e.to_string()))?,
+        nonce: json_u64(json, "nonce")? as u32,
+    };
+
+    let txs = json["tx"].as_array().ok_or(IndexError::MissingField("tx"))?;
+    let mut txdata = Vec::with_capacity(txs.len());
+    let mut node_txs = Vec::with_capacity(txs.len());
+    for tx in txs {
+        txdata.push(transaction_from_json(tx)?);
+        let fee = match tx.get("fee") {
+            Some(_) => Some(btc_to_sat(tx, "fee")?),
+            None => None,
+        };
+        let input_values = tx["vin"].as_array().into_iter().flatten()
+            .map(|input| match input.get("prevout") {
+                Some(prevout) => btc_to_sat(prevout, "value").map(Some),
+                None => Ok(None),
+            })
+            .collect::<Result<_, IndexError>>()?;
+        node_txs.push(NodeTxInfo { fee, input_values });
+    }
+
+    Ok((bitcoin::Block { header, txdata }, node_txs))
+}
+
+fn transaction_from_json(json: &serde_json::Value) -> Result<Transaction, IndexError> {
+    let vin = json["vin"].as_array().ok_or(IndexError::MissingField("vin"))?;
+    let input = vin.iter().map(|input| {
+        let witness: Vec<Vec<u8>> = match input["txinwitness"].as_array() {
+            Some(items) => items.iter()
+                .map(|item| Ok(hex::decode(item.as_str().ok_or(IndexError::MissingField("txinwitness"))?)?))
+                .collect::<Result<_, IndexError>>()?,
+            None => Vec::new(),
+        };
+        // A coinbase input carries its script in "coinbase" and spends the null outpoint
+        let (previous_output, script_sig) = match input.get("coinbase") {
+            Some(_) => (OutPoint::null(), hex::decode(json_str(input, "coinbase")?)?),
+            None => (
+                OutPoint::new(json_hash::<Txid>(input, "txid")?, json_u64(input, "vout")? as u32),
+                hex::decode(json_str(&input["scriptSig"], "hex")?)?,
+            ),
+        };
+        Ok(TxIn {
+            previous_output,
+            script_sig: ScriptBuf::from_bytes(script_sig),
+            sequence: Sequence(json_u64(input, "sequence")? as u32),
+            witness: Witness::from_slice(&witness),
+        })
+    }).collect::<Result<Vec<_>, IndexError>>()?;
+
+    let vout = json["vout"].as_array().ok_or(IndexError::MissingField("vout"))?;
+    let output = vout.iter().map(|output| {
+        Ok(TxOut {
+            value: Amount::from_sat(btc_to_sat(output, "value")?),
+            script_pubkey: ScriptBuf::from_bytes(hex::decode(json_str(&output["scriptPubKey"], "hex")?)?),
+        })
+    }).collect::<Result<Vec<_>, IndexError>>()?;
+
+    Ok(Transaction {
+        version: transaction::Version(json["version"].as_i64().ok_or(IndexError::MissingField("version"))? as i32),
+        lock_time: LockTime::from_consensus(json_u64(json, "locktime")? as u32),
+        input,
+        output,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Requests each route of the mock node has seen
+    #[derive(Default)]
+    struct Requests {
+        flaky: AtomicUsize,
+        down: AtomicUsize,
+        rejects: AtomicUsize,
+    }
+
+    fn busy() -> HttpResponse {
+        HttpResponse::ServiceUnavailable().body("Work queue depth exceeded")
+    }
+
+    // Busy for the first two requests, then answers
+    async fn flaky(requests: web::Data<Requests>) -> HttpResponse {
+        match requests.flaky.fetch_add(1, Ordering::SeqCst) {
+            0 | 1 => busy(),
+            _ => HttpResponse::Ok().json(json!({"result": 42, "error": null, "id": "1"})),
+        }
+    }
+
+    async fn down(requests: web::Data<Requests>) -> HttpResponse {
+        requests.down.fetch_add(1, Ordering::SeqCst);
+        busy()
+    }
+
+    // A JSON-RPC error, which the node sends with a 500
+    async fn rejects(requests: web::Data<Requests>) -> HttpResponse {
+        requests.rejects.fetch_add(1, Ordering::SeqCst);
+        HttpResponse::InternalServerError()
+            .json(json!({"result": null, "error": {"code": -8, "message": "Block height out of range"}, "id": "1"}))
+    }
+
+    #[actix_web::test]
+    async fn busy_node_is_retried_but_rpc_errors_are_not() {
+        let requests = web::Data::new(Requests::default());
+        let server = HttpServer::new({
+            let requests = requests.clone();
+            move || App::new()
+                .app_data(requests.clone())
+                .route("/flaky", web::post().to(flaky))
+                .route("/down", web::post().to(down))
+                .route("/rejects", web::post().to(rejects))
+        })
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+        let url = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+        let client = |path: &str, retries| {
+            BitcoinRpcClient::new(&format!("{}{}", url, path), "user", "password")
+                .with_retries(retries, Duration::from_millis(1))
+        };
+
+        assert_eq!(client("/flaky", 3).get_block_count().await.unwrap(), 42);
+        assert_eq!(requests.flaky.load(Ordering::SeqCst), 3);
+
+        let error = client("/down", 2).get_block_count().await.unwrap_err();
+        assert!(matches!(error, IndexError::HttpStatus(503, _)), "{}", error);
+        assert_eq!(requests.down.load(Ordering::SeqCst), 3);
+
+        let error = client("/rejects", 3).get_block_hash(1_000).await.unwrap_err();
+        assert!(matches!(error, IndexError::RpcError(_)), "{}", error);
+        assert_eq!(requests.rejects.load(Ordering::SeqCst), 1);
+        handle.stop(false).await;
+    }
+}
@@ -0,0 +1,220 @@
+use bitcoin::{EcdsaSighashType, Script};
+use bitcoin::opcodes::{Class, ClassifyContext, Opcode};
+use bitcoin::script::Instruction;
+
+// Classify an output script by its standard template
+pub fn output_script_type(script: &Script) -> &'static str {
+    if script.is_p2pkh() {
+        "p2pkh"
+    } else if script.is_p2sh() {
+        "p2sh"
+    } else if script.is_p2wpkh() {
+        "p2wpkh"
+    } else if script.is_p2wsh() {
+        "p2wsh"
+    } else if script.is_p2tr() {
+        "p2tr"
+    } else if script.is_op_return() {
+        "op_return"
+    } else {
+        "nonstandard"
+    }
+}
+
+// Address for a standard output script; None for OP_RETURN and non-standard scripts
+pub fn output_address(script: &Script, network: bitcoin::Network) -> Option<String> {
+    match output_script_type(script) {
+        "op_return" | "nonstandard" => None,
+        _ => bitcoin::Address::from_script(script, network).ok().map(|a| a.to_string()),
+    }
+}
+
+// Classify the output an input spends from how it unlocks it. Heuristics, in order:
+// - coinbase: the input of a coinbase transaction
+// - no witness: two pushes ending in a public key (33 or 65 bytes) is p2pkh; any other push-only
+//   script_sig is p2sh (the last push is the redeem script)
+// - witness with a script_sig: p2sh wrapping a segwit program (p2sh-p2wpkh / p2sh-p2wsh)
+// - witness only: two items ending in a 33-byte public key is p2wpkh. After dropping a taproot
+//   annex (a last item starting with 0x50), a single 64/65-byte signature is p2tr-keypath, and a
+//   last item shaped like a control block (0xc0/0xc1 leaf version, 33 + 32n bytes) is
+//   p2tr-scriptpath. Anything else is p2wsh (the last item is the witness script).
+// - nonstandard when none of these match, e.g. a bare p2pk or multisig spend
+pub fn input_script_type(script_sig: &Script, witness: &bitcoin::Witness, is_coinbase: bool) -> &'static str {
+    if is_coinbase {
+        return "coinbase";
+    }
+
+    if witness.is_empty() {
+        let pushes: Option<Vec<&[u8]>> = script_sig.instructions()
+            .map(|instruction| match instruction {
+                Ok(Instruction::PushBytes(bytes)) => Some(bytes.as_bytes()),
+                _ => None,
+            })
+            .collect();
+        return match pushes.as_deref() {
+            Some([_, pubkey]) if pubkey.len() == 33 || pubkey.len() == 65 => "p2pkh",
+            Some(pushes) if !pushes.is_empty() => "p2sh",
+            _ => "nonstandard",
+        };
+    }
+
+    if !script_sig.is_empty() {
+        return "p2sh";
+    }
+
+    let items: Vec<&[u8]> = witness.iter().collect();
+    if let [_, pubkey] = items[..]
+        && pubkey.len() == 33
+        && (pubkey[0] == 0x02 || pubkey[0] == 0x03)
+    {
+        return "p2wpkh";
+    }
+
+    let items = match items.split_last() {
+        Some((annex, rest)) if items.len() >= 2 && annex.first() == Some(&0x50) => rest,
+        _ => &items[..],
+    };
+    match items {
+        [signature] if signature.len() == 64 || signature.len() == 65 => "p2tr-keypath",
+        [.., _, control]
+            if control.len() >= 33 && (control.len() - 33) % 32 == 0 && control[0] & 0xfe == 0xc0 =>
+        {
+            "p2tr-scriptpath"
+        }
+        _ => "p2wsh",
+    }
+}
+
+// Disassemble a script the way Bitcoin Core's asm fields do: pushes of up to 4 bytes as numbers,
+// longer ones as hex, OP_1NEGATE..OP_16 as numbers and other opcodes by name. A push running
+// past the end of the script ends the output with [error].
+pub fn script_to_asm(script: &Script) -> String {
+    disassemble(script, false)
+}
+
+// Like script_to_asm, but a push holding a DER signature is shown with its sighash type split
+// off, e.g. 3044...01 becomes 3044...[ALL], as Core does for scriptSigs
+pub fn script_sig_to_asm(script: &Script) -> String {
+    disassemble(script, true)
+}
+
+fn disassemble(script: &Script, decode_sighash: bool) -> String {
+    let mut parts = Vec::new();
+    for instruction in script.instructions() {
+        match instruction {
+            Ok(Instruction::PushBytes(bytes)) => parts.push(push_asm(bytes.as_bytes(), decode_sighash)),
+            Ok(Instruction::Op(op)) => parts.push(opcode_asm(op)),
+            Err(_) => {
+                parts.push("[error]".to_string());
+                break;
+            }
+        }
+    }
+    parts.join(" ")
+}
+
+fn push_asm(data: &[u8], decode_sighash: bool) -> String {
+    if let Ok(number) = bitcoin::script::read_scriptint_non_minimal(data) {
+        return number.to_string();
+    }
+    if decode_sighash
+        && let Ok(signature) = bitcoin::ecdsa::Signature::from_slice(data)
+    {
+        let sighash = match signature.sighash_type {
+            EcdsaSighashType::All => "ALL",
+            EcdsaSighashType::None => "NONE",
+            EcdsaSighashType::Single => "SINGLE",
+            EcdsaSighashType::AllPlusAnyoneCanPay => "ALL|ANYONECANPAY",
+            EcdsaSighashType::NonePlusAnyoneCanPay => "NONE|ANYONECANPAY",
+            EcdsaSighashType::SinglePlusAnyoneCanPay => "SINGLE|ANYONECANPAY",
+        };
+        return format!("{}[{}]", hex::encode(&data[..data.len() - 1]), sighash);
+    }
+    hex::encode(data)
+}
+
+// Core's names where they differ from the bitcoin crate's: the NOP-upgraded timelock opcodes
+// are spelled out and undefined opcodes are OP_UNKNOWN
+fn opcode_asm(op: Opcode) -> String {
+    if let Class::PushNum(number) = op.classify(ClassifyContext::Legacy) {
+        return number.to_string();
+    }
+    match op.to_u8() {
+        0xb1 => "OP_CHECKLOCKTIMEVERIFY".to_string(),
+        0xb2 => "OP_CHECKSEQUENCEVERIFY".to_string(),
+        0xbb..=0xfe => "OP_UNKNOWN".to_string(),
+        _ => op.to_string(),
+    }
+}
+
+// Data pushed after OP_RETURN, concatenated across pushes; None if the script isn't an OP_RETURN.
+// Stops at the first opcode that isn't a push, as the payload ends there.
+pub fn op_return_data(script: &Script) -> Option<Vec<u8>> {
+    if !script.is_op_return() {
+        return None;
+    }
+    let mut data = Vec::new();
+    for instruction in script.instructions().skip(1) {
+        match instruction {
+            Ok(Instruction::PushBytes(bytes)) => data.extend_from_slice(bytes.as_bytes()),
+            _ => break,
+        }
+    }
+    Some(data)
+}
+
+// The payload as text if every byte is printable ASCII
+pub fn printable_ascii(data: &[u8]) -> Option<String> {
+    if !data.is_empty() && data.iter().all(|b| (0x20..=0x7e).contains(b)) {
+        Some(String::from_utf8_lossy(data).into_owned())
+    } else {
+        None
+    }
+}
+
+// Block height a coinbase scriptSig starts with under BIP34, as a minimally pushed script number.
+// Coinbases from before BIP34 start with arbitrary data, so the caller should only trust a
+// height that matches the block's.
+pub fn coinbase_height(script_sig: &Script) -> Option<u32> {
+    let number = script_sig.instructions_minimal().next()?.ok()?.script_num()?;
+    u32::try_from(number).ok()
+}
+
+// Miner tag in a coinbase scriptSig: the runs of at least 4 printable ASCII characters in its
+// pushes, joined with spaces. Shorter runs are usually binary data (the height, extranonce)
+// that happens to be printable.
+pub fn coinbase_tag(script_sig: &Script) -> Option<String> {
+    let mut runs = Vec::new();
+    for instruction in script_sig.instructions() {
+        let Ok(Instruction::PushBytes(bytes)) = instruction else { continue };
+        for run in bytes.as_bytes().split(|b| !(0x20..=0x7e).contains(b)) {
+            let run = String::from_utf8_lossy(run);
+            let run = run.trim();
+            if run.len() >= 4 {
+                runs.push(run.to_string());
+            }
+        }
+    }
+    (!runs.is_empty()).then(|| runs.join(" "))
+}
+
+// Bits of the tx_flags column
+pub const TX_FLAG_HAS_WITNESS: u32 = 1;
+pub const TX_FLAG_SEGWIT: u32 = 1 << 1;
+pub const TX_FLAG_TAPROOT: u32 = 1 << 2;
+
+// has_witness: an input carries witness data. segwit: it has witness data or pays to a witness
+// program. taproot: it pays to a p2tr output.
+pub fn tx_flags(tx: &bitcoin::Transaction) -> u32 {
+    let mut flags = 0;
+    if tx.input.iter().any(|input| !input.witness.is_empty()) {
+        flags |= TX_FLAG_HAS_WITNESS | TX_FLAG_SEGWIT;
+    }
+    if tx.output.iter().any(|output| output.script_pubkey.is_witness_program()) {
+        flags |= TX_FLAG_SEGWIT;
+    }
+    if tx.output.iter().any(|output| output.script_pubkey.is_p2tr()) {
+        flags |= TX_FLAG_TAPROOT;
+    }
+    flags
+}
@@ -0,0 +1,104 @@
+use bitcoin::absolute::LockTime;
+use bitcoin::block::{Header, Version};
+use bitcoin::hashes::Hash;
+use bitcoin::script::Builder;
+use bitcoin::transaction;
+use bitcoin::{
+    Amount, Block, BlockHash, CompactTarget, OutPoint, ScriptBuf, Sequence, Transaction,
+    TxIn, TxMerkleNode, TxOut, WPubkeyHash, Witness,
+};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::Connection;
+use crate::config::ChainConfig;
+use crate::db::{init_schema, insert_block, DbPool};
+
+// Fixtures shared by the unit tests: an in-memory database with the full schema, and
+// small regtest blocks and transactions built in code
+
+// Fresh database with every migration applied
+pub fn test_db() -> Connection {
+    let conn = Connection::open_in_memory().expect("open in-memory database");
+    init_schema(&conn, &ChainConfig::default()).expect("create schema");
+    conn
+}
+
+// Pool over a single in-memory database with the full schema, for calling handlers. It holds
+// one connection, so every checkout sees the same database; drop a checked out connection
+// before calling a handler.
+pub fn test_pool() -> DbPool {
+    let manager = SqliteConnectionManager::memory()
+        .with_init(|conn| init_schema(conn, &ChainConfig::default()));
+    r2d2::Pool::builder().max_size(1).build(manager).expect("build test pool")
+}
+
+// P2WPKH script paying a fixed key hash derived from `seed`
+pub fn p2wpkh_script(seed: u8) -> ScriptBuf {
+    ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([seed; 20]))
+}
+
+// Coinbase paying `value` sats. The height goes in the scriptSig as BIP34 requires, which
+// also keeps coinbase txids distinct.
+pub fn coinbase_tx(height: u32, value: u64) -> Transaction {
+    Transaction {
+        version: transaction::Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: Builder::new().push_int(height as i64).push_int(0).into_script(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: p2wpkh_script(1),
+        }],
+    }
+}
+
+// Transaction spending output `vout` of `prev` into a single output of `value` sats
+pub fn spend_tx(prev: &Transaction, vout: u32, value: u64) -> Transaction {
+    Transaction {
+        version: transaction::Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(prev.compute_txid(), vout),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::from_slice(&[vec![0x30; 71], vec![0x02; 33]]),
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: p2wpkh_script(2),
+        }],
+    }
+}
+
+// Block on top of `prev_blockhash` with a correct Merkle root. There's no proof of work;
+// nothing the database stores checks it.
+pub fn test_block(prev_blockhash: BlockHash, height: u32, txdata: Vec<Transaction>) -> Block {
+    let mut block = Block {
+        header: Header {
+            version: Version::TWO,
+            prev_blockhash,
+            merkle_root: TxMerkleNode::all_zeros(),
+            time: 1_700_000_000 + height * 600,
+            bits: CompactTarget::from_consensus(0x207fffff),
+            nonce: 0,
+        },
+        txdata,
+    };
+    block.header.merkle_root = block.compute_merkle_root().expect("block has transactions");
+    block
+}
+
+// Store `len` blocks from height 0 up, each holding only a coinbase, and return them
+pub fn store_chain(conn: &Connection, len: u32) -> Vec<Block> {
+    let mut chain: Vec<Block> = Vec::new();
+    for height in 0..len {
+        let prev = chain.last().map_or(BlockHash::all_zeros(), |block| block.block_hash());
+        let block = test_block(prev, height, vec![coinbase_tx(height, 5_000)]);
+        insert_block(conn, &block, height, &ChainConfig::default()).expect("store block");
+        chain.push(block);
+    }
+    chain
+}
@@ -0,0 +1,30 @@
+use anyhow::{bail, Context};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::ServerConfig;
+use std::path::Path;
+
+// Server config for `serve --tls-cert --tls-key`. The certificate file may hold a chain
+// (leaf first); the key file holds one PKCS#8, PKCS#1 or SEC1 private key.
+// Fails at startup rather than on the first handshake if either file is unusable.
+pub fn load_server_config(cert_path: &Path, key_path: &Path) -> anyhow::Result<ServerConfig> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("Failed to read TLS certificate from {}", cert_path.display()))?;
+    if certs.is_empty() {
+        bail!("No PEM certificates found in {}", cert_path.display());
+    }
+
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .with_context(|| format!("Failed to read TLS private key from {}", key_path.display()))?;
+
+    ServerConfig::builder_with_provider(rustls::crypto::ring::default_provider().into())
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .with_context(|| format!(
+            "TLS certificate {} and private key {} can't be used together",
+            cert_path.display(),
+            key_path.display()
+        ))
+}